@@ -0,0 +1,221 @@
+// Pluggable agent behaviours for the MAPLE ecosystem
+// © 2025 Finalverse Inc. All rights reserved.
+
+use crate::memory::AgentMemory;
+use crate::AgentConfig;
+use async_trait::async_trait;
+use maple_ual::{Mode, UalMessage};
+use std::collections::HashMap;
+use std::error::Error;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Error type returned by behaviour hooks
+pub type BehaviourError = Box<dyn Error + Send + Sync>;
+
+/// Mutable view of an agent handed to its behaviour
+#[derive(Debug, Clone)]
+pub struct AgentContext {
+    pub did: String,
+    pub config: AgentConfig,
+    pub state: Vec<u8>, // Persisted into the .map State section
+    pub memory: AgentMemory,
+}
+
+/// Logic run by an agent, selected by its `AgentConfig::role`
+#[async_trait]
+pub trait AgentBehaviour: Send {
+    /// Called once before the agent accepts messages
+    async fn on_start(&mut self, _ctx: &mut AgentContext) -> Result<(), BehaviourError> {
+        Ok(())
+    }
+
+    /// Handles an incoming message, optionally producing a response
+    async fn handle_message(
+        &mut self,
+        ctx: &mut AgentContext,
+        msg: UalMessage,
+    ) -> Result<Option<UalMessage>, BehaviourError>;
+
+    /// Called periodically if `tick_interval` returns a duration
+    async fn on_tick(&mut self, _ctx: &mut AgentContext) -> Result<(), BehaviourError> {
+        Ok(())
+    }
+
+    /// Called once after the agent stops accepting messages
+    async fn on_stop(&mut self, _ctx: &mut AgentContext) -> Result<(), BehaviourError> {
+        Ok(())
+    }
+
+    /// How often `on_tick` runs; `None` disables ticks
+    fn tick_interval(&self) -> Option<Duration> {
+        None
+    }
+}
+
+/// Constructs a behaviour for an agent config
+pub type BehaviourFactory = Arc<dyn Fn(&AgentConfig) -> Box<dyn AgentBehaviour> + Send + Sync>;
+
+/// Maps agent roles to behaviour factories
+#[derive(Clone)]
+pub struct BehaviourRegistry {
+    factories: HashMap<String, BehaviourFactory>,
+    fallback: BehaviourFactory,
+}
+
+impl BehaviourRegistry {
+    /// Creates a registry where every role falls back to `DefaultBehaviour`
+    pub fn new() -> Self {
+        BehaviourRegistry {
+            factories: HashMap::new(),
+            fallback: Arc::new(|_| Box::new(DefaultBehaviour)),
+        }
+    }
+
+    /// Registers the behaviour used for agents with the given role
+    pub fn register<F>(&mut self, role: &str, factory: F)
+    where
+        F: Fn(&AgentConfig) -> Box<dyn AgentBehaviour> + Send + Sync + 'static,
+    {
+        self.factories.insert(role.to_string(), Arc::new(factory));
+    }
+
+    /// Replaces the behaviour used for roles without a registration
+    pub fn set_fallback<F>(&mut self, factory: F)
+    where
+        F: Fn(&AgentConfig) -> Box<dyn AgentBehaviour> + Send + Sync + 'static,
+    {
+        self.fallback = Arc::new(factory);
+    }
+
+    /// Whether a behaviour is registered for the role
+    pub fn contains(&self, role: &str) -> bool {
+        self.factories.contains_key(role)
+    }
+
+    /// Instantiates the behaviour for an agent config
+    pub fn create(&self, config: &AgentConfig) -> Box<dyn AgentBehaviour> {
+        let factory = self.factories.get(&config.role).unwrap_or(&self.fallback);
+        factory(config)
+    }
+}
+
+impl Default for BehaviourRegistry {
+    fn default() -> Self {
+        BehaviourRegistry::new()
+    }
+}
+
+impl std::fmt::Debug for BehaviourRegistry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BehaviourRegistry")
+            .field("roles", &self.factories.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+/// Behaviour for roles without a registration: acknowledges each message
+#[derive(Debug, Default)]
+pub struct DefaultBehaviour;
+
+#[async_trait]
+impl AgentBehaviour for DefaultBehaviour {
+    async fn handle_message(
+        &mut self,
+        ctx: &mut AgentContext,
+        msg: UalMessage,
+    ) -> Result<Option<UalMessage>, BehaviourError> {
+        let action = format!("{}.result", msg.action());
+        let summary = match msg.mode() {
+            Mode::Json | Mode::Cbor | Mode::MessagePack => {
+                let payload: serde_json::Value = msg.decode().map_err(|e| e.to_string())?;
+                format!("Agent {} handled {} with payload: {}", ctx.config.name, msg.action(), payload)
+            }
+            Mode::ByteLevel if msg.payload().starts_with(crate::dna::MAGIC) => {
+                // Recognize .map data broadcasted via UAL
+                format!("Agent {} recognized .map data", ctx.config.name)
+            }
+            Mode::ByteLevel => format!(
+                "Agent {} handled byte-level {}: {}",
+                ctx.config.name,
+                msg.action(),
+                String::from_utf8_lossy(msg.payload())
+            ),
+            Mode::Grpc => format!(
+                "Agent {} handled Protobuf {} ({} bytes)",
+                ctx.config.name,
+                msg.action(),
+                msg.payload().len()
+            ),
+            Mode::CapnProto => format!(
+                "Agent {} handled Cap'n Proto {} ({} segments)",
+                ctx.config.name,
+                msg.action(),
+                msg.capnp_segments().map_err(|e| e.to_string())?.len()
+            ),
+        };
+        let reply = UalMessage::new(&action, Mode::Json)
+            .with_json_payload(&serde_json::json!({ "agent": ctx.did, "summary": summary }))
+            .map_err(|e| e.to_string())?;
+        Ok(Some(reply))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Counter(u32);
+
+    #[async_trait]
+    impl AgentBehaviour for Counter {
+        async fn handle_message(
+            &mut self,
+            _ctx: &mut AgentContext,
+            _msg: UalMessage,
+        ) -> Result<Option<UalMessage>, BehaviourError> {
+            self.0 += 1;
+            Ok(None)
+        }
+    }
+
+    fn context(role: &str) -> AgentContext {
+        AgentContext {
+            did: "did:maple:agent:1234".to_string(),
+            config: AgentConfig::new("test-agent", role),
+            state: Vec::new(),
+            memory: AgentMemory::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_registry_selects_behaviour_by_role() {
+        let mut registry = BehaviourRegistry::new();
+        registry.register("counter", |_| Box::new(Counter(0)));
+        assert!(registry.contains("counter"));
+
+        let msg = UalMessage::new("move", Mode::Json)
+            .with_json_payload(&serde_json::json!({"x": 1}))
+            .unwrap();
+
+        let mut ctx = context("counter");
+        let mut counter = registry.create(&ctx.config);
+        assert!(counter.handle_message(&mut ctx, msg.clone()).await.unwrap().is_none());
+
+        let mut ctx = context("unknown");
+        let mut fallback = registry.create(&ctx.config);
+        let reply = fallback.handle_message(&mut ctx, msg).await.unwrap().unwrap();
+        assert_eq!(reply.action(), "move.result");
+    }
+
+    #[tokio::test]
+    async fn test_default_behaviour_acknowledges_protobuf_and_rejects_bad_json() {
+        let mut ctx = context("default");
+        let msg = UalMessage::new("move", Mode::Grpc);
+        let reply = DefaultBehaviour.handle_message(&mut ctx, msg).await.unwrap().unwrap();
+        assert_eq!(reply.action(), "move.result");
+
+        let malformed = UalMessage::new("move", Mode::Json);
+        assert!(DefaultBehaviour.handle_message(&mut ctx, malformed).await.is_err());
+    }
+}
@@ -0,0 +1,81 @@
+// Capability declarations: the UAL actions an agent accepts
+// © 2025 Finalverse Inc. All rights reserved.
+
+use crate::dna::{DnaError, DnaFile, SectionKind};
+use serde::{Deserialize, Serialize};
+
+/// A UAL action an agent accepts, with hints used when routing by capability
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Capability {
+    pub action: String, // e.g., "route.plan"
+    #[serde(default = "default_version")]
+    pub version: u32, // Bumped on incompatible payload changes
+    #[serde(default)]
+    pub schema: Option<serde_json::Value>, // JSON Schema of the request payload
+    #[serde(default)]
+    pub cost: Option<u32>, // Relative cost per request; lower is preferred
+}
+
+fn default_version() -> u32 {
+    1
+}
+
+impl Capability {
+    /// Declares version 1 of an action with no schema or cost hint
+    pub fn new(action: &str) -> Self {
+        Capability {
+            action: action.to_string(),
+            version: default_version(),
+            schema: None,
+            cost: None,
+        }
+    }
+
+    /// Declares a later version of the action
+    pub fn with_version(mut self, version: u32) -> Self {
+        self.version = version;
+        self
+    }
+
+    /// Attaches the JSON Schema of the request payload; the runtime enforces it through the
+    /// UAL `SchemaRegistry` when the agent starts, this type only carries it
+    pub fn with_schema(mut self, schema: serde_json::Value) -> Self {
+        self.schema = Some(schema);
+        self
+    }
+
+    /// Sets the relative cost hint used to rank providers of the same action
+    pub fn with_cost(mut self, cost: u32) -> Self {
+        self.cost = Some(cost);
+        self
+    }
+
+    /// Whether this capability serves the action at or above `min_version`
+    pub fn serves(&self, action: &str, min_version: u32) -> bool {
+        self.action == action && self.version >= min_version
+    }
+}
+
+/// Reads the Capabilities section of agent DNA, if present
+pub fn capabilities_from_dna(dna: &DnaFile) -> Result<Option<Vec<Capability>>, DnaError> {
+    dna.section(SectionKind::Capabilities)
+        .map(|bytes| serde_json::from_slice(bytes).map_err(Into::into))
+        .transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_capability_defaults_and_matching() {
+        let parsed: Capability = serde_json::from_str(r#"{"action": "route.plan"}"#).unwrap();
+        assert_eq!(parsed, Capability::new("route.plan"));
+
+        let capability = Capability::new("route.plan").with_version(2).with_cost(5);
+        assert!(capability.serves("route.plan", 1));
+        assert!(capability.serves("route.plan", 2));
+        assert!(!capability.serves("route.plan", 3));
+        assert!(!capability.serves("route.eta", 1));
+    }
+}
@@ -0,0 +1,116 @@
+// Agent state checkpoints persisted in MapleDB
+// © 2025 Finalverse Inc. All rights reserved.
+
+use mapledb::MapleDb;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+
+const KEY_PREFIX: &str = "checkpoint:";
+
+/// Errors raised while saving or loading checkpoints
+#[derive(Debug, Error)]
+pub enum CheckpointError {
+    #[error("checkpoint storage error: {0}")]
+    Storage(String),
+    #[error("checkpoint encoding error: {0}")]
+    Encoding(#[from] bincode::Error),
+}
+
+/// Snapshot of an agent's state and mailbox progress
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub did: String,
+    pub state: Vec<u8>,
+    pub high_water_mark: u64, // Number of mailbox messages consumed so far
+    pub updated_at: u64, // Unix time in milliseconds
+}
+
+impl Checkpoint {
+    /// Creates a fresh checkpoint for an agent that has processed nothing yet
+    pub fn new(did: &str, state: Vec<u8>) -> Self {
+        Checkpoint {
+            did: did.to_string(),
+            state,
+            high_water_mark: 0,
+            updated_at: unix_millis(),
+        }
+    }
+
+    /// Refreshes the timestamp after the snapshot changed
+    pub(crate) fn touch(&mut self) {
+        self.updated_at = unix_millis();
+    }
+}
+
+/// Latest checkpoint per DID, stored in MapleDB
+#[derive(Clone)]
+pub struct CheckpointStore {
+    db: Arc<MapleDb>,
+}
+
+impl CheckpointStore {
+    /// Creates a checkpoint store on top of a shared MapleDB
+    pub fn new(db: Arc<MapleDb>) -> Self {
+        CheckpointStore { db }
+    }
+
+    /// Saves a checkpoint, replacing the previous one for the same DID
+    pub fn save(&self, checkpoint: &Checkpoint) -> Result<(), CheckpointError> {
+        let bytes = bincode::serialize(checkpoint)?;
+        self.db
+            .store(&key(&checkpoint.did), &bytes)
+            .map_err(|e| CheckpointError::Storage(e.to_string()))
+    }
+
+    /// Loads the latest checkpoint for a DID
+    pub fn load(&self, did: &str) -> Result<Option<Checkpoint>, CheckpointError> {
+        let bytes = self
+            .db
+            .get(&key(did))
+            .map_err(|e| CheckpointError::Storage(e.to_string()))?;
+        bytes
+            .map(|b| bincode::deserialize(&b).map_err(Into::into))
+            .transpose()
+    }
+
+    /// Removes the checkpoint for a DID
+    pub fn delete(&self, did: &str) -> Result<(), CheckpointError> {
+        self.db
+            .delete(&key(did))
+            .map_err(|e| CheckpointError::Storage(e.to_string()))
+    }
+}
+
+fn key(did: &str) -> String {
+    format!("{}{}", KEY_PREFIX, did)
+}
+
+fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checkpoint_roundtrip() {
+        let db = Arc::new(MapleDb::new("test_checkpoint_db").unwrap());
+        let store = CheckpointStore::new(db);
+        let mut checkpoint = Checkpoint::new("did:maple:agent:1234", vec![1, 2, 3]);
+        checkpoint.high_water_mark = 42;
+
+        store.save(&checkpoint).unwrap();
+        assert_eq!(store.load("did:maple:agent:1234").unwrap(), Some(checkpoint));
+        store.delete("did:maple:agent:1234").unwrap();
+        assert!(store.load("did:maple:agent:1234").unwrap().is_none());
+
+        drop(store);
+        std::fs::remove_dir_all("test_checkpoint_db").unwrap();
+    }
+}
@@ -0,0 +1,419 @@
+// Versioned `.map` DNA container format for MAPLE agents
+// © 2025 Finalverse Inc. All rights reserved.
+//
+// Version 2 layout (all integers big-endian):
+//
+//   magic "MAPLEDNA"        8 bytes
+//   version                 u16
+//   min reader version      u16  (oldest reader able to parse this file)
+//   DID length + DID        u16 + bytes
+//   section count           u16
+//   section table           (kind u16, length u32) per section
+//   section bodies          concatenated in table order
+//   BLAKE3 checksum         32 bytes over everything above
+//
+// Version 1 files (fixed 36-byte DID, config and state only) are still readable.
+
+use libp2p_identity::PeerId;
+use thiserror::Error;
+
+/// Magic bytes at the start of every `.map` file
+pub const MAGIC: &[u8; 8] = b"MAPLEDNA";
+/// Version written by this implementation
+pub const CURRENT_VERSION: u16 = 2;
+/// Oldest version this implementation can read
+pub const MIN_SUPPORTED_VERSION: u16 = 1;
+/// Upper bound for a single section body (64 MiB)
+pub const MAX_SECTION_LEN: usize = 64 * 1024 * 1024;
+
+const LEGACY_VERSION: u16 = 1;
+const LEGACY_DID_LEN: usize = 36;
+const CHECKSUM_LEN: usize = 32;
+
+/// Errors raised while reading or writing `.map` DNA
+#[derive(Debug, Error)]
+pub enum DnaError {
+    #[error("invalid .map file header")]
+    BadMagic,
+    #[error("truncated .map file: needed {needed} bytes at offset {offset}, {available} available")]
+    Truncated {
+        offset: usize,
+        needed: usize,
+        available: usize,
+    },
+    #[error("unsupported .map version {found} (supported {min}..={max})")]
+    UnsupportedVersion { found: u16, min: u16, max: u16 },
+    #[error("DID is not valid UTF-8")]
+    InvalidDid,
+    #[error("DID of {0} bytes exceeds the maximum length")]
+    DidTooLong(usize),
+    #[error("too many sections: {0}")]
+    TooManySections(usize),
+    #[error("section {kind:?} of {len} bytes exceeds the maximum length")]
+    SectionTooLarge { kind: SectionKind, len: usize },
+    #[error("duplicate {0:?} section")]
+    DuplicateSection(SectionKind),
+    #[error("missing {0:?} section")]
+    MissingSection(SectionKind),
+    #[error("checksum mismatch: .map file is corrupt")]
+    ChecksumMismatch,
+    #[error("{0} unexpected trailing bytes")]
+    TrailingBytes(usize),
+    #[error("malformed signatures section")]
+    MalformedSignatures,
+    #[error("invalid publisher key or signature: {0}")]
+    InvalidKey(String),
+    #[error("signature by {0} does not match DNA contents")]
+    InvalidSignature(PeerId),
+    #[error("DNA is signed by untrusted publisher {0}")]
+    UntrustedPublisher(PeerId),
+    #[error("DNA is unsigned")]
+    Unsigned,
+    #[error("invalid section contents: {0}")]
+    InvalidSection(#[from] serde_json::Error),
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// Kinds of sections stored in a DNA container
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SectionKind {
+    Config,
+    State,
+    Memory,
+    Capabilities,
+    Signatures,
+    Unknown(u16), // Written by a newer version; preserved as-is
+}
+
+impl SectionKind {
+    /// Numeric tag used in the section table
+    pub fn tag(self) -> u16 {
+        match self {
+            SectionKind::Config => 1,
+            SectionKind::State => 2,
+            SectionKind::Memory => 3,
+            SectionKind::Capabilities => 4,
+            SectionKind::Signatures => 5,
+            SectionKind::Unknown(tag) => tag,
+        }
+    }
+
+    /// Maps a section table tag back to its kind
+    pub fn from_tag(tag: u16) -> Self {
+        match tag {
+            1 => SectionKind::Config,
+            2 => SectionKind::State,
+            3 => SectionKind::Memory,
+            4 => SectionKind::Capabilities,
+            5 => SectionKind::Signatures,
+            other => SectionKind::Unknown(other),
+        }
+    }
+}
+
+/// A single typed section of a DNA container
+#[derive(Debug, Clone, PartialEq)]
+pub struct Section {
+    pub kind: SectionKind,
+    pub data: Vec<u8>,
+}
+
+/// In-memory representation of a `.map` DNA file
+#[derive(Debug, Clone, PartialEq)]
+pub struct DnaFile {
+    version: u16,
+    did: String,
+    sections: Vec<Section>,
+}
+
+impl DnaFile {
+    /// Creates an empty container for the given DID
+    pub fn new(did: &str) -> Self {
+        DnaFile {
+            version: CURRENT_VERSION,
+            did: did.to_string(),
+            sections: Vec::new(),
+        }
+    }
+
+    /// Version the container was read with (or will be written as)
+    pub fn version(&self) -> u16 {
+        self.version
+    }
+
+    /// DID of the agent described by this DNA
+    pub fn did(&self) -> &str {
+        &self.did
+    }
+
+    /// All sections in table order
+    pub fn sections(&self) -> &[Section] {
+        &self.sections
+    }
+
+    /// Returns the body of a section, if present
+    pub fn section(&self, kind: SectionKind) -> Option<&[u8]> {
+        self.sections
+            .iter()
+            .find(|s| s.kind == kind)
+            .map(|s| s.data.as_slice())
+    }
+
+    /// Returns the body of a required section
+    pub fn require_section(&self, kind: SectionKind) -> Result<&[u8], DnaError> {
+        self.section(kind).ok_or(DnaError::MissingSection(kind))
+    }
+
+    /// Inserts or replaces a section
+    pub fn set_section(&mut self, kind: SectionKind, data: Vec<u8>) {
+        match self.sections.iter_mut().find(|s| s.kind == kind) {
+            Some(section) => section.data = data,
+            None => self.sections.push(Section { kind, data }),
+        }
+    }
+
+    /// Removes a section, returning its body
+    pub fn remove_section(&mut self, kind: SectionKind) -> Option<Vec<u8>> {
+        let index = self.sections.iter().position(|s| s.kind == kind)?;
+        Some(self.sections.remove(index).data)
+    }
+
+    /// Serializes the container in the current format
+    pub fn to_bytes(&self) -> Result<Vec<u8>, DnaError> {
+        let did = self.did.as_bytes();
+        if did.len() > u16::MAX as usize {
+            return Err(DnaError::DidTooLong(did.len()));
+        }
+        if self.sections.len() > u16::MAX as usize {
+            return Err(DnaError::TooManySections(self.sections.len()));
+        }
+
+        let mut buf = Vec::new();
+        buf.extend_from_slice(MAGIC);
+        buf.extend_from_slice(&CURRENT_VERSION.to_be_bytes());
+        buf.extend_from_slice(&CURRENT_VERSION.to_be_bytes()); // Min reader version
+        buf.extend_from_slice(&(did.len() as u16).to_be_bytes());
+        buf.extend_from_slice(did);
+
+        buf.extend_from_slice(&(self.sections.len() as u16).to_be_bytes());
+        for section in &self.sections {
+            if section.data.len() > MAX_SECTION_LEN {
+                return Err(DnaError::SectionTooLarge {
+                    kind: section.kind,
+                    len: section.data.len(),
+                });
+            }
+            buf.extend_from_slice(&section.kind.tag().to_be_bytes());
+            buf.extend_from_slice(&(section.data.len() as u32).to_be_bytes());
+        }
+        for section in &self.sections {
+            buf.extend_from_slice(&section.data);
+        }
+
+        let checksum = blake3::hash(&buf);
+        buf.extend_from_slice(checksum.as_bytes());
+        Ok(buf)
+    }
+
+    /// Parses a container, accepting any supported version
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, DnaError> {
+        let mut reader = Reader::new(bytes);
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err(DnaError::BadMagic);
+        }
+        let version = reader.u16()?;
+        match version {
+            LEGACY_VERSION => Self::parse_v1(reader),
+            v if v < MIN_SUPPORTED_VERSION => Err(unsupported(v)),
+            v => Self::parse_v2(v, reader),
+        }
+    }
+
+    /// Parses the fixed version 1 layout following the version field
+    fn parse_v1(mut reader: Reader<'_>) -> Result<Self, DnaError> {
+        let did_bytes = reader.take(LEGACY_DID_LEN)?;
+        let did_end = did_bytes.iter().position(|&b| b == 0).unwrap_or(LEGACY_DID_LEN);
+        let did = std::str::from_utf8(&did_bytes[..did_end])
+            .map_err(|_| DnaError::InvalidDid)?
+            .to_string();
+
+        let config_len = reader.u32()? as usize;
+        let config = reader.take(config_len)?.to_vec();
+        let state_len = reader.u32()? as usize;
+        let state = reader.take(state_len)?.to_vec();
+        reader.finish()?;
+
+        Ok(DnaFile {
+            version: LEGACY_VERSION,
+            did,
+            sections: vec![
+                Section { kind: SectionKind::Config, data: config },
+                Section { kind: SectionKind::State, data: state },
+            ],
+        })
+    }
+
+    /// Parses the sectioned layout, verifying the trailing checksum first
+    fn parse_v2(version: u16, mut reader: Reader<'_>) -> Result<Self, DnaError> {
+        let min_reader = reader.u16()?;
+        if !(MIN_SUPPORTED_VERSION..=CURRENT_VERSION).contains(&min_reader) {
+            return Err(unsupported(version));
+        }
+
+        let bytes = reader.bytes;
+        if bytes.len() < reader.offset + CHECKSUM_LEN {
+            return Err(DnaError::Truncated {
+                offset: reader.offset,
+                needed: CHECKSUM_LEN,
+                available: bytes.len() - reader.offset,
+            });
+        }
+        let (body, checksum) = bytes.split_at(bytes.len() - CHECKSUM_LEN);
+        if blake3::hash(body).as_bytes() != checksum {
+            return Err(DnaError::ChecksumMismatch);
+        }
+        reader.bytes = body;
+
+        let did_len = reader.u16()? as usize;
+        let did = std::str::from_utf8(reader.take(did_len)?)
+            .map_err(|_| DnaError::InvalidDid)?
+            .to_string();
+
+        let count = reader.u16()? as usize;
+        let mut table = Vec::with_capacity(count);
+        for _ in 0..count {
+            let kind = SectionKind::from_tag(reader.u16()?);
+            let len = reader.u32()? as usize;
+            if len > MAX_SECTION_LEN {
+                return Err(DnaError::SectionTooLarge { kind, len });
+            }
+            if table.iter().any(|(k, _)| *k == kind) {
+                return Err(DnaError::DuplicateSection(kind));
+            }
+            table.push((kind, len));
+        }
+
+        let mut sections = Vec::with_capacity(count);
+        for (kind, len) in table {
+            sections.push(Section {
+                kind,
+                data: reader.take(len)?.to_vec(),
+            });
+        }
+        reader.finish()?;
+
+        Ok(DnaFile { version, did, sections })
+    }
+}
+
+fn unsupported(found: u16) -> DnaError {
+    DnaError::UnsupportedVersion {
+        found,
+        min: MIN_SUPPORTED_VERSION,
+        max: CURRENT_VERSION,
+    }
+}
+
+/// Bounds-checked cursor over a byte slice
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Reader { bytes, offset: 0 }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], DnaError> {
+        let available = self.bytes.len().saturating_sub(self.offset);
+        if len > available {
+            return Err(DnaError::Truncated {
+                offset: self.offset,
+                needed: len,
+                available,
+            });
+        }
+        let slice = &self.bytes[self.offset..self.offset + len];
+        self.offset += len;
+        Ok(slice)
+    }
+
+    fn u16(&mut self) -> Result<u16, DnaError> {
+        let b = self.take(2)?;
+        Ok(u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, DnaError> {
+        let b = self.take(4)?;
+        Ok(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn finish(self) -> Result<(), DnaError> {
+        match self.bytes.len() - self.offset {
+            0 => Ok(()),
+            extra => Err(DnaError::TrailingBytes(extra)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DID: &str = "did:maple:agent:8f14e45f-ceea-467a-9575-2f5d5e7e1d8b";
+
+    fn sample() -> DnaFile {
+        let mut dna = DnaFile::new(DID);
+        dna.set_section(SectionKind::Config, br#"{"name":"a","role":"r"}"#.to_vec());
+        dna.set_section(SectionKind::State, vec![1, 2, 3]);
+        dna
+    }
+
+    #[test]
+    fn test_roundtrip_keeps_full_did() {
+        let dna = sample();
+        let parsed = DnaFile::from_bytes(&dna.to_bytes().unwrap()).unwrap();
+        assert_eq!(parsed, dna);
+        assert_eq!(parsed.did().len(), 52);
+    }
+
+    #[test]
+    fn test_corrupt_and_truncated_files_are_rejected() {
+        let bytes = sample().to_bytes().unwrap();
+        for len in 0..bytes.len() {
+            assert!(DnaFile::from_bytes(&bytes[..len]).is_err());
+        }
+
+        let mut corrupt = bytes.clone();
+        corrupt[20] ^= 0xff;
+        assert!(matches!(DnaFile::from_bytes(&corrupt), Err(DnaError::ChecksumMismatch)));
+
+        let mut future = bytes;
+        future[8..12].copy_from_slice(&[0, 9, 0, 9]);
+        assert!(matches!(
+            DnaFile::from_bytes(&future),
+            Err(DnaError::UnsupportedVersion { found: 9, .. })
+        ));
+    }
+
+    #[test]
+    fn test_reads_version_1_files() {
+        let mut legacy = Vec::new();
+        legacy.extend_from_slice(MAGIC);
+        legacy.extend_from_slice(&1u16.to_be_bytes());
+        let mut did = b"did:maple:agent:1234".to_vec();
+        did.resize(LEGACY_DID_LEN, 0);
+        legacy.extend_from_slice(&did);
+        legacy.extend_from_slice(&2u32.to_be_bytes());
+        legacy.extend_from_slice(b"{}");
+        legacy.extend_from_slice(&1u32.to_be_bytes());
+        legacy.push(7);
+
+        let dna = DnaFile::from_bytes(&legacy).unwrap();
+        assert_eq!(dna.version(), 1);
+        assert_eq!(dna.did(), "did:maple:agent:1234");
+        assert_eq!(dna.section(SectionKind::State), Some(&[7u8][..]));
+    }
+}
@@ -1,161 +1,505 @@
 // Agent implementations for the MAPLE ecosystem
 // © 2025 Finalverse Inc. All rights reserved.
 
-use maple_ual::{UalMessage, Mode};
+use maple_ual::UalMessage;
+use futures::FutureExt;
 use serde::{Deserialize, Serialize};
 use std::error::Error;
-use tokio::sync::mpsc;
+use std::panic::AssertUnwindSafe;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{broadcast, mpsc, oneshot, watch};
 use tokio::fs::File;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::io::AsyncWriteExt;
 use uuid::Uuid;
 
+pub mod behaviour;
+pub mod capability;
+pub mod checkpoint;
+pub mod dna;
+pub mod lifecycle;
+pub mod mailbox;
+pub mod memory;
+pub mod request;
+pub mod trust;
+
+pub use behaviour::{AgentBehaviour, AgentContext, BehaviourError, BehaviourRegistry, DefaultBehaviour};
+pub use capability::Capability;
+pub use checkpoint::{Checkpoint, CheckpointError, CheckpointStore};
+pub use dna::{DnaError, DnaFile, SectionKind};
+pub use lifecycle::{AgentControl, AgentState, AgentTransition};
+pub use mailbox::{Lane, MailboxConfig, MailboxConfigError, MailboxError, MailboxStats, OverflowPolicy};
+pub use memory::{AgentMemory, Episode, EpisodicLog, MemoryError, SemanticMemory, ShortTermMemory};
+pub use request::{RequestError, DEFAULT_REQUEST_TIMEOUT};
+pub use trust::{DnaSignature, TrustStore};
+pub use libp2p_identity::{Keypair, PeerId};
+
+use mailbox::{MailboxReceiver, MailboxSender};
+use request::Envelope;
+
 /// Configuration for an agent
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct AgentConfig {
     pub name: String,
     pub role: String, // e.g., "logistics", "research"
+    #[serde(default)]
+    pub capabilities: Vec<Capability>, // Actions accepted; empty accepts any action
+    #[serde(default)]
+    pub mailbox: MailboxConfig,
+}
+
+impl AgentConfig {
+    /// Creates a config that declares no capabilities
+    pub fn new(name: &str, role: &str) -> Self {
+        AgentConfig {
+            name: name.to_string(),
+            role: role.to_string(),
+            capabilities: Vec::new(),
+            mailbox: MailboxConfig::default(),
+        }
+    }
+
+    /// Sets the mailbox capacity and overflow policy
+    pub fn with_mailbox(mut self, capacity: usize, overflow: OverflowPolicy) -> Result<Self, MailboxConfigError> {
+        self.mailbox = MailboxConfig::new(capacity, overflow)?;
+        Ok(self)
+    }
+
+    /// Declares an action the agent accepts
+    pub fn with_capability(mut self, capability: Capability) -> Self {
+        self.capabilities.push(capability);
+        self
+    }
+
+    /// Returns the declared capability for an action
+    pub fn capability(&self, action: &str) -> Option<&Capability> {
+        self.capabilities.iter().find(|c| c.action == action)
+    }
+
+    /// Whether the agent accepts an action
+    pub fn accepts(&self, action: &str) -> bool {
+        self.capabilities.is_empty() || self.capability(action).is_some()
+    }
+
+    /// Reads the Config and Capabilities sections of agent DNA
+    pub fn from_dna(dna: &DnaFile) -> Result<Self, DnaError> {
+        let mut config: AgentConfig = serde_json::from_slice(dna.require_section(SectionKind::Config)?)?;
+        if let Some(capabilities) = capability::capabilities_from_dna(dna)? {
+            config.capabilities = capabilities;
+        }
+        Ok(config)
+    }
 }
 
-/// Represents a MAPLE agent with DNA data
-#[derive(Debug)]
+/// A queued control signal, with where to report whether the run loop applied it
+type ControlRequest = (AgentControl, Option<oneshot::Sender<Result<AgentState, AgentState>>>);
+
+/// Receiving ends of an agent's mailbox and control queue
+struct Lanes {
+    messages: MailboxReceiver,
+    control: mpsc::UnboundedReceiver<ControlRequest>,
+}
+
+/// Publishes the run loop's lifecycle state and reports every transition, in order
+struct StateReporter {
+    status_tx: watch::Sender<AgentState>,
+    transitions_tx: mpsc::UnboundedSender<AgentTransition>,
+}
+
+impl StateReporter {
+    fn current(&self) -> AgentState {
+        *self.status_tx.borrow()
+    }
+
+    fn enter(&self, to: AgentState) {
+        let from = self.status_tx.send_replace(to);
+        if from != to {
+            // Nobody taking the transitions is not an error; the watch still shows the state
+            let _ = self.transitions_tx.send(AgentTransition { from, to });
+        }
+    }
+}
+
+/// Handle to a running MAPLE agent with DNA data
+#[derive(Debug, Clone)]
 pub struct Agent {
     did: String, // Decentralized Identifier
     config: AgentConfig,
-    state: Vec<u8>, // Placeholder for agent state (e.g., memory, weights)
-    message_rx: mpsc::Receiver<UalMessage>,
-    message_tx: mpsc::Sender<UalMessage>,
+    snapshot_rx: watch::Receiver<Checkpoint>, // Latest state published by the run loop
+    status_rx: watch::Receiver<AgentState>,
+    transitions_rx: Arc<Mutex<Option<mpsc::UnboundedReceiver<AgentTransition>>>>, // Taken by the first watcher
+    control_tx: mpsc::UnboundedSender<ControlRequest>,
+    message_tx: MailboxSender,
+    response_tx: broadcast::Sender<UalMessage>,
 }
 
 impl Agent {
-    /// Creates a new agent instance with a unique DID
+    /// Creates a new agent instance with a unique DID and the default behaviour
     pub fn new(config: AgentConfig) -> Self {
+        let behaviour = BehaviourRegistry::new().create(&config);
+        Self::with_behaviour(config, behaviour)
+    }
+
+    /// Creates a new agent instance with a unique DID running the given behaviour
+    pub fn with_behaviour(config: AgentConfig, behaviour: Box<dyn AgentBehaviour>) -> Self {
         let did = format!("did:maple:agent:{}", Uuid::new_v4());
-        let (tx, rx) = mpsc::channel(100);
-        let agent = Agent {
-            did,
-            config,
-            state: Vec::new(), // Initial empty state
-            message_rx: rx,
-            message_tx: tx,
-        };
-        tokio::spawn(agent.clone().run());
-        agent
+        Self::from_parts(did, config, Vec::new(), behaviour)
     }
 
     /// Spawns an agent from a .map file
     pub async fn from_map_file(path: &str) -> Result<Self, Box<dyn Error>> {
-        let mut file = File::open(path).await?;
-        let mut buffer = Vec::new();
-        file.read_to_end(&mut buffer).await?;
+        let buffer = tokio::fs::read(path).await?;
+        let dna = DnaFile::from_bytes(&buffer)?;
+        Ok(Self::from_dna(&dna)?)
+    }
 
-        // Validate header
-        if &buffer[0..8] != b"MAPLEDNA" {
-            return Err("Invalid .map file header".into());
-        }
+    /// Spawns an agent from a .map file after checking its publisher signature
+    pub async fn from_verified_map_file(path: &str, trust: &TrustStore) -> Result<Self, Box<dyn Error>> {
+        let buffer = tokio::fs::read(path).await?;
+        let dna = DnaFile::from_bytes(&buffer)?;
+        trust.verify(&dna)?;
+        Ok(Self::from_dna(&dna)?)
+    }
+
+    /// Builds an agent from parsed DNA with the default behaviour registry
+    pub fn from_dna(dna: &DnaFile) -> Result<Self, DnaError> {
+        Self::from_dna_with(dna, &BehaviourRegistry::new())
+    }
+
+    /// Builds an agent from parsed DNA, picking its behaviour by role
+    pub fn from_dna_with(dna: &DnaFile, registry: &BehaviourRegistry) -> Result<Self, DnaError> {
+        let config = AgentConfig::from_dna(dna)?;
+        let state = dna.section(SectionKind::State).unwrap_or_default().to_vec();
+        let behaviour = registry.create(&config);
+        Ok(Self::from_parts(dna.did().to_string(), config, state, behaviour))
+    }
+
+    /// Starts the run loop for an agent with a known DID and state, returning its handle
+    pub fn from_parts(did: String, config: AgentConfig, state: Vec<u8>, behaviour: Box<dyn AgentBehaviour>) -> Self {
+        let checkpoint = Checkpoint::new(&did, state);
+        Self::restore(config, checkpoint, behaviour)
+    }
 
-        // Parse version (skip for now)
-        let version = u16::from_be_bytes([buffer[8], buffer[9]]);
-
-        // Extract DID
-        let did = String::from_utf8(buffer[10..46].to_vec())?;
-
-        // Extract config
-        let config_len = u32::from_be_bytes([buffer[46], buffer[47], buffer[48], buffer[49]]) as usize;
-        let config_data = &buffer[50..50 + config_len];
-        let config: AgentConfig = serde_json::from_slice(config_data)?;
-
-        // Extract state
-        let state_start = 50 + config_len;
-        let state_len = u32::from_be_bytes([
-            buffer[state_start],
-            buffer[state_start + 1],
-            buffer[state_start + 2],
-            buffer[state_start + 3],
-        ]) as usize;
-        let state = buffer[state_start + 4..state_start + 4 + state_len].to_vec();
-
-        let (tx, rx) = mpsc::channel(100);
-        let agent = Agent {
+    /// Starts the run loop from a saved checkpoint, returning its handle
+    pub fn restore(config: AgentConfig, checkpoint: Checkpoint, behaviour: Box<dyn AgentBehaviour>) -> Self {
+        Self::restore_with_memory(config, checkpoint, behaviour, AgentMemory::default())
+    }
+
+    /// Starts the run loop from a saved checkpoint with the given memory stores
+    pub fn restore_with_memory(
+        config: AgentConfig,
+        checkpoint: Checkpoint,
+        behaviour: Box<dyn AgentBehaviour>,
+        memory: AgentMemory,
+    ) -> Self {
+        let (message_tx, message_rx) = mailbox::mailbox(config.mailbox.clone());
+        let (response_tx, _) = broadcast::channel(100);
+        let did = checkpoint.did.clone();
+        let ctx = AgentContext {
+            did: did.clone(),
+            config: config.clone(),
+            state: checkpoint.state.clone(),
+            memory,
+        };
+        let (snapshot_tx, snapshot_rx) = watch::channel(checkpoint);
+        let (status_tx, status_rx) = watch::channel(AgentState::Created);
+        let (transitions_tx, transitions_rx) = mpsc::unbounded_channel();
+        let reporter = Arc::new(StateReporter { status_tx, transitions_tx });
+        reporter.enter(AgentState::Starting);
+        let (control_tx, control_rx) = mpsc::unbounded_channel();
+        let lanes = Lanes {
+            messages: message_rx,
+            control: control_rx,
+        };
+        let run = Self::run(ctx, behaviour, lanes, reporter.clone(), snapshot_tx, response_tx.clone());
+        let name = config.name.clone();
+        tokio::spawn(async move {
+            // A panicking behaviour must surface as Failed so supervisors can restart it
+            if AssertUnwindSafe(run).catch_unwind().await.is_err() {
+                eprintln!("Agent {} panicked", name);
+                reporter.enter(AgentState::Failed);
+            }
+        });
+        Agent {
             did,
             config,
-            state,
-            message_rx: rx,
-            message_tx: tx,
-        };
-        tokio::spawn(agent.clone().run());
-        Ok(agent)
+            snapshot_rx,
+            status_rx,
+            transitions_rx: Arc::new(Mutex::new(Some(transitions_rx))),
+            control_tx,
+            message_tx,
+            response_tx,
+        }
     }
 
     /// Runs the agent's main loop
-    async fn run(self) {
-        let mut rx = self.message_rx;
-        while let Some(msg) = rx.recv().await {
-            match self.process_message(&msg) {
-                Ok(response) => println!("Agent {} processed: {:?}", self.config.name, response),
-                Err(e) => eprintln!("Agent {} error: {}", self.config.name, e),
+    async fn run(
+        mut ctx: AgentContext,
+        mut behaviour: Box<dyn AgentBehaviour>,
+        mut lanes: Lanes,
+        reporter: Arc<StateReporter>,
+        snapshot_tx: watch::Sender<Checkpoint>,
+        response_tx: broadcast::Sender<UalMessage>,
+    ) {
+        if let Err(e) = ctx.memory.rebuild_semantic().await {
+            eprintln!("Agent {} could not rebuild its semantic memory: {}", ctx.config.name, e);
+        }
+        if let Err(e) = behaviour.on_start(&mut ctx).await {
+            eprintln!("Agent {} failed to start: {}", ctx.config.name, e);
+            reporter.enter(AgentState::Failed);
+            return;
+        }
+        let mut ticker = behaviour.tick_interval().map(tokio::time::interval);
+        let mut high_water_mark = snapshot_tx.borrow().high_water_mark;
+        let mut control = AgentControl::Run;
+        let mut control_open = true;
+
+        loop {
+            match control {
+                AgentControl::Stop => break,
+                AgentControl::Drain => lanes.messages.close(),
+                AgentControl::Run | AgentControl::Pause => {}
             }
+            reporter.enter(control.target());
+            let paused = control == AgentControl::Pause;
+
+            let result = tokio::select! {
+                // Control signals go first so Stop is not held up by a backlog of messages
+                biased;
+                request = lanes.control.recv(), if control_open => {
+                    match request {
+                        Some((next, reply)) => {
+                            // Checked against the state the loop is actually in, not a caller's stale view
+                            let from = reporter.current();
+                            let allowed = from == next.target() || from.can_transition_to(next.target());
+                            if allowed {
+                                control = next;
+                            }
+                            if let Some(reply) = reply {
+                                let _ = reply.send(if allowed { Ok(from) } else { Err(from) });
+                            }
+                        }
+                        None => {
+                            // Every handle is gone: nothing can resume or message the agent again
+                            control_open = false;
+                            control = AgentControl::Drain;
+                        }
+                    }
+                    continue;
+                }
+                envelope = lanes.messages.recv(), if !paused => match envelope {
+                    Some(envelope) => {
+                        high_water_mark += 1;
+                        ctx.memory.short_term.push(envelope.msg.clone());
+                        Self::dispatch(&mut ctx, behaviour.as_mut(), envelope, &response_tx).await
+                    }
+                    None => break,
+                },
+                _ = async { ticker.as_mut().unwrap().tick().await }, if ticker.is_some() && !paused => {
+                    behaviour.on_tick(&mut ctx).await
+                }
+            };
+            if let Err(e) = result {
+                eprintln!("Agent {} error: {}", ctx.config.name, e);
+            }
+            Self::publish_snapshot(&snapshot_tx, &ctx, high_water_mark);
+        }
+
+        if let Err(e) = behaviour.on_stop(&mut ctx).await {
+            eprintln!("Agent {} failed to stop cleanly: {}", ctx.config.name, e);
         }
+        Self::publish_snapshot(&snapshot_tx, &ctx, high_water_mark);
+        reporter.enter(AgentState::Stopped);
     }
 
-    /// Processes a UAL message
-    fn process_message(&self, msg: &UalMessage) -> Result<String, Box<dyn Error>> {
-        match msg.mode {
-            Mode::Json => {
-                let payload: serde_json::Value = msg.decode()?;
-                Ok(format!(
-                    "Agent {} handled {} with payload: {}",
-                    self.config.name, msg.action, payload
-                ))
+    /// Publishes state and mailbox progress if either changed
+    fn publish_snapshot(snapshot_tx: &watch::Sender<Checkpoint>, ctx: &AgentContext, high_water_mark: u64) {
+        snapshot_tx.send_if_modified(|snapshot| {
+            let changed = snapshot.state != ctx.state || snapshot.high_water_mark != high_water_mark;
+            if changed {
+                snapshot.state.clone_from(&ctx.state);
+                snapshot.high_water_mark = high_water_mark;
+                snapshot.touch();
             }
-            Mode::ByteLevel => {
-                if &msg.payload[0..8] == b"MAPLEDNA" {
-                    // Recognize .map data broadcasted via UAL
-                    Ok(format!("Agent {} recognized .map data", self.config.name))
-                } else {
-                    let coords = String::from_utf8(msg.payload.clone())?;
-                    Ok(format!(
-                        "Agent {} handled byte-level {}: {}",
-                        self.config.name, msg.action, coords
-                    ))
+            changed
+        });
+    }
+
+    /// Hands one message to the behaviour and routes its response
+    async fn dispatch(
+        ctx: &mut AgentContext,
+        behaviour: &mut dyn AgentBehaviour,
+        mut envelope: Envelope,
+        response_tx: &broadcast::Sender<UalMessage>,
+    ) -> Result<(), BehaviourError> {
+        if envelope.msg.is_expired() {
+            envelope.answer(Err(RequestError::DeadlineExceeded));
+            return Ok(());
+        }
+        if !ctx.config.accepts(envelope.msg.action()) {
+            envelope.answer(Err(RequestError::Unsupported(envelope.msg.action().to_string())));
+            return Ok(());
+        }
+        if let Err(e) = envelope.msg.validate() {
+            envelope.answer(Err(RequestError::Invalid(e)));
+            return Ok(());
+        }
+        let request_id = envelope.msg.id().to_string();
+        match behaviour.handle_message(ctx, envelope.msg.clone()).await {
+            Ok(Some(mut response)) => {
+                if response.correlation_id().is_none() {
+                    response = response.with_correlation_id(&request_id);
                 }
+                // No subscribers is not an error; broadcast responses are best-effort
+                let _ = response_tx.send(response.clone());
+                envelope.answer(Ok(response));
+                Ok(())
+            }
+            Ok(None) => {
+                envelope.answer(Err(RequestError::NoResponse));
+                Ok(())
+            }
+            Err(e) => {
+                envelope.answer(Err(RequestError::Behaviour(e.to_string())));
+                Err(e)
             }
-            Mode::Grpc => unimplemented!("gRPC processing not yet implemented"),
         }
     }
 
-    /// Sends a message to the agent
+    /// Checks a message against its action's schema and sends it to the agent
     pub async fn send(&self, msg: UalMessage) -> Result<(), Box<dyn Error>> {
-        self.message_tx.send(msg).await?;
+        msg.validate()?;
+        self.message_tx.send(Envelope::tell(msg), Lane::Normal).await?;
         Ok(())
     }
 
-    /// Dumps agent DNA to a .map file
-    pub async fn dump_to_map(&self, path: &str) -> Result<(), Box<dyn Error>> {
-        let mut file = File::create(path).await?;
-        let config_data = serde_json::to_vec(&self.config)?;
-        let config_len = config_data.len() as u32;
-        let state_len = self.state.len() as u32;
+    /// Sends a message on the control lane, ahead of queued normal messages
+    pub async fn send_control(&self, msg: UalMessage) -> Result<(), Box<dyn Error>> {
+        msg.validate()?;
+        self.message_tx.send(Envelope::tell(msg), Lane::Control).await?;
+        Ok(())
+    }
+
+    /// Sends a message and waits for the agent's correlated response.
+    ///
+    /// The message deadline is set from `timeout` unless it already has an earlier one.
+    pub async fn request(&self, msg: UalMessage, timeout: Duration) -> Result<UalMessage, RequestError> {
+        msg.validate().map_err(RequestError::Invalid)?;
+        let timeout = msg.remaining().map_or(timeout, |left| left.min(timeout));
+        let (envelope, reply_rx) = Envelope::ask(msg.with_timeout(timeout));
+        let exchange = async {
+            self.message_tx
+                .send(envelope, Lane::Normal)
+                .await
+                .map_err(|e| match e {
+                    MailboxError::Full => RequestError::MailboxFull,
+                    MailboxError::Closed => RequestError::Closed,
+                })?;
+            reply_rx.await.map_err(|_| RequestError::Closed)?
+        };
+        tokio::time::timeout(timeout, exchange)
+            .await
+            .map_err(|_| RequestError::Timeout)?
+    }
+
+    /// Queues a control signal without waiting for it; the run loop applies signals in order
+    /// and drops any its state does not allow
+    pub fn control(&self, control: AgentControl) {
+        // A closed queue means the run loop has exited and there is nothing left to control
+        let _ = self.control_tx.send((control, None));
+    }
+
+    /// Queues a control signal and waits for the run loop to take it, returning the state it
+    /// moved from, or the state that refused it
+    pub async fn apply_control(&self, control: AgentControl) -> Result<AgentState, AgentState> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        if self.control_tx.send((control, Some(reply_tx))).is_err() {
+            return Err(self.lifecycle());
+        }
+        // The loop drops queued signals when it exits, by which point it has reported its final state
+        reply_rx.await.unwrap_or_else(|_| Err(self.lifecycle()))
+    }
+
+    /// Returns the agent's current lifecycle state
+    pub fn lifecycle(&self) -> AgentState {
+        *self.status_rx.borrow()
+    }
+
+    /// Watches the latest lifecycle state; states passed through quickly may be skipped
+    pub fn lifecycle_changes(&self) -> watch::Receiver<AgentState> {
+        self.status_rx.clone()
+    }
+
+    /// Takes the stream of every lifecycle transition, starting from `Created`; only the first
+    /// caller gets it
+    pub fn take_transitions(&self) -> Option<mpsc::UnboundedReceiver<AgentTransition>> {
+        self.transitions_rx.lock().ok()?.take()
+    }
 
-        // Write header
-        file.write_all(b"MAPLEDNA").await?; // 8 bytes
-        file.write_all(&1u16.to_be_bytes()).await?; // Version 1.0 (2 bytes)
+    /// Returns the agent's mailbox depth and overflow counters
+    pub fn mailbox_stats(&self) -> MailboxStats {
+        self.message_tx.stats()
+    }
 
-        // Write DID (fixed 36 bytes, padded if needed)
-        let mut did_bytes = self.did.as_bytes().to_vec();
-        did_bytes.resize(36, 0);
-        file.write_all(&did_bytes).await?;
+    /// Subscribes to responses produced by the agent's behaviour
+    pub fn responses(&self) -> broadcast::Receiver<UalMessage> {
+        self.response_tx.subscribe()
+    }
 
-        // Write config length and data
-        file.write_all(&config_len.to_be_bytes()).await?;
-        file.write_all(&config_data).await?;
+    /// Returns the agent's configuration
+    pub fn config(&self) -> &AgentConfig {
+        &self.config
+    }
 
-        // Write state length and data
-        file.write_all(&state_len.to_be_bytes()).await?;
-        file.write_all(&self.state).await?;
+    /// Returns the latest state published by the agent
+    pub fn state(&self) -> Vec<u8> {
+        self.snapshot_rx.borrow().state.clone()
+    }
 
+    /// Returns the latest checkpoint of state and mailbox progress
+    pub fn checkpoint(&self) -> Checkpoint {
+        self.snapshot_rx.borrow().clone()
+    }
+
+    /// Watches checkpoints as the agent processes messages
+    pub fn snapshots(&self) -> watch::Receiver<Checkpoint> {
+        self.snapshot_rx.clone()
+    }
+
+    /// Captures the agent's DNA in a container
+    pub fn to_dna(&self) -> Result<DnaFile, DnaError> {
+        let mut dna = DnaFile::new(&self.did);
+        let mut config = self.config.clone();
+        let capabilities = std::mem::take(&mut config.capabilities);
+        dna.set_section(SectionKind::Config, serde_json::to_vec(&config)?);
+        if !capabilities.is_empty() {
+            dna.set_section(SectionKind::Capabilities, serde_json::to_vec(&capabilities)?);
+        }
+        dna.set_section(SectionKind::State, self.state());
+        Ok(dna)
+    }
+
+    /// Dumps agent DNA to a .map file
+    pub async fn dump_to_map(&self, path: &str) -> Result<(), Box<dyn Error>> {
+        let bytes = self.to_dna()?.to_bytes()?;
+        let mut file = File::create(path).await?;
+        file.write_all(&bytes).await?;
         file.flush().await?;
         Ok(())
     }
+
+    /// Dumps agent DNA to a .map file signed by the publisher's key
+    pub async fn dump_signed_map(&self, path: &str, keypair: &Keypair) -> Result<(), Box<dyn Error>> {
+        let mut dna = self.to_dna()?;
+        dna.sign(keypair)?;
+        tokio::fs::write(path, dna.to_bytes()?).await?;
+        Ok(())
+    }
+
+    /// Returns the agent's DID
+    pub fn did(&self) -> &str {
+        &self.did
+    }
 }
 
 #[cfg(test)]
@@ -164,10 +508,7 @@ mod tests {
 
     #[tokio::test]
     async fn test_agent_dump_and_spawn() {
-        let config = AgentConfig {
-            name: "test-agent".to_string(),
-            role: "test".to_string(),
-        };
+        let config = AgentConfig::new("test-agent", "test");
         let agent = Agent::new(config);
         agent.dump_to_map("test_agent.map").await.unwrap();
 
@@ -178,4 +519,106 @@ mod tests {
         // Cleanup
         tokio::fs::remove_file("test_agent.map").await.unwrap();
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_agent_publishes_behaviour_responses() {
+        let config = AgentConfig::new("test-agent", "test");
+        let agent = Agent::new(config);
+        let mut responses = agent.responses();
+        let msg = UalMessage::new("move", maple_ual::Mode::Json)
+            .with_json_payload(&serde_json::json!({"x": 10, "y": 20}))
+            .unwrap();
+        agent.send(msg).await.unwrap();
+
+        let reply = responses.recv().await.unwrap();
+        assert_eq!(reply.action(), "move.result");
+    }
+
+    #[tokio::test]
+    async fn test_agent_request_awaits_correlated_response() {
+        let config = AgentConfig::new("test-agent", "test");
+        let agent = Agent::new(config);
+        let msg = UalMessage::new("move", maple_ual::Mode::Json)
+            .with_json_payload(&serde_json::json!({"x": 10}))
+            .unwrap();
+        let request_id = msg.id().to_string();
+
+        let reply = agent.request(msg, Duration::from_secs(5)).await.unwrap();
+        assert_eq!(reply.correlation_id(), Some(request_id.as_str()));
+
+        // An empty Json payload fails to decode in the default behaviour
+        let malformed = UalMessage::new("move", maple_ual::Mode::Json);
+        let err = agent.request(malformed, Duration::from_secs(5)).await.unwrap_err();
+        assert!(matches!(err, RequestError::Behaviour(_)));
+    }
+
+    #[tokio::test]
+    async fn test_restored_agent_resumes_from_checkpoint() {
+        let config = AgentConfig::new("test-agent", "test");
+        let mut checkpoint = Checkpoint::new("did:maple:agent:1234", vec![9]);
+        checkpoint.high_water_mark = 7;
+        let behaviour = BehaviourRegistry::new().create(&config);
+        let agent = Agent::restore(config, checkpoint, behaviour);
+        assert_eq!(agent.did(), "did:maple:agent:1234");
+
+        let mut snapshots = agent.snapshots();
+        agent.send(UalMessage::new("ping", maple_ual::Mode::ByteLevel)).await.unwrap();
+        snapshots.changed().await.unwrap();
+        let latest = snapshots.borrow().clone();
+        assert_eq!(latest.high_water_mark, 8);
+        assert_eq!(latest.state, vec![9]);
+    }
+
+    #[tokio::test]
+    async fn test_capabilities_roundtrip_dna_and_gate_actions() {
+        let config = AgentConfig::new("test-agent", "test").with_capability(Capability::new("move").with_cost(3));
+        let agent = Agent::new(config);
+        let dna = agent.to_dna().unwrap();
+        assert!(dna.section(SectionKind::Capabilities).is_some());
+        assert_eq!(AgentConfig::from_dna(&dna).unwrap().capabilities, agent.config().capabilities);
+
+        let msg = UalMessage::new("fly", maple_ual::Mode::ByteLevel);
+        let err = agent.request(msg, Duration::from_secs(5)).await.unwrap_err();
+        assert!(matches!(err, RequestError::Unsupported(action) if action == "fly"));
+    }
+
+    #[tokio::test]
+    async fn test_pause_then_drain_processes_queued_messages() {
+        let agent = Agent::new(AgentConfig::new("test-agent", "test"));
+        let mut status = agent.lifecycle_changes();
+        status.wait_for(|s| *s == AgentState::Running).await.unwrap();
+
+        agent.control(AgentControl::Pause);
+        status.wait_for(|s| *s == AgentState::Paused).await.unwrap();
+        let mut responses = agent.responses();
+        agent.send(UalMessage::new("ping", maple_ual::Mode::ByteLevel)).await.unwrap();
+        assert!(tokio::time::timeout(Duration::from_millis(50), responses.recv()).await.is_err());
+
+        agent.control(AgentControl::Drain);
+        assert_eq!(responses.recv().await.unwrap().action(), "ping.result");
+        status.wait_for(|s| *s == AgentState::Stopped).await.unwrap();
+        assert!(agent.send(UalMessage::new("ping", maple_ual::Mode::ByteLevel)).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_controls_apply_in_order_and_report_every_transition() {
+        let agent = Agent::new(AgentConfig::new("test-agent", "test"));
+        let mut transitions = agent.take_transitions().unwrap();
+        assert!(agent.take_transitions().is_none());
+
+        agent.control(AgentControl::Pause);
+        agent.control(AgentControl::Run);
+        agent.control(AgentControl::Stop);
+        agent.control(AgentControl::Pause); // Must not undo the Stop queued before it
+        let mut seen = Vec::new();
+        while let Some(transition) = transitions.recv().await {
+            seen.push((transition.from, transition.to));
+        }
+        use AgentState::*;
+        assert_eq!(
+            seen,
+            vec![(Created, Starting), (Starting, Running), (Running, Paused), (Paused, Running), (Running, Stopped)]
+        );
+        assert_eq!(agent.apply_control(AgentControl::Run).await, Err(Stopped));
+    }
+}
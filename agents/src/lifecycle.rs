@@ -0,0 +1,90 @@
+// Agent lifecycle states and the control signals that move between them
+// © 2025 Finalverse Inc. All rights reserved.
+
+use serde::{Deserialize, Serialize};
+
+/// Where an agent is in its lifecycle
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum AgentState {
+    Created,  // Known to the runtime, run loop not started
+    Starting, // Running `on_start`
+    Running,  // Processing its mailbox
+    Paused,   // Mailbox accepts messages but they are not processed
+    Draining, // Mailbox closed; remaining messages are processed before stopping
+    Stopped,  // Run loop exited cleanly
+    Failed,   // Run loop exited because of an error
+}
+
+impl AgentState {
+    /// Whether the lifecycle allows moving from this state to `next`
+    pub fn can_transition_to(self, next: AgentState) -> bool {
+        use AgentState::*;
+        matches!(
+            (self, next),
+            (Created, Starting)
+                | (Created, Stopped)
+                | (Starting, Running)
+                | (Starting, Failed)
+                | (Running, Paused)
+                | (Running, Draining)
+                | (Running, Stopped)
+                | (Running, Failed)
+                | (Paused, Running)
+                | (Paused, Draining)
+                | (Paused, Stopped)
+                | (Paused, Failed)
+                | (Draining, Stopped)
+                | (Draining, Failed)
+                | (Stopped, Starting)
+                | (Failed, Starting)
+        )
+    }
+
+    /// Whether the run loop has exited
+    pub fn is_terminal(self) -> bool {
+        matches!(self, AgentState::Stopped | AgentState::Failed)
+    }
+}
+
+/// A lifecycle change reported by the run loop
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AgentTransition {
+    pub from: AgentState,
+    pub to: AgentState,
+}
+
+/// Signal from the owner of an agent to its run loop
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AgentControl {
+    Run,   // Process messages (the initial signal; resumes a paused agent)
+    Pause, // Stop taking messages off the mailbox
+    Drain, // Close the mailbox, finish queued messages, then stop
+    Stop,  // Stop immediately, dropping queued messages
+}
+
+impl AgentControl {
+    /// The state an agent enters when it obeys this signal
+    pub fn target(self) -> AgentState {
+        match self {
+            AgentControl::Run => AgentState::Running,
+            AgentControl::Pause => AgentState::Paused,
+            AgentControl::Drain => AgentState::Draining,
+            AgentControl::Stop => AgentState::Stopped,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lifecycle_transitions() {
+        assert!(AgentState::Created.can_transition_to(AgentState::Starting));
+        assert!(AgentState::Running.can_transition_to(AgentState::Paused));
+        assert!(AgentState::Paused.can_transition_to(AgentState::Running));
+        assert!(!AgentState::Stopped.can_transition_to(AgentState::Running));
+        assert!(!AgentState::Draining.can_transition_to(AgentState::Running));
+        assert!(AgentState::Failed.is_terminal());
+    }
+}
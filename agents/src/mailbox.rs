@@ -0,0 +1,365 @@
+// Bounded agent mailboxes with overflow policies and a priority lane for control messages
+// © 2025 Finalverse Inc. All rights reserved.
+
+use crate::request::{Envelope, RequestError};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use thiserror::Error;
+use tokio::sync::Notify;
+
+/// Default number of normal messages an agent queues
+pub const DEFAULT_MAILBOX_CAPACITY: usize = 100;
+
+/// Number of control messages an agent queues; control messages skip the overflow policy,
+/// and one arriving at a full control lane is rejected with `MailboxError::Full`
+pub const CONTROL_LANE_CAPACITY: usize = 16;
+
+/// What happens when a message arrives at a full mailbox
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum OverflowPolicy {
+    #[default]
+    Block, // Sender waits for space
+    DropOldest, // Oldest queued message is discarded to make room
+    DropNewest, // Incoming message is discarded
+    Reject, // Sender gets `MailboxError::Full`
+}
+
+/// Per-agent mailbox settings
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "MailboxSettings")]
+pub struct MailboxConfig {
+    pub capacity: usize, // Normal messages queued before the overflow policy applies
+    pub overflow: OverflowPolicy,
+}
+
+impl MailboxConfig {
+    /// Checks the settings, rejecting a mailbox with no room whose policy needs some: blocking senders
+    /// would wait forever, and there is no oldest message to drop
+    pub fn new(capacity: usize, overflow: OverflowPolicy) -> Result<Self, MailboxConfigError> {
+        if capacity == 0 && matches!(overflow, OverflowPolicy::Block | OverflowPolicy::DropOldest) {
+            return Err(MailboxConfigError::ZeroCapacity);
+        }
+        Ok(MailboxConfig { capacity, overflow })
+    }
+}
+
+/// Mailbox settings as written in agent DNA, checked before use
+#[derive(Deserialize)]
+struct MailboxSettings {
+    capacity: usize,
+    overflow: OverflowPolicy,
+}
+
+impl TryFrom<MailboxSettings> for MailboxConfig {
+    type Error = MailboxConfigError;
+
+    fn try_from(settings: MailboxSettings) -> Result<Self, Self::Error> {
+        MailboxConfig::new(settings.capacity, settings.overflow)
+    }
+}
+
+/// Errors raised by invalid mailbox settings
+#[derive(Debug, Error)]
+pub enum MailboxConfigError {
+    #[error("a mailbox that blocks or drops its oldest message when full needs a capacity of at least 1")]
+    ZeroCapacity,
+}
+
+impl Default for MailboxConfig {
+    fn default() -> Self {
+        MailboxConfig {
+            capacity: DEFAULT_MAILBOX_CAPACITY,
+            overflow: OverflowPolicy::Block,
+        }
+    }
+}
+
+/// Queue of a mailbox a message is sent on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lane {
+    Control, // Delivered before any normal message
+    Normal,
+}
+
+/// Errors returned to senders
+#[derive(Debug, Error)]
+pub enum MailboxError {
+    #[error("mailbox is full")]
+    Full,
+    #[error("mailbox is closed")]
+    Closed,
+}
+
+/// Point-in-time view of a mailbox
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MailboxStats {
+    pub depth: usize, // Normal messages waiting
+    pub control_depth: usize, // Control messages waiting
+    pub capacity: usize,
+    pub delivered: u64, // Messages handed to the run loop so far
+    pub dropped: u64, // Messages discarded by DropOldest or DropNewest
+    pub rejected: u64, // Sends refused because a lane was full
+}
+
+#[derive(Debug, Default)]
+struct Queues {
+    control: VecDeque<Envelope>,
+    normal: VecDeque<Envelope>,
+    closed: bool, // No further sends accepted
+}
+
+#[derive(Debug)]
+struct Shared {
+    config: MailboxConfig,
+    queues: Mutex<Queues>,
+    not_empty: Notify,
+    not_full: Notify,
+    senders_gone: AtomicBool,
+    delivered: AtomicU64,
+    dropped: AtomicU64,
+    rejected: AtomicU64,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, Queues> {
+        // A panic while holding the lock cannot leave the queues inconsistent
+        self.queues.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Creates a mailbox, returning its sending and receiving halves
+pub(crate) fn mailbox(config: MailboxConfig) -> (MailboxSender, MailboxReceiver) {
+    let shared = Arc::new(Shared {
+        config,
+        queues: Mutex::new(Queues::default()),
+        not_empty: Notify::new(),
+        not_full: Notify::new(),
+        senders_gone: AtomicBool::new(false),
+        delivered: AtomicU64::new(0),
+        dropped: AtomicU64::new(0),
+        rejected: AtomicU64::new(0),
+    });
+    let sender = MailboxSender {
+        handle: Arc::new(SenderHandle { shared: shared.clone() }),
+    };
+    (sender, MailboxReceiver { shared })
+}
+
+/// Marks the mailbox as abandoned once the last sender is dropped
+#[derive(Debug)]
+struct SenderHandle {
+    shared: Arc<Shared>,
+}
+
+impl Drop for SenderHandle {
+    fn drop(&mut self) {
+        self.shared.senders_gone.store(true, Ordering::SeqCst);
+        self.shared.not_empty.notify_one();
+    }
+}
+
+/// Sending half of a mailbox; clones share the same queue
+#[derive(Debug, Clone)]
+pub(crate) struct MailboxSender {
+    handle: Arc<SenderHandle>,
+}
+
+impl MailboxSender {
+    /// Queues an envelope, applying the overflow policy if the lane is full
+    pub async fn send(&self, mut envelope: Envelope, lane: Lane) -> Result<(), MailboxError> {
+        let shared = &self.handle.shared;
+        loop {
+            let notified = shared.not_full.notified();
+            tokio::pin!(notified);
+            {
+                let mut queues = shared.lock();
+                if queues.closed {
+                    return Err(MailboxError::Closed);
+                }
+                if lane == Lane::Control {
+                    if queues.control.len() >= CONTROL_LANE_CAPACITY {
+                        shared.rejected.fetch_add(1, Ordering::Relaxed);
+                        return Err(MailboxError::Full);
+                    }
+                    queues.control.push_back(envelope);
+                    shared.not_empty.notify_one();
+                    return Ok(());
+                }
+                if queues.normal.len() < shared.config.capacity {
+                    queues.normal.push_back(envelope);
+                    shared.not_empty.notify_one();
+                    return Ok(());
+                }
+                match shared.config.overflow {
+                    OverflowPolicy::Block => {
+                        notified.as_mut().enable();
+                    }
+                    OverflowPolicy::DropOldest => {
+                        // With no room at all, the incoming message is the only one to drop
+                        match queues.normal.pop_front() {
+                            Some(mut oldest) => {
+                                oldest.answer(Err(RequestError::Dropped));
+                                queues.normal.push_back(envelope);
+                                shared.not_empty.notify_one();
+                            }
+                            None => envelope.answer(Err(RequestError::Dropped)),
+                        }
+                        shared.dropped.fetch_add(1, Ordering::Relaxed);
+                        return Ok(());
+                    }
+                    OverflowPolicy::DropNewest => {
+                        envelope.answer(Err(RequestError::Dropped));
+                        shared.dropped.fetch_add(1, Ordering::Relaxed);
+                        return Ok(());
+                    }
+                    OverflowPolicy::Reject => {
+                        shared.rejected.fetch_add(1, Ordering::Relaxed);
+                        return Err(MailboxError::Full);
+                    }
+                }
+            }
+            notified.await;
+        }
+    }
+
+    /// Current depth and counters
+    pub fn stats(&self) -> MailboxStats {
+        let shared = &self.handle.shared;
+        let queues = shared.lock();
+        MailboxStats {
+            depth: queues.normal.len(),
+            control_depth: queues.control.len(),
+            capacity: shared.config.capacity,
+            delivered: shared.delivered.load(Ordering::Relaxed),
+            dropped: shared.dropped.load(Ordering::Relaxed),
+            rejected: shared.rejected.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Receiving half of a mailbox, owned by the agent's run loop
+#[derive(Debug)]
+pub(crate) struct MailboxReceiver {
+    shared: Arc<Shared>,
+}
+
+impl MailboxReceiver {
+    /// Takes the next envelope, control lane first; `None` once closed and empty
+    /// or once every sender is gone and the queue is empty
+    pub async fn recv(&mut self) -> Option<Envelope> {
+        loop {
+            let notified = self.shared.not_empty.notified();
+            tokio::pin!(notified);
+            {
+                let mut queues = self.shared.lock();
+                let next = match queues.control.pop_front() {
+                    Some(envelope) => Some(envelope),
+                    None => queues.normal.pop_front(),
+                };
+                if let Some(envelope) = next {
+                    self.shared.delivered.fetch_add(1, Ordering::Relaxed);
+                    self.shared.not_full.notify_one();
+                    return Some(envelope);
+                }
+                if queues.closed || self.shared.senders_gone.load(Ordering::SeqCst) {
+                    return None;
+                }
+                notified.as_mut().enable();
+            }
+            notified.await;
+        }
+    }
+
+    /// Stops accepting sends; queued envelopes are still delivered
+    pub fn close(&mut self) {
+        self.shared.lock().closed = true;
+        // Wake blocked senders so they observe the closed mailbox
+        self.shared.not_full.notify_waiters();
+    }
+}
+
+impl Drop for MailboxReceiver {
+    fn drop(&mut self) {
+        let mut queues = self.shared.lock();
+        queues.closed = true;
+        // Dropping queued envelopes tells waiting requesters the agent is gone
+        queues.control.clear();
+        queues.normal.clear();
+        drop(queues);
+        self.shared.not_full.notify_waiters();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use maple_ual::{Mode, UalMessage};
+
+    fn envelope(action: &str) -> Envelope {
+        Envelope::tell(UalMessage::new(action, Mode::ByteLevel))
+    }
+
+    async fn drain(rx: &mut MailboxReceiver) -> Vec<String> {
+        let mut actions = Vec::new();
+        while let Ok(Some(e)) = tokio::time::timeout(std::time::Duration::from_millis(10), rx.recv()).await {
+            actions.push(e.msg.action().to_string());
+        }
+        actions
+    }
+
+    #[tokio::test]
+    async fn test_overflow_policies() {
+        for (overflow, expected) in [
+            (OverflowPolicy::DropOldest, vec!["ctl", "b", "c"]),
+            (OverflowPolicy::DropNewest, vec!["ctl", "a", "b"]),
+            (OverflowPolicy::Reject, vec!["ctl", "a", "b"]),
+        ] {
+            let (tx, mut rx) = mailbox(MailboxConfig { capacity: 2, overflow });
+            tx.send(envelope("a"), Lane::Normal).await.unwrap();
+            tx.send(envelope("b"), Lane::Normal).await.unwrap();
+            let third = tx.send(envelope("c"), Lane::Normal).await;
+            assert_eq!(third.is_err(), overflow == OverflowPolicy::Reject);
+            tx.send(envelope("ctl"), Lane::Control).await.unwrap();
+
+            let stats = tx.stats();
+            assert_eq!((stats.depth, stats.control_depth), (2, 1));
+            assert_eq!(drain(&mut rx).await, expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_block_waits_for_space_and_close_rejects_sends() {
+        let (tx, mut rx) = mailbox(MailboxConfig { capacity: 1, overflow: OverflowPolicy::Block });
+        tx.send(envelope("a"), Lane::Normal).await.unwrap();
+        let blocked = {
+            let tx = tx.clone();
+            tokio::spawn(async move { tx.send(envelope("b"), Lane::Normal).await })
+        };
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        assert!(!blocked.is_finished());
+
+        assert_eq!(rx.recv().await.unwrap().msg.action(), "a");
+        blocked.await.unwrap().unwrap();
+        rx.close();
+        assert!(matches!(tx.send(envelope("c"), Lane::Normal).await, Err(MailboxError::Closed)));
+        assert_eq!(rx.recv().await.unwrap().msg.action(), "b");
+        assert!(rx.recv().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_mailbox_needs_capacity_to_block_or_drop_oldest() {
+        assert!(MailboxConfig::new(0, OverflowPolicy::Block).is_err());
+        assert!(MailboxConfig::new(0, OverflowPolicy::DropOldest).is_err());
+        assert!(MailboxConfig::new(0, OverflowPolicy::Reject).is_ok());
+        let parsed = serde_json::from_str::<MailboxConfig>(r#"{"capacity": 0, "overflow": "Block"}"#);
+        assert!(parsed.unwrap_err().to_string().contains("capacity of at least 1"));
+
+        // Built directly, an empty mailbox dropping its oldest message still stays within capacity
+        let (tx, _rx) = mailbox(MailboxConfig { capacity: 0, overflow: OverflowPolicy::DropOldest });
+        tx.send(envelope("a"), Lane::Normal).await.unwrap();
+        let stats = tx.stats();
+        assert_eq!((stats.depth, stats.dropped), (0, 1));
+    }
+}
@@ -0,0 +1,444 @@
+// Structured agent memory: short-term buffer, episodic log and semantic recall
+// © 2025 Finalverse Inc. All rights reserved.
+
+use maple_ual::UalMessage;
+use maple_vectordb::{VectorDb, VectorEntry};
+use mapledb::MapleDb;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+
+/// Default number of messages kept in the short-term buffer
+pub const DEFAULT_SHORT_TERM_CAPACITY: usize = 32;
+
+/// Errors raised by the memory subsystem
+#[derive(Debug, Error)]
+pub enum MemoryError {
+    #[error("memory storage error: {0}")]
+    Storage(String),
+    #[error("memory encoding error: {0}")]
+    Encoding(#[from] bincode::Error),
+    #[error("{0} memory is not configured for this agent")]
+    Unavailable(&'static str),
+    #[error("embedding is empty")]
+    EmptyEmbedding,
+}
+
+/// One recorded interaction in the episodic log
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Episode {
+    pub seq: u64,
+    pub recorded_at: u64, // Unix time in milliseconds
+    pub message_id: String,
+    pub action: String,
+    pub payload: Vec<u8>,
+}
+
+/// Bounded buffer of the most recent messages in a conversation
+#[derive(Debug, Clone)]
+pub struct ShortTermMemory {
+    capacity: usize,
+    messages: VecDeque<UalMessage>,
+}
+
+impl ShortTermMemory {
+    /// Creates a buffer holding at most `capacity` messages
+    pub fn new(capacity: usize) -> Self {
+        ShortTermMemory {
+            capacity,
+            messages: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    /// Adds a message, evicting the oldest one when full
+    pub fn push(&mut self, msg: UalMessage) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.messages.len() == self.capacity {
+            self.messages.pop_front();
+        }
+        self.messages.push_back(msg);
+    }
+
+    /// Messages from oldest to newest
+    pub fn iter(&self) -> impl Iterator<Item = &UalMessage> {
+        self.messages.iter()
+    }
+
+    /// The `n` most recent messages, oldest first
+    pub fn recent(&self, n: usize) -> impl Iterator<Item = &UalMessage> {
+        self.messages.iter().skip(self.messages.len().saturating_sub(n))
+    }
+
+    /// Number of messages currently buffered
+    pub fn len(&self) -> usize {
+        self.messages.len()
+    }
+
+    /// Whether no messages are buffered
+    pub fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+
+    /// Forgets every buffered message, e.g. when a conversation ends
+    pub fn clear(&mut self) {
+        self.messages.clear();
+    }
+}
+
+/// Append-only log of episodes stored in MapleDB under the agent's DID
+#[derive(Clone)]
+pub struct EpisodicLog {
+    db: Arc<MapleDb>,
+    did: String,
+}
+
+impl EpisodicLog {
+    /// Opens the log for an agent
+    pub fn new(db: Arc<MapleDb>, did: &str) -> Self {
+        EpisodicLog {
+            db,
+            did: did.to_string(),
+        }
+    }
+
+    /// Appends an episode for a message, returning it with its sequence number
+    pub fn append(&self, msg: &UalMessage) -> Result<Episode, MemoryError> {
+        // Reserving the number atomically keeps concurrent appends from sharing a sequence number
+        let seq = self.db.increment(&self.seq_key()).map_err(storage)?;
+        let episode = Episode {
+            seq,
+            recorded_at: unix_millis(),
+            message_id: msg.id().to_string(),
+            action: msg.action().to_string(),
+            payload: msg.payload().to_vec(),
+        };
+        self.db
+            .store(&self.episode_key(seq), &bincode::serialize(&episode)?)
+            .map_err(storage)?;
+        Ok(episode)
+    }
+
+    /// Keeps the embedding of an episode so the semantic index can be rebuilt from the log
+    pub fn attach_embedding(&self, seq: u64, embedding: &[f32]) -> Result<(), MemoryError> {
+        self.db
+            .store(&self.embedding_key(seq), &bincode::serialize(embedding)?)
+            .map_err(storage)
+    }
+
+    /// Embeddings attached to episodes, in sequence order
+    pub fn embeddings(&self) -> Result<Vec<(u64, Vec<f32>)>, MemoryError> {
+        let prefix = self.embedding_prefix();
+        self.db
+            .scan_prefix(&prefix)
+            .map_err(storage)?
+            .into_iter()
+            .filter_map(|(key, bytes)| Some((key.strip_prefix(&prefix)?.parse().ok()?, bytes)))
+            .map(|(seq, bytes)| Ok((seq, bincode::deserialize(&bytes)?)))
+            .collect()
+    }
+
+    /// Retrieves an episode by sequence number
+    pub fn get(&self, seq: u64) -> Result<Option<Episode>, MemoryError> {
+        let bytes = self.db.get(&self.episode_key(seq)).map_err(storage)?;
+        bytes
+            .map(|b| bincode::deserialize(&b).map_err(Into::into))
+            .transpose()
+    }
+
+    /// All episodes in the order they were recorded
+    pub fn all(&self) -> Result<Vec<Episode>, MemoryError> {
+        self.db
+            .scan_prefix(&self.episode_prefix())
+            .map_err(storage)?
+            .into_iter()
+            .map(|(_, bytes)| bincode::deserialize(&bytes).map_err(Into::into))
+            .collect()
+    }
+
+    /// Number of episodes recorded so far
+    pub fn count(&self) -> Result<u64, MemoryError> {
+        self.next_seq()
+    }
+
+    fn next_seq(&self) -> Result<u64, MemoryError> {
+        let bytes = self.db.get(&self.seq_key()).map_err(storage)?;
+        Ok(bytes
+            .and_then(|b| <[u8; 8]>::try_from(b.as_slice()).ok())
+            .map_or(0, u64::from_be_bytes))
+    }
+
+    fn episode_prefix(&self) -> String {
+        format!("episode:{}:", self.did)
+    }
+
+    fn episode_key(&self, seq: u64) -> String {
+        // Zero-padded so key order matches sequence order
+        format!("{}{:020}", self.episode_prefix(), seq)
+    }
+
+    fn embedding_prefix(&self) -> String {
+        format!("episode-embedding:{}:", self.did)
+    }
+
+    fn embedding_key(&self, seq: u64) -> String {
+        format!("{}{:020}", self.embedding_prefix(), seq)
+    }
+
+    fn seq_key(&self) -> String {
+        format!("episode-seq:{}", self.did)
+    }
+}
+
+impl std::fmt::Debug for EpisodicLog {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EpisodicLog").field("did", &self.did).finish()
+    }
+}
+
+/// Embeddings of episodes in the vector database, for recall by similarity
+#[derive(Clone)]
+pub struct SemanticMemory {
+    db: Arc<VectorDb>,
+    did: String,
+}
+
+impl SemanticMemory {
+    /// Opens the semantic store for an agent
+    pub fn new(db: Arc<VectorDb>, did: &str) -> Self {
+        SemanticMemory {
+            db,
+            did: did.to_string(),
+        }
+    }
+
+    /// Stores the embedding of an episode
+    pub async fn remember(&self, seq: u64, embedding: Vec<f32>) -> Result<(), MemoryError> {
+        let entry = VectorEntry {
+            id: format!("{}{}", self.prefix(), seq),
+            vector: embedding,
+        };
+        self.db.store(entry).await.map_err(|e| MemoryError::Storage(e.to_string()))
+    }
+
+    /// Sequence numbers of the episodes most similar to the query, best first
+    pub async fn search(&self, query: Vec<f32>, limit: usize) -> Result<Vec<u64>, MemoryError> {
+        let prefix = self.prefix();
+        let entries = self
+            .db
+            .search_prefix(query, limit, &prefix)
+            .await
+            .map_err(|e| MemoryError::Storage(e.to_string()))?;
+        Ok(entries
+            .iter()
+            .filter_map(|e| e.id.strip_prefix(&prefix)?.parse().ok())
+            .collect())
+    }
+
+    fn prefix(&self) -> String {
+        format!("{}#", self.did)
+    }
+}
+
+impl std::fmt::Debug for SemanticMemory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SemanticMemory").field("did", &self.did).finish()
+    }
+}
+
+/// Memory available to an agent's behaviour
+#[derive(Debug, Clone)]
+pub struct AgentMemory {
+    pub short_term: ShortTermMemory,
+    episodic: Option<EpisodicLog>,
+    semantic: Option<SemanticMemory>,
+}
+
+impl AgentMemory {
+    /// Creates memory with only a short-term buffer
+    pub fn new(short_term_capacity: usize) -> Self {
+        AgentMemory {
+            short_term: ShortTermMemory::new(short_term_capacity),
+            episodic: None,
+            semantic: None,
+        }
+    }
+
+    /// Persists episodes in MapleDB
+    pub fn with_episodic(mut self, log: EpisodicLog) -> Self {
+        self.episodic = Some(log);
+        self
+    }
+
+    /// Indexes episode embeddings for similarity recall
+    pub fn with_semantic(mut self, semantic: SemanticMemory) -> Self {
+        self.semantic = Some(semantic);
+        self
+    }
+
+    /// The episodic log, if configured
+    pub fn episodic(&self) -> Result<&EpisodicLog, MemoryError> {
+        self.episodic.as_ref().ok_or(MemoryError::Unavailable("episodic"))
+    }
+
+    /// Records a message as an episode, indexing its embedding if one is given
+    pub async fn record(&self, msg: &UalMessage, embedding: Option<Vec<f32>>) -> Result<Episode, MemoryError> {
+        let episodic = self.episodic()?;
+        // Checked up front so a rejected embedding leaves no episode behind
+        let semantic = match &embedding {
+            Some(embedding) if embedding.is_empty() => return Err(MemoryError::EmptyEmbedding),
+            Some(_) => Some(self.semantic.as_ref().ok_or(MemoryError::Unavailable("semantic"))?),
+            None => None,
+        };
+        let episode = episodic.append(msg)?;
+        if let (Some(semantic), Some(embedding)) = (semantic, embedding) {
+            // Only embeddings the index accepted are kept for rebuilding it
+            semantic.remember(episode.seq, embedding.clone()).await?;
+            episodic.attach_embedding(episode.seq, &embedding)?;
+        }
+        Ok(episode)
+    }
+
+    /// Re-indexes the embeddings kept in the episodic log, as the vector store does not survive restarts.
+    ///
+    /// Returns how many were indexed; an embedding the index rejects is logged and skipped.
+    pub async fn rebuild_semantic(&self) -> Result<usize, MemoryError> {
+        let (Some(episodic), Some(semantic)) = (&self.episodic, &self.semantic) else {
+            return Ok(0);
+        };
+        let mut count = 0;
+        for (seq, embedding) in episodic.embeddings()? {
+            match semantic.remember(seq, embedding).await {
+                Ok(()) => count += 1,
+                Err(e) => eprintln!("Skipping embedding of episode {} for {}: {}", seq, semantic.did, e),
+            }
+        }
+        Ok(count)
+    }
+
+    /// Recalls the recorded episodes most similar to the query embedding
+    pub async fn recall(&self, query: Vec<f32>, limit: usize) -> Result<Vec<Episode>, MemoryError> {
+        let semantic = self.semantic.as_ref().ok_or(MemoryError::Unavailable("semantic"))?;
+        let episodic = self.episodic()?;
+        let mut episodes = Vec::new();
+        for seq in semantic.search(query, limit).await? {
+            if let Some(episode) = episodic.get(seq)? {
+                episodes.push(episode);
+            }
+        }
+        Ok(episodes)
+    }
+}
+
+impl Default for AgentMemory {
+    fn default() -> Self {
+        AgentMemory::new(DEFAULT_SHORT_TERM_CAPACITY)
+    }
+}
+
+fn storage(e: impl std::fmt::Display) -> MemoryError {
+    MemoryError::Storage(e.to_string())
+}
+
+fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use maple_ual::Mode;
+
+    #[test]
+    fn test_short_term_memory_is_bounded() {
+        let mut memory = ShortTermMemory::new(2);
+        for action in ["a", "b", "c"] {
+            memory.push(UalMessage::new(action, Mode::ByteLevel));
+        }
+        let actions: Vec<_> = memory.iter().map(|m| m.action().to_string()).collect();
+        assert_eq!(actions, vec!["b", "c"]);
+    }
+
+    #[tokio::test]
+    async fn test_recall_by_embedding_similarity() {
+        let db = Arc::new(MapleDb::new("test_memory_db").unwrap());
+        let did = "did:maple:agent:memory";
+        let memory = AgentMemory::new(4)
+            .with_episodic(EpisodicLog::new(db, did))
+            .with_semantic(SemanticMemory::new(Arc::new(VectorDb::new()), did));
+
+        let north = UalMessage::new("move-north", Mode::ByteLevel);
+        let south = UalMessage::new("move-south", Mode::ByteLevel);
+        memory.record(&north, Some(vec![0.0, 1.0])).await.unwrap();
+        memory.record(&south, Some(vec![0.0, -1.0])).await.unwrap();
+
+        let recalled = memory.recall(vec![0.1, 0.9], 1).await.unwrap();
+        assert_eq!(recalled[0].action, "move-north");
+        assert_eq!(memory.episodic().unwrap().all().unwrap().len(), 2);
+
+        drop(memory);
+        std::fs::remove_dir_all("test_memory_db").unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_semantic_index_is_rebuilt_from_the_log() {
+        let db = Arc::new(MapleDb::new("test_memory_rebuild_db").unwrap());
+        let did = "did:maple:agent:rebuild";
+        let open = |vectors| {
+            AgentMemory::new(4)
+                .with_episodic(EpisodicLog::new(db.clone(), did))
+                .with_semantic(SemanticMemory::new(vectors, did))
+        };
+        let memory = open(Arc::new(VectorDb::new()));
+        memory.record(&UalMessage::new("east", Mode::ByteLevel), Some(vec![1.0, 0.0])).await.unwrap();
+        memory.record(&UalMessage::new("plain", Mode::ByteLevel), None).await.unwrap();
+        memory.record(&UalMessage::new("west", Mode::ByteLevel), Some(vec![-1.0, 0.0])).await.unwrap();
+
+        // A fresh vector store, as after a restart, recalls nothing until rebuilt
+        let restored = open(Arc::new(VectorDb::new()));
+        assert!(restored.recall(vec![-1.0, 0.1], 1).await.unwrap().is_empty());
+        assert_eq!(restored.rebuild_semantic().await.unwrap(), 2);
+        assert_eq!(restored.recall(vec![-1.0, 0.1], 1).await.unwrap()[0].action, "west");
+        assert_eq!(restored.episodic().unwrap().count().unwrap(), 3);
+
+        drop((memory, restored));
+        drop(db);
+        std::fs::remove_dir_all("test_memory_rebuild_db").unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_empty_embedding_records_nothing() {
+        let db = Arc::new(MapleDb::new("test_memory_empty_db").unwrap());
+        let did = "did:maple:agent:empty";
+        let log = EpisodicLog::new(db.clone(), did);
+        let memory = AgentMemory::new(4)
+            .with_episodic(log.clone())
+            .with_semantic(SemanticMemory::new(Arc::new(VectorDb::new()), did));
+        let msg = UalMessage::new("north", Mode::ByteLevel);
+        assert!(matches!(memory.record(&msg, Some(vec![])).await, Err(MemoryError::EmptyEmbedding)));
+        assert_eq!(log.count().unwrap(), 0);
+
+        // An empty embedding stored by an older version is skipped rather than blocking the rebuild
+        let old = log.append(&msg).unwrap();
+        log.attach_embedding(old.seq, &[]).unwrap();
+        memory.record(&msg, Some(vec![0.0, 1.0])).await.unwrap();
+        assert_eq!(memory.rebuild_semantic().await.unwrap(), 1);
+
+        let episodic_only = AgentMemory::new(4).with_episodic(log);
+        assert!(matches!(
+            episodic_only.record(&msg, Some(vec![1.0])).await,
+            Err(MemoryError::Unavailable("semantic"))
+        ));
+        assert_eq!(memory.episodic().unwrap().count().unwrap(), 2);
+
+        drop((memory, episodic_only));
+        drop(db);
+        std::fs::remove_dir_all("test_memory_empty_db").unwrap();
+    }
+}
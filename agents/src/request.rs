@@ -0,0 +1,60 @@
+// Request/response plumbing between callers and agent run loops
+// © 2025 Finalverse Inc. All rights reserved.
+
+use maple_ual::{UalMessage, ValidationError};
+use thiserror::Error;
+use tokio::sync::oneshot;
+
+/// Default time a caller waits for an agent's answer
+pub const DEFAULT_REQUEST_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// Errors returned to callers awaiting a response
+#[derive(Debug, Error)]
+pub enum RequestError {
+    #[error("request timed out")]
+    Timeout,
+    #[error("request deadline passed before the agent handled it")]
+    DeadlineExceeded,
+    #[error("agent does not accept action {0}")]
+    Unsupported(String),
+    #[error("request does not match its action schema: {0}")]
+    Invalid(#[from] ValidationError),
+    #[error("agent handled the request without responding")]
+    NoResponse,
+    #[error("agent failed to handle the request: {0}")]
+    Behaviour(String),
+    #[error("agent mailbox is full")]
+    MailboxFull,
+    #[error("message was dropped from a full mailbox")]
+    Dropped,
+    #[error("agent is not running")]
+    Closed,
+}
+
+/// Message queued in an agent mailbox, with an optional reply path
+#[derive(Debug)]
+pub(crate) struct Envelope {
+    pub msg: UalMessage,
+    pub reply_tx: Option<oneshot::Sender<Result<UalMessage, RequestError>>>,
+}
+
+impl Envelope {
+    /// Wraps a fire-and-forget message
+    pub fn tell(msg: UalMessage) -> Self {
+        Envelope { msg, reply_tx: None }
+    }
+
+    /// Wraps a request, returning the receiver for its answer
+    pub fn ask(msg: UalMessage) -> (Self, oneshot::Receiver<Result<UalMessage, RequestError>>) {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        (Envelope { msg, reply_tx: Some(reply_tx) }, reply_rx)
+    }
+
+    /// Delivers the outcome to the requester, if any is waiting
+    pub fn answer(&mut self, result: Result<UalMessage, RequestError>) {
+        if let Some(reply_tx) = self.reply_tx.take() {
+            // The requester may have timed out and dropped its receiver
+            let _ = reply_tx.send(result);
+        }
+    }
+}
@@ -0,0 +1,229 @@
+// Publisher signatures and trust store for `.map` DNA
+// © 2025 Finalverse Inc. All rights reserved.
+//
+// Signatures are Ed25519 signatures made with the same libp2p `identity::Keypair`
+// MAP nodes use. Each signature covers the container encoded without its
+// Signatures section, so several publishers can co-sign the same DNA.
+
+use crate::dna::{DnaError, DnaFile, SectionKind};
+use libp2p_identity::{Keypair, PeerId, PublicKey};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::path::Path;
+
+/// Domain separator so DNA signatures cannot be replayed as other messages
+const SIGNING_CONTEXT: &[u8] = b"maple-dna-signature-v1";
+
+/// A single publisher signature over a DNA container
+#[derive(Debug, Clone, PartialEq)]
+pub struct DnaSignature {
+    pub public_key: PublicKey,
+    pub signature: Vec<u8>,
+}
+
+impl DnaSignature {
+    /// Peer ID of the publisher that produced this signature
+    pub fn publisher(&self) -> PeerId {
+        self.public_key.to_peer_id()
+    }
+}
+
+impl DnaFile {
+    /// Bytes covered by publisher signatures
+    fn signing_payload(&self) -> Result<Vec<u8>, DnaError> {
+        let mut unsigned = self.clone();
+        unsigned.remove_section(SectionKind::Signatures);
+        let mut payload = SIGNING_CONTEXT.to_vec();
+        payload.extend_from_slice(&unsigned.to_bytes()?);
+        Ok(payload)
+    }
+
+    /// Signs the DNA, replacing any earlier signature by the same key
+    pub fn sign(&mut self, keypair: &Keypair) -> Result<(), DnaError> {
+        let signature = keypair
+            .sign(&self.signing_payload()?)
+            .map_err(|e| DnaError::InvalidKey(e.to_string()))?;
+        let public_key = keypair.public();
+
+        let mut signatures = self.signatures()?;
+        signatures.retain(|s| s.public_key != public_key);
+        signatures.push(DnaSignature { public_key, signature });
+        self.set_section(SectionKind::Signatures, encode_signatures(&signatures)?);
+        Ok(())
+    }
+
+    /// Decodes the Signatures section (empty if the DNA is unsigned)
+    pub fn signatures(&self) -> Result<Vec<DnaSignature>, DnaError> {
+        match self.section(SectionKind::Signatures) {
+            Some(data) => decode_signatures(data),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Checks every signature against the DNA contents, returning the signers
+    pub fn verify_signatures(&self) -> Result<Vec<PeerId>, DnaError> {
+        let payload = self.signing_payload()?;
+        self.signatures()?
+            .iter()
+            .map(|s| match s.public_key.verify(&payload, &s.signature) {
+                true => Ok(s.publisher()),
+                false => Err(DnaError::InvalidSignature(s.publisher())),
+            })
+            .collect()
+    }
+}
+
+/// Set of publisher keys whose DNA may be spawned on this node
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TrustStore {
+    publishers: BTreeSet<PeerId>,
+    #[serde(default)]
+    allow_unsigned: bool, // Accept DNA without any signature (development only)
+}
+
+impl TrustStore {
+    /// Creates an empty trust store that rejects all DNA
+    pub fn new() -> Self {
+        TrustStore::default()
+    }
+
+    /// Loads a trust store from a JSON file
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, DnaError> {
+        let data = std::fs::read(path)?;
+        Ok(serde_json::from_slice(&data)?)
+    }
+
+    /// Loads a trust store if a path is configured, otherwise returns an empty one
+    pub fn load_optional(path: Option<&str>) -> Result<Self, DnaError> {
+        path.map_or_else(|| Ok(TrustStore::new()), TrustStore::load)
+    }
+
+    /// Writes the trust store as JSON
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), DnaError> {
+        std::fs::write(path, serde_json::to_vec_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Accepts DNA signed by the given publisher
+    pub fn add_publisher(&mut self, publisher: PeerId) {
+        self.publishers.insert(publisher);
+    }
+
+    /// Stops accepting DNA signed by the given publisher
+    pub fn remove_publisher(&mut self, publisher: &PeerId) -> bool {
+        self.publishers.remove(publisher)
+    }
+
+    /// Whether the publisher is trusted
+    pub fn trusts(&self, publisher: &PeerId) -> bool {
+        self.publishers.contains(publisher)
+    }
+
+    /// Allows or forbids spawning unsigned DNA
+    pub fn set_allow_unsigned(&mut self, allow: bool) {
+        self.allow_unsigned = allow;
+    }
+
+    /// Verifies DNA before it is spawned, returning the trusted publisher.
+    ///
+    /// All signatures present must be valid, and at least one must come from a
+    /// trusted publisher. Unsigned DNA yields `None` only if explicitly allowed.
+    pub fn verify(&self, dna: &DnaFile) -> Result<Option<PeerId>, DnaError> {
+        let signers = dna.verify_signatures()?;
+        if signers.is_empty() {
+            return match self.allow_unsigned {
+                true => Ok(None),
+                false => Err(DnaError::Unsigned),
+            };
+        }
+        signers
+            .iter()
+            .find(|p| self.trusts(p))
+            .copied()
+            .map(Some)
+            .ok_or(DnaError::UntrustedPublisher(signers[0]))
+    }
+}
+
+/// Encodes signatures as: count u16, then (key len u16, key, sig len u16, sig)
+fn encode_signatures(signatures: &[DnaSignature]) -> Result<Vec<u8>, DnaError> {
+    let mut buf = Vec::new();
+    buf.extend_from_slice(&(signatures.len() as u16).to_be_bytes());
+    for s in signatures {
+        let key = s.public_key.encode_protobuf();
+        for field in [key.as_slice(), s.signature.as_slice()] {
+            let len = u16::try_from(field.len())
+                .map_err(|_| DnaError::InvalidKey("signature field too long".to_string()))?;
+            buf.extend_from_slice(&len.to_be_bytes());
+            buf.extend_from_slice(field);
+        }
+    }
+    Ok(buf)
+}
+
+fn decode_signatures(data: &[u8]) -> Result<Vec<DnaSignature>, DnaError> {
+    let mut rest = data;
+    let count = u16::from_be_bytes(take(&mut rest, 2)?.try_into().unwrap());
+    let mut signatures = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let public_key = PublicKey::try_decode_protobuf(take_field(&mut rest)?)
+            .map_err(|e| DnaError::InvalidKey(e.to_string()))?;
+        let signature = take_field(&mut rest)?.to_vec();
+        signatures.push(DnaSignature { public_key, signature });
+    }
+    if !rest.is_empty() {
+        return Err(DnaError::MalformedSignatures);
+    }
+    Ok(signatures)
+}
+
+fn take<'a>(rest: &mut &'a [u8], len: usize) -> Result<&'a [u8], DnaError> {
+    if rest.len() < len {
+        return Err(DnaError::MalformedSignatures);
+    }
+    let (head, tail) = rest.split_at(len);
+    *rest = tail;
+    Ok(head)
+}
+
+fn take_field<'a>(rest: &mut &'a [u8]) -> Result<&'a [u8], DnaError> {
+    let len = u16::from_be_bytes(take(rest, 2)?.try_into().unwrap());
+    take(rest, len as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> DnaFile {
+        let mut dna = DnaFile::new("did:maple:agent:1234");
+        dna.set_section(SectionKind::Config, br#"{"name":"a","role":"r"}"#.to_vec());
+        dna
+    }
+
+    #[test]
+    fn test_signed_dna_verifies_against_trust_store() {
+        let publisher = Keypair::generate_ed25519();
+        let mut dna = sample();
+        dna.sign(&publisher).unwrap();
+        let dna = DnaFile::from_bytes(&dna.to_bytes().unwrap()).unwrap();
+
+        let mut trust = TrustStore::new();
+        assert!(matches!(trust.verify(&dna), Err(DnaError::UntrustedPublisher(_))));
+        trust.add_publisher(publisher.public().to_peer_id());
+        assert_eq!(trust.verify(&dna).unwrap(), Some(publisher.public().to_peer_id()));
+    }
+
+    #[test]
+    fn test_tampered_or_unsigned_dna_is_rejected() {
+        let publisher = Keypair::generate_ed25519();
+        let mut trust = TrustStore::new();
+        trust.add_publisher(publisher.public().to_peer_id());
+        assert!(matches!(trust.verify(&sample()), Err(DnaError::Unsigned)));
+
+        let mut dna = sample();
+        dna.sign(&publisher).unwrap();
+        dna.set_section(SectionKind::State, vec![0xde, 0xad]);
+        assert!(matches!(trust.verify(&dna), Err(DnaError::InvalidSignature(_))));
+    }
+}
@@ -1,12 +1,18 @@
 // Secure RESTful API for the MAPLE ecosystem
 // © 2025 Finalverse Inc. All rights reserved.
 
-use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
-use maple_agents::Agent;
-use maple_runtime::{Runtime, RuntimeConfig, RuntimeMode};
+use jsonwebtoken::{decode, DecodingKey, Validation};
+use maple_agents::{AgentControl, DnaError, DnaFile};
+use maple_runtime::{Runtime, RuntimeConfig, RuntimeMode, SupervisionError};
+use maple_agents::RequestError;
+use maple_ual::{Mode, SchemaRegistry, UalMessage, ValidationError};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::error::Error;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio_stream::wrappers::BroadcastStream;
+use tokio_stream::StreamExt;
 use warp::Filter;
 
 /// Configuration for the API
@@ -14,6 +20,7 @@ use warp::Filter;
 pub struct ApiConfig {
     bind_addr: String, // e.g., "0.0.0.0:8080"
     secret_key: String, // Secret for JWT signing
+    trust_store_path: Option<String>, // Publishers whose .map DNA may be spawned
 }
 
 /// JWT claims for access key authentication
@@ -37,6 +44,9 @@ impl ApiServer {
             mode: RuntimeMode::Enterprise, // API runs in enterprise mode
             map_listen_addr: "/ip4/0.0.0.0/tcp/0".to_string(),
             db_path: "maple_api_db".to_string(),
+            trust_store_path: api_config.trust_store_path.clone(),
+            map_key_file: None,
+            remote_peers: None,
         };
         let runtime = Runtime::new(runtime_config).await?;
 
@@ -51,51 +61,209 @@ impl ApiServer {
     /// Starts the API server
     pub async fn start(self, config: ApiConfig) {
         tracing_subscriber::fmt::init();
+        let server = Arc::new(self);
+        let secret_key = Arc::new(config.secret_key);
 
+        let auth_server = server.clone();
         let auth_filter = warp::any()
             .and(warp::header::<String>("authorization"))
             .and_then(move |auth: String| {
-                let server = self.clone();
+                let server = auth_server.clone();
+                let secret_key = secret_key.clone();
                 async move {
                     let token = auth.trim();
-                    let key = server.keys.get(token).ok_or("Invalid API key")?;
+                    let key = server.keys.get(token).ok_or_else(|| reject("Invalid API key"))?;
                     let claims = decode::<Claims>(
                         token,
-                        &DecodingKey::from_secret(config.secret_key.as_ref()),
+                        &DecodingKey::from_secret(secret_key.as_bytes()),
                         &Validation::default(),
                     )
-                        .map_err(|_| "Invalid token")?;
+                        .map_err(|_| reject("Invalid token"))?;
                     Ok::<(String, String), warp::Rejection>((claims.claims.sub, key.clone()))
                 }
-                    .map_err(warp::reject::custom)
             });
 
+        let spawn_server = server.clone();
         let spawn_agent = warp::post()
             .and(warp::path("agents"))
             .and(warp::path("spawn"))
             .and(auth_filter.clone())
             .and(warp::body::bytes())
-            .and_then(move |(sub, tier), body: bytes::Bytes| {
-                let server = self.clone();
+            .and_then(move |(sub, tier): (String, String), body: bytes::Bytes| {
+                let server = spawn_server.clone();
                 async move {
                     // Limit free tier to basic agents
                     if tier == "free" && body.len() > 1024 {
-                        return Err(warp::reject::custom("Free tier limited to small agents"));
+                        return Err(reject("Free tier limited to small agents"));
                     }
-                    let agent = Agent::from_map_file(&format!("temp_{}.map", sub)).await?;
-                    tokio::fs::write(format!("temp_{}.map", sub), &body).await?;
-                    let did = agent.did.clone();
-                    server.runtime.spawn_agent(did.clone()).await?;
-                    Ok::<warp::reply::Json, warp::Rejection>(warp::reply::json(&serde_json::json!({"did": did})))
+                    // Reject malformed or unsigned DNA before it reaches the runtime
+                    let dna = match DnaFile::from_bytes(&body) {
+                        Ok(dna) => dna,
+                        Err(e) => return spawn_error(e.into()),
+                    };
+                    let did = match server.runtime.spawn_dna(dna).await {
+                        Ok(did) => did,
+                        Err(e) => return spawn_error(e),
+                    };
+                    tracing::info!("{} spawned agent {}", sub, did);
+                    Ok(warp::reply::with_status(
+                        warp::reply::json(&serde_json::json!({"did": did})),
+                        warp::http::StatusCode::OK,
+                    ))
                 }
-                    .map_err(warp::reject::custom)
             });
 
-        let routes = spawn_agent.with(warp::log("maple_api"));
+        let list_server = server.clone();
+        let list_agents = warp::get()
+            .and(warp::path("agents"))
+            .and(warp::path::end())
+            .and(auth_filter.clone())
+            .and_then(move |(_sub, _tier): (String, String)| {
+                let server = list_server.clone();
+                async move {
+                    let agents: Vec<_> = server
+                        .runtime
+                        .list_agents()
+                        .await
+                        .map_err(reject)?
+                        .into_iter()
+                        .map(|(did, state)| serde_json::json!({"did": did, "state": state}))
+                        .collect();
+                    Ok::<warp::reply::Json, warp::Rejection>(warp::reply::json(&agents))
+                }
+            });
+
+        let control_server = server.clone();
+        let control_agent = warp::post()
+            .and(warp::path!("agents" / String / String))
+            .and(auth_filter.clone())
+            .and_then(move |did: String, command: String, (sub, _tier): (String, String)| {
+                let server = control_server.clone();
+                async move {
+                    let control = parse_control(&command).ok_or_else(|| reject("Unknown lifecycle command"))?;
+                    let from = server.runtime.control_agent(&did, control).await.map_err(reject)?;
+                    tracing::info!("{} moved agent {} from {:?} to {:?}", sub, did, from, control.target());
+                    Ok::<warp::reply::Json, warp::Rejection>(warp::reply::json(
+                        &serde_json::json!({"did": did, "from": from, "to": control.target()}),
+                    ))
+                }
+            });
+
+        // Server-sent stream of lifecycle transitions for dashboards
+        let events_server = server.clone();
+        let lifecycle_events = warp::get()
+            .and(warp::path!("agents" / "events"))
+            .and(auth_filter.clone())
+            .map(move |(_sub, _tier): (String, String)| {
+                let events = BroadcastStream::new(events_server.runtime.lifecycle_events())
+                    // A lagging subscriber skips the events it missed
+                    .filter_map(|event| event.ok())
+                    .map(|event| warp::sse::Event::default().event("lifecycle").json_data(&event));
+                warp::sse::reply(warp::sse::keep_alive().stream(events))
+            });
+
+        // Declared actions and their schema versions
+        let list_actions = warp::get()
+            .and(warp::path("actions"))
+            .and(warp::path::end())
+            .and(auth_filter.clone())
+            .map(|(_sub, _tier): (String, String)| warp::reply::json(&SchemaRegistry::global().actions()));
+
+        let request_action = warp::post()
+            .and(warp::path!("actions" / String))
+            .and(auth_filter.clone())
+            .and(warp::body::json())
+            .and_then(move |action: String, (sub, _tier): (String, String), body: serde_json::Value| {
+                let server = server.clone();
+                async move {
+                    let msg = match UalMessage::new(&action, Mode::Json).with_json_payload(&body) {
+                        Ok(msg) => msg,
+                        Err(e) => return action_error(e),
+                    };
+                    if let Err(e) = msg.validate() {
+                        return action_error(e.into());
+                    }
+                    let reply = match server.runtime.request_capable(msg, Duration::from_secs(30)).await {
+                        Ok(reply) => reply,
+                        Err(e) => return action_error(e),
+                    };
+                    tracing::info!("{} requested {}", sub, action);
+                    action_reply(&reply)
+                }
+            });
+
+        let routes = spawn_agent
+            .or(list_agents)
+            .or(lifecycle_events)
+            .or(control_agent)
+            .or(list_actions)
+            .or(request_action)
+            .with(warp::log("maple_api"));
         warp::serve(routes).run(([0, 0, 0, 0], 8080)).await;
     }
 }
 
+/// Rejection carrying the reason a request failed
+#[derive(Debug)]
+#[allow(dead_code)] // Read through Debug when warp reports the rejection
+struct ApiRejection(String);
+
+impl warp::reject::Reject for ApiRejection {}
+
+/// Turns any error into a warp rejection
+fn reject(e: impl std::fmt::Display) -> warp::Rejection {
+    warp::reject::custom(ApiRejection(e.to_string()))
+}
+
+/// Maps a lifecycle command in a request path to its control signal
+fn parse_control(command: &str) -> Option<AgentControl> {
+    match command {
+        "pause" => Some(AgentControl::Pause),
+        "resume" => Some(AgentControl::Run),
+        "drain" => Some(AgentControl::Drain),
+        "stop" => Some(AgentControl::Stop),
+        _ => None,
+    }
+}
+
+/// Answers a failed action request; schema violations are the caller's to fix, so they come back typed
+fn action_error(e: Box<dyn Error>) -> Result<warp::reply::WithStatus<warp::reply::Json>, warp::Rejection> {
+    match validation_error(e.as_ref()) {
+        Some(invalid) => Ok(warp::reply::with_status(
+            warp::reply::json(invalid),
+            warp::http::StatusCode::UNPROCESSABLE_ENTITY,
+        )),
+        None => Err(reject(e)),
+    }
+}
+
+/// Answers with the agent's decoded reply; one breaking its result schema is `422`, one that cannot be decoded a `500`
+fn action_reply(reply: &UalMessage) -> Result<warp::reply::WithStatus<warp::reply::Json>, warp::Rejection> {
+    match reply.decode::<serde_json::Value>() {
+        Ok(payload) => Ok(warp::reply::with_status(warp::reply::json(&payload), warp::http::StatusCode::OK)),
+        Err(e) => action_error(e),
+    }
+}
+
+/// Answers DNA the runtime refused, e.g., unsigned, with a malformed config or an unknown supervisor, with `400`
+fn spawn_error(e: Box<dyn Error>) -> Result<warp::reply::WithStatus<warp::reply::Json>, warp::Rejection> {
+    if e.is::<DnaError>() || e.is::<SupervisionError>() {
+        return Ok(warp::reply::with_status(
+            warp::reply::json(&serde_json::json!({"error": e.to_string()})),
+            warp::http::StatusCode::BAD_REQUEST,
+        ));
+    }
+    Err(reject(e))
+}
+
+/// Finds a schema violation raised locally or reported back by the agent
+fn validation_error<'a>(e: &'a (dyn Error + 'static)) -> Option<&'a ValidationError> {
+    match e.downcast_ref::<RequestError>() {
+        Some(RequestError::Invalid(invalid)) => Some(invalid),
+        _ => e.downcast_ref::<ValidationError>(),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -105,8 +273,43 @@ mod tests {
         let config = ApiConfig {
             bind_addr: "0.0.0.0:8080".to_string(),
             secret_key: "secret".to_string(),
+            trust_store_path: None,
         };
         let server = ApiServer::new(config).await;
         assert!(server.is_ok());
+        drop(server);
+        std::fs::remove_dir_all("maple_api_db").unwrap();
+    }
+
+    #[test]
+    fn test_refused_dna_is_a_client_error() {
+        use warp::Reply;
+        let refused = spawn_error(DnaError::Unsigned.into()).unwrap().into_response();
+        assert_eq!(refused.status(), warp::http::StatusCode::BAD_REQUEST);
+        let unknown = SupervisionError::UnknownSupervisor("missing".to_string());
+        assert_eq!(spawn_error(unknown.into()).unwrap().into_response().status(), warp::http::StatusCode::BAD_REQUEST);
+        assert!(spawn_error("runtime has shut down".into()).is_err());
+    }
+
+    #[test]
+    fn test_undecodable_reply_is_not_answered_as_empty() {
+        use maple_ual::ActionSchema;
+        use warp::Reply;
+        let schema = serde_json::json!({"type": "object", "required": ["x"]});
+        SchemaRegistry::global().register(ActionSchema::json("api-test.move.result", 1, &schema).unwrap());
+        let reply = |payload: serde_json::Value| {
+            UalMessage::new("api-test.move.result", Mode::Json)
+                .with_schema_version(1)
+                .with_json_payload(&payload)
+                .unwrap()
+        };
+        let ok = action_reply(&reply(serde_json::json!({"x": 1}))).unwrap().into_response();
+        assert_eq!(ok.status(), warp::http::StatusCode::OK);
+        let invalid = action_reply(&reply(serde_json::json!({"y": 1}))).unwrap().into_response();
+        assert_eq!(invalid.status(), warp::http::StatusCode::UNPROCESSABLE_ENTITY);
+        SchemaRegistry::global().unregister("api-test.move.result");
+
+        let opaque = UalMessage::new("api-test.move.result", Mode::ByteLevel).with_byte_payload(vec![0xff]);
+        assert!(action_reply(&opaque).is_err());
     }
 }
\ No newline at end of file
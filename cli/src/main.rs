@@ -2,11 +2,11 @@
 // © 2025 Finalverse Inc. All rights reserved.
 
 use clap::{Parser, Subcommand};
-use maple_agents::{Agent, AgentConfig};
+use maple_agents::{Agent, AgentConfig, Capability};
+use maple_map::DEFAULT_KEY_FILE;
 use maple_mrs::{Mrs, MrsConfig};
 use maple_runtime::{Runtime, RuntimeConfig, RuntimeMode};
 use std::error::Error;
-use tokio;
 
 #[derive(Parser)]
 #[command(name = "maple", about = "MAPLE CLI", version = "0.1.0")]
@@ -23,6 +23,9 @@ enum Commands {
         name: String,
         #[arg(short, long)]
         role: String,
+        /// UAL action the agent accepts; repeat for several
+        #[arg(short, long = "capability")]
+        capabilities: Vec<String>,
     },
     /// Registers an agent with MRS
     MrsRegister {
@@ -35,11 +38,34 @@ enum Commands {
         mode: String,
         #[arg(short, long, default_value = "1")]
         nodes: usize,
+        /// Node identity key file, created if missing
+        #[arg(short, long, default_value = DEFAULT_KEY_FILE)]
+        key_file: String,
     },
     /// Spawns an agent in the runtime
     RuntimeSpawn {
         #[arg(short, long)]
         did: String,
+        #[arg(short, long, default_value = DEFAULT_KEY_FILE)]
+        key_file: String,
+    },
+    /// Creates a node identity key file
+    IdentityCreate {
+        #[arg(short, long, default_value = DEFAULT_KEY_FILE)]
+        key_file: String,
+        /// Replace an existing key, changing the node's PeerId
+        #[arg(long)]
+        force: bool,
+    },
+    /// Shows the PeerId of a node identity key file
+    IdentityShow {
+        #[arg(short, long, default_value = DEFAULT_KEY_FILE)]
+        key_file: String,
+    },
+    /// Replaces a node identity with a new key, keeping the old one as <key-file>.prev
+    IdentityRotate {
+        #[arg(short, long, default_value = DEFAULT_KEY_FILE)]
+        key_file: String,
     },
 }
 
@@ -50,25 +76,24 @@ async fn main() -> Result<(), Box<dyn Error>> {
     let cli = Cli::parse();
 
     match cli.command {
-        Commands::AgentCreate { name, role } => {
-            let config = AgentConfig { name, role };
+        Commands::AgentCreate { name, role, capabilities } => {
+            let config = capabilities
+                .iter()
+                .fold(AgentConfig::new(&name, &role), |config, action| {
+                    config.with_capability(Capability::new(action))
+                });
             let agent = Agent::new(config.clone());
             agent.dump_to_map(&format!("{}.map", config.name)).await?;
             println!("Created agent: {}.map", config.name);
         }
         Commands::MrsRegister { name } => {
-            let map_config = maple_map::MapConfig {
-                listen_addr: "/ip4/0.0.0.0/tcp/0".to_string(),
-            };
+            let map_config = maple_map::MapConfig::new("/ip4/0.0.0.0/tcp/0");
             let mrs = Mrs::new(MrsConfig { map_config }).await?;
-            let config = AgentConfig {
-                name: name.clone(),
-                role: "default".to_string(),
-            };
+            let config = AgentConfig::new(&name, "default");
             let did = mrs.register_agent(config).await?;
             println!("Registered agent {} with DID: {}", name, did);
         }
-        Commands::RuntimeStart { mode, nodes } => {
+        Commands::RuntimeStart { mode, nodes, key_file } => {
             let runtime_mode = match mode.as_str() {
                 "distributed" => RuntimeMode::Distributed,
                 "enterprise" => RuntimeMode::Enterprise,
@@ -78,25 +103,64 @@ async fn main() -> Result<(), Box<dyn Error>> {
                 mode: runtime_mode,
                 map_listen_addr: "/ip4/0.0.0.0/tcp/0".to_string(),
                 db_path: "maple_cli_db".to_string(),
+                trust_store_path: None,
+                map_key_file: Some(key_file),
+                remote_peers: None,
             };
             let runtime = Runtime::new(config).await?;
+            print_lifecycle_events(&runtime);
             println!("Started runtime in {} mode with {} nodes", mode, nodes);
             tokio::signal::ctrl_c().await?;
             runtime.shutdown().await?;
         }
-        Commands::RuntimeSpawn { did } => {
+        Commands::RuntimeSpawn { did, key_file } => {
             let config = RuntimeConfig {
                 mode: RuntimeMode::Distributed, // Default for CLI simplicity
                 map_listen_addr: "/ip4/0.0.0.0/tcp/0".to_string(),
                 db_path: "maple_cli_db".to_string(),
+                trust_store_path: None,
+                map_key_file: Some(key_file),
+                remote_peers: None,
             };
             let runtime = Runtime::new(config).await?;
+            print_lifecycle_events(&runtime);
             runtime.spawn_agent(did.clone()).await?;
             println!("Spawned agent with DID: {}", did);
             tokio::signal::ctrl_c().await?;
             runtime.shutdown().await?;
         }
+        Commands::IdentityCreate { key_file, force } => {
+            let key = maple_map::create_key(&key_file, force)?;
+            println!("Created node identity {} in {}", key.public().to_peer_id(), key_file);
+        }
+        Commands::IdentityShow { key_file } => {
+            let key = maple_map::load_key(&key_file)?;
+            println!("{}", key.public().to_peer_id());
+        }
+        Commands::IdentityRotate { key_file } => {
+            let key = maple_map::rotate_key(&key_file)?;
+            println!("Rotated node identity to {} (previous key in {}.prev)", key.public().to_peer_id(), key_file);
+        }
     }
 
     Ok(())
-}
\ No newline at end of file
+}
+
+/// Prints agent lifecycle transitions as the runtime reports them
+fn print_lifecycle_events(runtime: &Runtime) {
+    let mut events = runtime.lifecycle_events();
+    tokio::spawn(async move {
+        loop {
+            match events.recv().await {
+                Ok(event) => match event.from {
+                    Some(from) => println!("Agent {}: {:?} -> {:?}", event.did, from, event.to),
+                    None => println!("Agent {}: {:?}", event.did, event.to),
+                },
+                Err(tokio::sync::broadcast::error::RecvError::Lagged(missed)) => {
+                    println!("Missed {} lifecycle events", missed)
+                }
+                Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    });
+}
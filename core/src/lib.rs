@@ -4,8 +4,6 @@
 use serde::{Deserialize, Serialize};
 use tokio::sync::mpsc;
 
-mod error;
-
 pub use error::CoreError;
 
 /// Represents the Maple Core instance managing agents and resources
@@ -36,6 +34,11 @@ impl MapleCore {
         MapleCore { agent_channel: tx }
     }
 
+    /// Creates a Maple Core whose agent commands are carried out by the receiver of `commands`, e.g. a runtime
+    pub fn connect(commands: mpsc::Sender<AgentCommand>) -> Self {
+        MapleCore { agent_channel: commands }
+    }
+
     /// Sends a command to spawn an agent
     pub async fn spawn_agent(&self, did: String) -> Result<(), CoreError> {
         self.agent_channel
@@ -44,6 +47,14 @@ impl MapleCore {
             .map_err(|e| CoreError::ChannelError(e.to_string()))
     }
 
+    /// Sends a command to terminate an agent
+    pub async fn terminate_agent(&self, did: String) -> Result<(), CoreError> {
+        self.agent_channel
+            .send(AgentCommand::Terminate(did))
+            .await
+            .map_err(|e| CoreError::ChannelError(e.to_string()))
+    }
+
     /// Monitors agent health (placeholder)
     pub fn monitor_agents(&self) {
         // TODO: Implement health checks
@@ -51,6 +62,12 @@ impl MapleCore {
     }
 }
 
+impl Default for MapleCore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 /// Error handling for core operations
 pub mod error {
     #[derive(Debug)]
@@ -68,4 +85,12 @@ mod tests {
         let core = MapleCore::new();
         assert!(core.spawn_agent("did:maple:agent:1234".to_string()).await.is_ok());
     }
+
+    #[tokio::test]
+    async fn test_connected_core_forwards_commands() {
+        let (tx, mut rx) = mpsc::channel(1);
+        let core = MapleCore::connect(tx);
+        core.terminate_agent("did:maple:agent:1234".to_string()).await.unwrap();
+        assert!(matches!(rx.recv().await, Some(AgentCommand::Terminate(did)) if did == "did:maple:agent:1234"));
+    }
 }
\ No newline at end of file
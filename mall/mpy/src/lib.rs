@@ -1,22 +1,28 @@
 // Python agent runtime for MALL in MAPLE
 // © 2025 Finalverse Inc. All rights reserved.
 
+// This pyo3 version's #[pymethods] expansion defines impls inside a const block
+#![allow(non_local_definitions)]
+
 use maple_ual::{UalMessage, Mode};
 use pyo3::prelude::*;
 use std::error::Error;
+use std::time::Duration;
 
 /// Represents a Python-based agent
 #[pyclass]
-#[derive(Clone)]
+#[derive(Debug, Clone)]
 pub struct PythonAgent {
     name: String,
+    handler: Option<PyObject>, // Python object whose `process` handles messages instead of the built-in one
 }
 
 #[pymethods]
 impl PythonAgent {
+    /// Creates a new Python agent instance
     #[new]
-    fn new(name: String) -> Self {
-        PythonAgent { name }
+    pub fn new(name: String) -> Self {
+        PythonAgent { name, handler: None }
     }
 
     /// Processes a UAL message (Python method)
@@ -26,25 +32,44 @@ impl PythonAgent {
 }
 
 impl PythonAgent {
-    /// Creates a new Python agent instance
-    pub fn new(name: String) -> Self {
-        PythonAgent { name }
+    /// Hands messages to a Python object's `process(payload)` method, e.g., an instance of a user-defined class
+    pub fn with_handler(mut self, handler: PyObject) -> Self {
+        self.handler = Some(handler);
+        self
     }
 
     /// Processes a UAL message asynchronously
     pub async fn process_message(&self, msg: UalMessage) -> Result<String, Box<dyn Error>> {
+        let agent = self.clone();
+        // Python runs on a blocking thread so a slow `process` neither stalls the tokio worker nor a timeout
+        tokio::task::spawn_blocking(move || agent.process_blocking(&msg))
+            .await?
+            .map_err(|e| e as Box<dyn Error>)
+    }
+
+    fn process_blocking(&self, msg: &UalMessage) -> Result<String, Box<dyn Error + Send + Sync>> {
         Python::with_gil(|py| {
-            let agent = PyCell::new(py, self.clone())?;
-            let payload = match msg.mode {
-                Mode::Json => String::from_utf8(msg.payload)?,
+            let payload = match msg.mode() {
+                Mode::Json => String::from_utf8(msg.payload().to_vec())?,
                 _ => return Err("Only JSON mode supported currently".into()),
             };
-            let result = agent
-                .borrow()
-                .call_method1(py, "process", (payload,))?;
+            let result = match &self.handler {
+                Some(handler) => handler.call_method1(py, "process", (payload,))?,
+                None => Py::new(py, self.clone())?.call_method1(py, "process", (payload,))?,
+            };
             result.extract::<String>(py).map_err(Into::into)
         })
     }
+
+    /// Processes a UAL message and returns a correlated JSON response, failing after `timeout`.
+    /// The Python call itself cannot be interrupted and finishes in the background.
+    pub async fn request(&self, msg: UalMessage, timeout: Duration) -> Result<UalMessage, Box<dyn Error>> {
+        let reply = msg.reply(&format!("{}.result", msg.action()), Mode::Json);
+        let output = tokio::time::timeout(timeout, self.process_message(msg))
+            .await
+            .map_err(|_| "Python agent request timed out")??;
+        reply.with_json_payload(&serde_json::json!({ "agent": self.name, "output": output }))
+    }
 }
 
 #[cfg(test)]
@@ -60,4 +85,27 @@ mod tests {
         let result = agent.process_message(msg).await.unwrap();
         assert!(result.contains("test-agent"));
     }
+
+    #[tokio::test]
+    async fn test_slow_python_agent_times_out() {
+        let handler = Python::with_gil(|py| -> PyResult<PyObject> {
+            let module = PyModule::from_code(
+                py,
+                "import time\nclass Slow:\n    def process(self, payload):\n        time.sleep(2)\n        return payload\n",
+                "slow.py",
+                "slow",
+            )?;
+            Ok(module.getattr("Slow")?.call0()?.into())
+        })
+        .unwrap();
+        let agent = PythonAgent::new("slow-agent".to_string()).with_handler(handler);
+        let msg = UalMessage::new("train", Mode::Json)
+            .with_json_payload(&serde_json::json!({"task": "test"}))
+            .unwrap();
+
+        // The single-threaded test runtime would only see the deadline after `process` returned
+        let started = std::time::Instant::now();
+        assert!(agent.request(msg, Duration::from_millis(100)).await.is_err());
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
 }
\ No newline at end of file
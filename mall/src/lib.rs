@@ -5,8 +5,12 @@ use maple_mpy::PythonAgent;
 use maple_ual::{UalMessage, Mode};
 use serde::{Deserialize, Serialize};
 use std::error::Error;
+use std::time::Duration;
 use tokio::sync::mpsc;
 
+/// How long a training step waits for each agent's answer
+const TRAIN_STEP_TIMEOUT: Duration = Duration::from_secs(30);
+
 /// Configuration for the MALL
 #[derive(Debug, Serialize, Deserialize)]
 pub struct MallConfig {
@@ -16,11 +20,10 @@ pub struct MallConfig {
 
 /// Learning Lab instance
 pub struct Mall {
-    agents: Vec<PythonAgent>,
     command_tx: mpsc::Sender<MallCommand>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug)]
 pub enum MallCommand {
     AddAgent(PythonAgent),
     Train(String, u32), // Task, iterations
@@ -28,11 +31,11 @@ pub enum MallCommand {
 
 impl Mall {
     /// Initializes a new MALL instance
-    pub async fn new(config: MallConfig) -> Result<Self, Box<dyn Error>> {
+    pub async fn new(_config: MallConfig) -> Result<Self, Box<dyn Error>> {
         let (command_tx, mut command_rx) = mpsc::channel(100);
-        let mut agents = Vec::new();
 
         tokio::spawn(async move {
+            let mut agents = Vec::new();
             while let Some(cmd) = command_rx.recv().await {
                 match cmd {
                     MallCommand::AddAgent(agent) => {
@@ -46,14 +49,17 @@ impl Mall {
                             let msg = UalMessage::new("train", Mode::Json)
                                 .with_json_payload(&serde_json::json!({"task": task.clone()}))
                                 .unwrap();
-                            agent.process_message(msg).await.unwrap();
+                            match agent.request(msg, TRAIN_STEP_TIMEOUT).await {
+                                Ok(reply) => println!("Training reply: {:?}", reply.decode::<serde_json::Value>()),
+                                Err(e) => eprintln!("Training step failed: {}", e),
+                            }
                         }
                     }
                 }
             }
         });
 
-        Ok(Mall { agents, command_tx })
+        Ok(Mall { command_tx })
     }
 
     /// Adds an agent to the learning lab
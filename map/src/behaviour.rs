@@ -0,0 +1,126 @@
+// libp2p behaviours composed into a MAP node
+// © 2025 Finalverse Inc. All rights reserved.
+
+use crate::codec::{UalFrameCodec, DIRECT_PROTOCOL};
+use crate::topic;
+use crate::transfer::{BlobCodec, BLOB_PROTOCOL};
+use libp2p::swarm::behaviour::toggle::Toggle;
+use libp2p::swarm::NetworkBehaviour;
+use crate::MapConfig;
+use libp2p::{allow_block_list, autonat, connection_limits, dcutr, gossipsub, identify, identity, kad, mdns, relay, request_response, StreamProtocol};
+use std::error::Error;
+use std::time::Duration;
+
+/// Topic every node joins for network-wide broadcasts
+pub const BROADCAST_TOPIC: &str = "maple/broadcast";
+
+/// Largest broadcast message, as an encoded UAL frame
+pub const MAX_BROADCAST_LEN: usize = 1024 * 1024;
+
+/// Kademlia protocol, kept apart from the public IPFS DHT
+pub const KAD_PROTOCOL: StreamProtocol = StreamProtocol::new("/maple/kad/1.0.0");
+
+/// Protocol version peers exchange over identify
+const IDENTIFY_PROTOCOL: &str = "/maple/id/1.0.0";
+
+/// How often the routing table is refreshed by a bootstrap query
+pub const BOOTSTRAP_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// How long a direct send waits for the peer's acknowledgement
+pub const DIRECT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// AutoNAT settings; tests probe soon after start rather than waiting out the default boot delay
+fn autonat_config(config: &MapConfig) -> autonat::Config {
+    let defaults = autonat::Config {
+        only_global_ips: !config.private_nat_probes,
+        ..autonat::Config::default()
+    };
+    if !cfg!(test) {
+        return defaults;
+    }
+    autonat::Config {
+        boot_delay: Duration::from_millis(100),
+        retry_interval: Duration::from_millis(500),
+        throttle_server_period: Duration::ZERO,
+        ..defaults
+    }
+}
+
+#[derive(NetworkBehaviour)]
+pub(crate) struct MapBehaviour {
+    pub limits: connection_limits::Behaviour, // Caps established connections, overall and per peer
+    pub allowed: Toggle<allow_block_list::Behaviour<allow_block_list::AllowedPeers>>, // Only when an allow list is configured
+    pub blocked: allow_block_list::Behaviour<allow_block_list::BlockedPeers>, // Denied and banned peers
+    pub mdns: Toggle<mdns::tokio::Behaviour>, // LAN peer discovery
+    pub kademlia: kad::Behaviour<kad::store::MemoryStore>, // Discovery beyond the LAN and provider records
+    pub identify: identify::Behaviour, // Learns the listen addresses peers want to be reached on
+    pub autonat: autonat::Behaviour, // Asks peers to dial back to tell whether this node is behind NAT
+    pub relay_client: relay::client::Behaviour, // Reserves slots on relays and dials through them
+    pub relay_server: Toggle<relay::Behaviour>, // Relays circuits for NATed peers
+    pub dcutr: dcutr::Behaviour, // Upgrades relayed connections to direct ones by hole punching
+    pub direct: request_response::Behaviour<UalFrameCodec>, // Point-to-point messages
+    pub blobs: request_response::Behaviour<BlobCodec>, // Chunks of shared files
+    pub gossipsub: gossipsub::Behaviour, // Broadcasts
+}
+
+impl MapBehaviour {
+    pub fn new(
+        key: &identity::Keypair,
+        relay_client: relay::client::Behaviour,
+        config: &MapConfig,
+    ) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let peer_id = key.public().to_peer_id();
+        let gossipsub_config = gossipsub::ConfigBuilder::default()
+            .max_transmit_size(MAX_BROADCAST_LEN)
+            .validation_mode(gossipsub::ValidationMode::Strict) // Only signed messages
+            .message_id_fn(topic::message_id)
+            .validate_messages() // Held until the topic's validator accepts them
+            .build()?;
+        let mut kad_config = kad::Config::new(KAD_PROTOCOL);
+        kad_config.set_periodic_bootstrap_interval(Some(BOOTSTRAP_INTERVAL));
+        let mut kademlia = kad::Behaviour::with_config(peer_id, kad::store::MemoryStore::new(peer_id), kad_config);
+        // Answer queries even without a confirmed public address, as LAN and test nodes never get one
+        kademlia.set_mode(Some(kad::Mode::Server));
+        let limits = connection_limits::ConnectionLimits::default()
+            .with_max_established(Some(config.max_connections))
+            .with_max_established_per_peer(Some(config.max_connections_per_peer));
+        let mdns = config
+            .mdns
+            .then(|| mdns::tokio::Behaviour::new(mdns::Config::default(), peer_id))
+            .transpose()?;
+        let mut gossipsub = gossipsub::Behaviour::new(gossipsub::MessageAuthenticity::Signed(key.clone()), gossipsub_config)?;
+        // Peers scoring below the graylist threshold have their gossip ignored
+        let mut score_params = gossipsub::PeerScoreParams {
+            ip_colocation_factor_weight: 0.0, // Many nodes often share a host or NAT
+            ..Default::default()
+        };
+        score_params.topics.insert(topic::ident(BROADCAST_TOPIC).hash(), topic::score_params());
+        gossipsub.with_peer_score(score_params, gossipsub::PeerScoreThresholds::default())?;
+        Ok(MapBehaviour {
+            limits: connection_limits::Behaviour::new(limits),
+            allowed: (!config.allow_peers.is_empty())
+                .then(allow_block_list::Behaviour::default)
+                .into(),
+            blocked: allow_block_list::Behaviour::default(),
+            mdns: mdns.into(),
+            kademlia,
+            identify: identify::Behaviour::new(identify::Config::new(IDENTIFY_PROTOCOL.to_string(), key.public())),
+            autonat: autonat::Behaviour::new(peer_id, autonat_config(config)),
+            relay_client,
+            relay_server: config
+                .relay_server
+                .then(|| relay::Behaviour::new(peer_id, relay::Config::default()))
+                .into(),
+            dcutr: dcutr::Behaviour::new(peer_id),
+            direct: request_response::Behaviour::new(
+                [(DIRECT_PROTOCOL, request_response::ProtocolSupport::Full)],
+                request_response::Config::default().with_request_timeout(DIRECT_TIMEOUT),
+            ),
+            blobs: request_response::Behaviour::new(
+                [(BLOB_PROTOCOL, request_response::ProtocolSupport::Full)],
+                request_response::Config::default().with_request_timeout(DIRECT_TIMEOUT),
+            ),
+            gossipsub,
+        })
+    }
+}
@@ -0,0 +1,76 @@
+// Request-response codec carrying whole UAL messages between MAP peers
+// © 2025 Finalverse Inc. All rights reserved.
+
+use async_trait::async_trait;
+use bytes::BytesMut;
+use futures::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use libp2p::request_response::Codec;
+use libp2p::StreamProtocol;
+use maple_ual::{FrameOptions, UalCodec, UalMessage};
+use std::io;
+use tokio_util::codec::Decoder;
+
+/// Protocol for direct messages between two peers
+pub const DIRECT_PROTOCOL: StreamProtocol = StreamProtocol::new("/maple/ual/1.0.0");
+
+/// Largest direct message accepted; larger blobs belong in a file transfer
+pub const MAX_DIRECT_MESSAGE_LEN: u64 = 64 * 1024 * 1024;
+
+/// Writes a message as UAL frames on its own stream; the response is an empty acknowledgement
+#[derive(Debug, Clone, Default)]
+pub(crate) struct UalFrameCodec;
+
+#[async_trait]
+impl Codec for UalFrameCodec {
+    type Protocol = StreamProtocol;
+    type Request = UalMessage;
+    type Response = ();
+
+    async fn read_request<T>(&mut self, _: &StreamProtocol, io: &mut T) -> io::Result<UalMessage>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        let mut buf = Vec::new();
+        io.take(MAX_DIRECT_MESSAGE_LEN).read_to_end(&mut buf).await?;
+        decode_message(&buf)
+    }
+
+    async fn read_response<T>(&mut self, _: &StreamProtocol, io: &mut T) -> io::Result<()>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        let mut buf = Vec::new();
+        io.take(1).read_to_end(&mut buf).await?;
+        Ok(())
+    }
+
+    async fn write_request<T>(&mut self, _: &StreamProtocol, io: &mut T, msg: UalMessage) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        let frames = msg
+            .to_frames(&FrameOptions::default())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        for frame in frames {
+            io.write_all(&frame).await?;
+        }
+        io.close().await
+    }
+
+    async fn write_response<T>(&mut self, _: &StreamProtocol, io: &mut T, _: ()) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        io.close().await
+    }
+}
+
+/// Decodes exactly one message, reassembling it if it was chunked
+pub(crate) fn decode_message(bytes: &[u8]) -> io::Result<UalMessage> {
+    let mut buf = BytesMut::from(bytes);
+    match UalCodec::default().decode(&mut buf) {
+        Ok(Some(msg)) if buf.is_empty() => Ok(msg),
+        Ok(_) => Err(io::Error::new(io::ErrorKind::InvalidData, "incomplete or trailing UAL frames")),
+        Err(e) => Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+    }
+}
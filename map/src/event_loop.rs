@@ -0,0 +1,652 @@
+// Swarm task driving a MAP node: runs commands from handles and surfaces inbound messages
+// © 2025 Finalverse Inc. All rights reserved.
+
+use crate::behaviour::{MapBehaviour, MapBehaviourEvent, BROADCAST_TOPIC};
+use crate::guard::PeerGuard;
+use crate::peer_store::{self, PeerStore};
+use crate::topic::{self, TopicValidator, TOPIC_CAPACITY};
+use crate::transfer::{self, ChunkRequest, ChunkResponse};
+use crate::{MapCommand, TopicReceiver};
+use futures::StreamExt;
+use libp2p::request_response::{self, OutboundRequestId, ResponseChannel};
+use libp2p::swarm::dial_opts::DialOpts;
+use libp2p::core::transport::ListenerId;
+use libp2p::swarm::SwarmEvent;
+use libp2p::gossipsub::{self, MessageAcceptance, MessageId, TopicHash};
+use libp2p::kad::{self, GetProvidersOk, QueryId, QueryResult};
+use libp2p::{autonat, dcutr, identify, mdns, relay, Multiaddr, PeerId, Swarm};
+use maple_ual::{negotiate, Mode, UalMessage};
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::sync::{broadcast, mpsc, oneshot};
+use tokio::time::Instant;
+
+/// How often due reconnections are dialled
+const RECONNECT_TICK: Duration = Duration::from_secs(1);
+
+/// How long to wait before asking a relay for a new reservation after losing one
+const RELAY_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+/// Reputation lost by a peer relaying a message its topic's validator rejected, or sending a malformed one
+const INVALID_MESSAGE_PENALTY: i32 = -10;
+
+/// Reputation lost for each message over the peer's rate limit
+const RATE_LIMIT_PENALTY: i32 = -5;
+
+/// DHT queries awaiting their final result
+enum PendingQuery {
+    StartProviding(oneshot::Sender<Result<(), String>>),
+    GetProviders(oneshot::Sender<Result<HashSet<PeerId>, String>>, HashSet<PeerId>), // Providers found so far
+}
+
+/// Hello sent to a peer, awaiting its answer
+struct PendingNegotiation {
+    peer: PeerId,
+    hello_id: String, // The answer carries it as its correlation ID
+    reply: oneshot::Sender<Result<Mode, String>>,
+}
+
+pub(crate) struct EventLoop {
+    swarm: Swarm<MapBehaviour>,
+    command_rx: mpsc::Receiver<MapCommand>,
+    incoming_tx: broadcast::Sender<(PeerId, UalMessage)>,
+    topics: HashMap<TopicHash, broadcast::Sender<(PeerId, UalMessage)>>, // Subscribed topics other than broadcast
+    validators: HashMap<TopicHash, TopicValidator>,
+    pending_sends: HashMap<OutboundRequestId, (oneshot::Sender<Result<(), String>>, Instant)>, // Awaiting the peer's acknowledgement
+    pending_queries: HashMap<QueryId, PendingQuery>,
+    peers: PeerStore,
+    redials: HashMap<PeerId, Instant>, // When to next try peers we lost or failed to reach
+    shared: HashMap<[u8; 32], PathBuf>, // Files served by content hash
+    pending_chunks: HashMap<OutboundRequestId, oneshot::Sender<Result<ChunkResponse, String>>>,
+    chunks_tx: mpsc::UnboundedSender<(ResponseChannel<ChunkResponse>, ChunkResponse)>, // Chunks read off disk, ready to send
+    chunks_rx: mpsc::UnboundedReceiver<(ResponseChannel<ChunkResponse>, ChunkResponse)>,
+    relay_listeners: HashMap<ListenerId, Multiaddr>, // Reservations by the circuit address they listen on
+    relay_retries: HashMap<Multiaddr, Instant>, // Lost reservations and when to renew them
+    guard: PeerGuard,
+    modes: Vec<Mode>, // Offered and accepted in mode negotiation, most preferred first
+    peer_modes: HashMap<PeerId, Mode>, // Outcome of the last negotiation with each peer
+    pending_negotiations: HashMap<OutboundRequestId, PendingNegotiation>,
+}
+
+impl EventLoop {
+    pub fn new(
+        swarm: Swarm<MapBehaviour>,
+        command_rx: mpsc::Receiver<MapCommand>,
+        incoming_tx: broadcast::Sender<(PeerId, UalMessage)>,
+        peers: PeerStore,
+        relays: Vec<Multiaddr>,
+        guard: PeerGuard,
+        modes: Vec<Mode>,
+    ) -> Self {
+        // Reconnect to the peers that served us well last time
+        let now = Instant::now();
+        let mut swarm = swarm;
+        let mut redials = HashMap::new();
+        for (peer_id, addrs) in peers.redial_candidates() {
+            for addr in addrs {
+                swarm.behaviour_mut().kademlia.add_address(&peer_id, addr);
+            }
+            redials.insert(peer_id, now);
+        }
+        for peer_id in peers.banned() {
+            swarm.behaviour_mut().blocked.block_peer(peer_id);
+        }
+        let (chunks_tx, chunks_rx) = mpsc::unbounded_channel();
+        let mut event_loop = EventLoop {
+            swarm,
+            command_rx,
+            incoming_tx,
+            topics: HashMap::new(),
+            validators: HashMap::new(),
+            pending_sends: HashMap::new(),
+            pending_queries: HashMap::new(),
+            peers,
+            redials,
+            shared: HashMap::new(),
+            pending_chunks: HashMap::new(),
+            chunks_tx,
+            chunks_rx,
+            relay_listeners: HashMap::new(),
+            relay_retries: HashMap::new(),
+            guard,
+            modes,
+            peer_modes: HashMap::new(),
+            pending_negotiations: HashMap::new(),
+        };
+        for circuit in relays {
+            event_loop.listen_via_relay(circuit);
+        }
+        event_loop
+    }
+
+    /// Runs until every `MapProtocol` handle is dropped
+    pub async fn run(mut self) {
+        let mut reconnect_timer = tokio::time::interval(RECONNECT_TICK);
+        loop {
+            tokio::select! {
+                _ = reconnect_timer.tick() => {
+                    self.redial_due();
+                    self.guard.prune();
+                    // Callers that gave up waiting for an answer
+                    self.pending_negotiations.retain(|_, pending| !pending.reply.is_closed());
+                }
+                Some((channel, chunk)) = self.chunks_rx.recv() => {
+                    // Fails only if the requester went away meanwhile
+                    let _ = self.swarm.behaviour_mut().blobs.send_response(channel, chunk);
+                }
+                event = self.swarm.select_next_some() => self.handle_event(event),
+                command = self.command_rx.recv() => match command {
+                    Some(command) => self.handle_command(command),
+                    None => return,
+                },
+            }
+        }
+    }
+
+    fn handle_command(&mut self, command: MapCommand) {
+        match command {
+            MapCommand::SendMessage(peer, msg, reply) => {
+                let request_id = self.swarm.behaviour_mut().direct.send_request(&peer, msg);
+                self.pending_sends.insert(request_id, (reply, Instant::now()));
+            }
+            MapCommand::Publish(topic, msg, reply) => {
+                let result = msg
+                    .to_frame()
+                    .map_err(|e| e.to_string())
+                    .and_then(|frame| {
+                        self.swarm
+                            .behaviour_mut()
+                            .gossipsub
+                            .publish(topic::ident(&topic), frame)
+                            .map(|_| ())
+                            .map_err(|e| e.to_string())
+                    });
+                let _ = reply.send(result);
+            }
+            MapCommand::Subscribe(topic, reply) => {
+                let _ = reply.send(self.subscribe(&topic));
+            }
+            MapCommand::Unsubscribe(topic, reply) => {
+                let _ = reply.send(self.unsubscribe(&topic));
+            }
+            MapCommand::SetValidator(topic, hook) => {
+                let hash = topic::ident(&topic).hash();
+                match hook {
+                    Some(topic::Hook(validator)) => self.validators.insert(hash, validator),
+                    None => self.validators.remove(&hash),
+                };
+            }
+            MapCommand::StartProviding(key, reply) => {
+                match self.swarm.behaviour_mut().kademlia.start_providing(key.into_bytes().into()) {
+                    Ok(query_id) => {
+                        self.pending_queries.insert(query_id, PendingQuery::StartProviding(reply));
+                    }
+                    Err(e) => {
+                        let _ = reply.send(Err(e.to_string()));
+                    }
+                }
+            }
+            MapCommand::StopProviding(key) => {
+                self.swarm.behaviour_mut().kademlia.stop_providing(&key.into_bytes().into());
+            }
+            MapCommand::GetProviders(key, reply) => {
+                let query_id = self.swarm.behaviour_mut().kademlia.get_providers(key.into_bytes().into());
+                self.pending_queries
+                    .insert(query_id, PendingQuery::GetProviders(reply, HashSet::new()));
+            }
+            MapCommand::Dial(addr, reply) => {
+                let _ = reply.send(self.swarm.dial(addr).map_err(|e| e.to_string()));
+            }
+            MapCommand::NatStatus(reply) => {
+                let _ = reply.send(self.swarm.behaviour().autonat.nat_status());
+            }
+            MapCommand::ListenAddrs(reply) => {
+                let _ = reply.send(self.swarm.listeners().cloned().collect());
+            }
+            MapCommand::KnownPeers(reply) => {
+                let _ = reply.send(self.peers.all());
+            }
+            MapCommand::KnownPeer(peer, reply) => {
+                let _ = reply.send(self.peers.get(&peer).cloned());
+            }
+            MapCommand::BanPeer(peer, duration) => {
+                self.peers.ban(&peer, duration);
+                self.swarm.behaviour_mut().blocked.block_peer(peer);
+                self.redials.remove(&peer);
+            }
+            MapCommand::UnbanPeer(peer) => {
+                self.peers.unban(&peer);
+                if !self.guard.denied().contains(&peer) {
+                    self.swarm.behaviour_mut().blocked.unblock_peer(peer);
+                }
+            }
+            MapCommand::ShareBlob(hash, path) => {
+                self.shared.insert(hash, path);
+            }
+            MapCommand::FetchChunk(peer, request, reply) => {
+                let request_id = self.swarm.behaviour_mut().blobs.send_request(&peer, request);
+                self.pending_chunks.insert(request_id, reply);
+            }
+            MapCommand::ConnectedPeers(reply) => {
+                let _ = reply.send(self.swarm.connected_peers().cloned().collect());
+            }
+            MapCommand::Negotiate(peer, reply) => {
+                let hello = negotiate::hello(&self.modes);
+                let hello_id = hello.id().to_string();
+                let request_id = self.swarm.behaviour_mut().direct.send_request(&peer, hello);
+                self.pending_negotiations
+                    .insert(request_id, PendingNegotiation { peer, hello_id, reply });
+            }
+            MapCommand::PeerMode(peer, reply) => {
+                let _ = reply.send(self.peer_modes.get(&peer).cloned());
+            }
+        }
+    }
+
+    fn handle_event(&mut self, event: SwarmEvent<MapBehaviourEvent>) {
+        match event {
+            SwarmEvent::Behaviour(MapBehaviourEvent::Mdns(mdns::Event::Discovered(peers))) => {
+                for (peer_id, addr) in peers {
+                    println!("Discovered peer: {:?}", peer_id);
+                    self.swarm.behaviour_mut().kademlia.add_address(&peer_id, addr.clone());
+                    self.peers.add_address(&peer_id, &addr);
+                    if self.swarm.is_connected(&peer_id) || !self.may_connect(&peer_id) {
+                        continue;
+                    }
+                    if let Err(e) = self.swarm.dial(addr) {
+                        eprintln!("Failed to dial {:?}: {}", peer_id, e);
+                    }
+                }
+            }
+            SwarmEvent::Behaviour(MapBehaviourEvent::Identify(identify::Event::Received { peer_id, info, .. })) => {
+                // Peers that dialled us are only reachable on the addresses they listen on
+                for addr in info.listen_addrs {
+                    self.peers.add_address(&peer_id, &addr);
+                    self.swarm.behaviour_mut().kademlia.add_address(&peer_id, addr);
+                }
+            }
+            SwarmEvent::Behaviour(MapBehaviourEvent::Autonat(autonat::Event::StatusChanged { new, .. })) => {
+                println!("NAT status: {:?}", new);
+            }
+            SwarmEvent::Behaviour(MapBehaviourEvent::RelayClient(relay::client::Event::ReservationReqAccepted {
+                relay_peer_id,
+                renewal: false,
+                ..
+            })) => println!("Reserved a circuit on relay {:?}", relay_peer_id),
+            SwarmEvent::Behaviour(MapBehaviourEvent::Dcutr(dcutr::Event { remote_peer_id, result })) => match result {
+                Ok(_) => println!("Upgraded relayed connection to {:?} to a direct one", remote_peer_id),
+                Err(e) => eprintln!("Hole punching to {:?} failed: {}", remote_peer_id, e),
+            },
+            SwarmEvent::Behaviour(MapBehaviourEvent::Kademlia(kad::Event::OutboundQueryProgressed {
+                id,
+                result,
+                step,
+                ..
+            })) => self.handle_query(id, result, step.last),
+            SwarmEvent::Behaviour(MapBehaviourEvent::Direct(event)) => self.handle_direct(event),
+            SwarmEvent::Behaviour(MapBehaviourEvent::Blobs(event)) => self.handle_blobs(event),
+            SwarmEvent::Behaviour(MapBehaviourEvent::Gossipsub(gossipsub::Event::Message {
+                propagation_source,
+                message_id,
+                message,
+            })) => self.handle_gossip(propagation_source, message_id, message),
+            SwarmEvent::ConnectionEstablished {
+                peer_id,
+                endpoint,
+                established_in,
+                ..
+            } => {
+                if endpoint.is_dialer() {
+                    self.peers.add_address(&peer_id, endpoint.get_remote_address());
+                }
+                self.peers.connected(&peer_id, established_in);
+                self.redials.remove(&peer_id);
+            }
+            SwarmEvent::ConnectionClosed {
+                peer_id,
+                num_established: 0,
+                cause,
+                ..
+            } => {
+                self.peers.disconnected(&peer_id);
+                self.guard.forget(&peer_id);
+                self.peer_modes.remove(&peer_id);
+                // Idle and deliberate closes carry no cause; only lost connections are re-established
+                if cause.is_some() {
+                    self.redials.insert(peer_id, Instant::now() + peer_store::backoff(1));
+                }
+            }
+            SwarmEvent::OutgoingConnectionError {
+                peer_id: Some(peer_id),
+                error,
+                ..
+            } => {
+                if self.swarm.is_connected(&peer_id) {
+                    return; // Another address of the peer worked
+                }
+                match self.peers.dial_failed(&peer_id) {
+                    Some(delay) => {
+                        self.redials.insert(peer_id, Instant::now() + delay);
+                    }
+                    None => {
+                        eprintln!("Giving up on {:?} after repeated failures: {}", peer_id, error);
+                        self.redials.remove(&peer_id);
+                    }
+                }
+            }
+            SwarmEvent::NewListenAddr { address, .. } => println!("Listening on {}", address),
+            SwarmEvent::ListenerClosed { listener_id, reason, .. } => {
+                if let Some(circuit) = self.relay_listeners.remove(&listener_id) {
+                    eprintln!("Lost reservation on {}: {:?}", circuit, reason);
+                    self.relay_retries.insert(circuit, Instant::now() + RELAY_RETRY_DELAY);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn subscribe(&mut self, topic: &str) -> Result<TopicReceiver, String> {
+        if topic == BROADCAST_TOPIC {
+            return Err(format!("{} is delivered on MapProtocol::incoming", BROADCAST_TOPIC));
+        }
+        let ident = topic::ident(topic);
+        let gossipsub = &mut self.swarm.behaviour_mut().gossipsub;
+        // Peers forwarding invalid messages lose score on every topic we join, not only on broadcasts
+        gossipsub.set_topic_params(ident.clone(), topic::score_params())?;
+        gossipsub.subscribe(&ident).map_err(|e| e.to_string())?;
+        let sender = self
+            .topics
+            .entry(ident.hash())
+            .or_insert_with(|| broadcast::channel(TOPIC_CAPACITY).0);
+        Ok(sender.subscribe())
+    }
+
+    fn unsubscribe(&mut self, topic: &str) -> Result<(), String> {
+        if topic == BROADCAST_TOPIC {
+            return Err(format!("{} cannot be left", BROADCAST_TOPIC));
+        }
+        let ident = topic::ident(topic);
+        self.topics.remove(&ident.hash());
+        self.swarm
+            .behaviour_mut()
+            .gossipsub
+            .unsubscribe(&ident)
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+
+    /// Validates a gossiped message, then delivers it and lets gossipsub forward it
+    fn handle_gossip(&mut self, propagation_source: PeerId, message_id: MessageId, message: gossipsub::Message) {
+        // Strict validation means the original publisher is always known
+        let publisher = message.source.unwrap_or(propagation_source);
+        // Limits apply to the publisher; peers merely forwarding its messages are left to gossipsub's scoring
+        if !self.guard.within_rate(&publisher) {
+            self.penalize(&publisher, RATE_LIMIT_PENALTY);
+            // Ignored rather than rejected: the message itself may be fine, only its publisher is too fast
+            let _ = self.swarm.behaviour_mut().gossipsub.report_message_validation_result(
+                &message_id,
+                &propagation_source,
+                MessageAcceptance::Ignore,
+            );
+            return;
+        }
+        let verdict = UalMessage::from_frame(&message.data)
+            .map_err(|e| e.to_string())
+            .and_then(|msg| match self.validators.get(&message.topic) {
+                Some(validator) => validator(&publisher, &msg).map(|_| msg),
+                None => Ok(msg),
+            });
+        let acceptance = match verdict {
+            Ok(msg) => {
+                if message.topic == topic::ident(BROADCAST_TOPIC).hash() {
+                    self.deliver(publisher, msg);
+                } else if let Some(sender) = self.topics.get(&message.topic) {
+                    let _ = sender.send((publisher, msg));
+                }
+                MessageAcceptance::Accept
+            }
+            Err(e) => {
+                eprintln!("Rejected message on {} from {:?}: {}", message.topic, publisher, e);
+                self.penalize(&propagation_source, INVALID_MESSAGE_PENALTY);
+                MessageAcceptance::Reject
+            }
+        };
+        // Only fails if the message already left the cache, when there is nothing left to forward
+        let _ = self
+            .swarm
+            .behaviour_mut()
+            .gossipsub
+            .report_message_validation_result(&message_id, &propagation_source, acceptance);
+    }
+
+    fn handle_query(&mut self, id: QueryId, result: QueryResult, last: bool) {
+        let Some(pending) = self.pending_queries.remove(&id) else {
+            return; // Bootstrap and other queries nobody waits on
+        };
+        match (pending, result) {
+            (PendingQuery::StartProviding(reply), QueryResult::StartProviding(result)) => {
+                let _ = reply.send(result.map(|_| ()).map_err(|e| e.to_string()));
+            }
+            (PendingQuery::GetProviders(reply, mut found), QueryResult::GetProviders(result)) => {
+                let error = match result {
+                    Ok(GetProvidersOk::FoundProviders { providers, .. }) => {
+                        found.extend(providers);
+                        None
+                    }
+                    Ok(GetProvidersOk::FinishedWithNoAdditionalRecord { .. }) => None,
+                    Err(e) => Some(e.to_string()),
+                };
+                if !last {
+                    self.pending_queries.insert(id, PendingQuery::GetProviders(reply, found));
+                    return;
+                }
+                // A timed-out lookup still answers with whatever providers it reached
+                let _ = reply.send(match error {
+                    Some(e) if found.is_empty() => Err(e),
+                    _ => Ok(found),
+                });
+            }
+            (pending, _) => {
+                self.pending_queries.insert(id, pending);
+            }
+        }
+    }
+
+    fn handle_direct(&mut self, event: request_response::Event<UalMessage, ()>) {
+        match event {
+            request_response::Event::Message { peer, message } => match message {
+                request_response::Message::Request { request, channel, .. } => {
+                    if !self.guard.within_rate(&peer) {
+                        // Dropping the channel fails the send on the peer's side
+                        self.penalize(&peer, RATE_LIMIT_PENALTY);
+                        return;
+                    }
+                    match request.action() {
+                        negotiate::HELLO_ACTION => self.answer_hello(peer, &request),
+                        negotiate::HELLO_ACK_ACTION => self.accept_hello_ack(peer, &request),
+                        _ => self.deliver(peer, request),
+                    }
+                    // Acknowledges receipt, not processing; replies travel as their own messages
+                    let _ = self.swarm.behaviour_mut().direct.send_response(channel, ());
+                }
+                request_response::Message::Response { request_id, .. } => {
+                    if let Some((reply, sent_at)) = self.pending_sends.remove(&request_id) {
+                        self.peers.observed_rtt(&peer, sent_at.elapsed());
+                        let _ = reply.send(Ok(()));
+                    }
+                }
+            },
+            request_response::Event::OutboundFailure { request_id, error, .. } => {
+                if let Some((reply, _)) = self.pending_sends.remove(&request_id) {
+                    let _ = reply.send(Err(error.to_string()));
+                }
+                if let Some(pending) = self.pending_negotiations.remove(&request_id) {
+                    let _ = pending.reply.send(Err(error.to_string()));
+                }
+            }
+            request_response::Event::InboundFailure { peer, error, .. } => match error {
+                // Requests over the rate limit, left unanswered on purpose
+                request_response::InboundFailure::ResponseOmission => {}
+                request_response::InboundFailure::Io(e) if e.kind() == std::io::ErrorKind::InvalidData => {
+                    eprintln!("Malformed message from {:?}: {}", peer, e);
+                    self.penalize(&peer, INVALID_MESSAGE_PENALTY);
+                }
+                error => eprintln!("Failed to receive message from {:?}: {}", peer, error),
+            },
+            request_response::Event::ResponseSent { .. } => {}
+        }
+    }
+
+    /// Answers a peer's hello with the first of its modes this node also supports
+    fn answer_hello(&mut self, peer: PeerId, hello: &UalMessage) {
+        match negotiate::answer(hello, &self.modes) {
+            Ok((ack, mode)) => {
+                if let Some(mode) = mode {
+                    self.peer_modes.insert(peer, mode);
+                }
+                // Delivery of the answer is not tracked; the initiator times out if it is lost
+                self.swarm.behaviour_mut().direct.send_request(&peer, ack);
+            }
+            Err(e) => {
+                eprintln!("Malformed mode negotiation from {:?}: {}", peer, e);
+                self.penalize(&peer, INVALID_MESSAGE_PENALTY);
+            }
+        }
+    }
+
+    /// Completes a negotiation this node started, if the answer is from the peer it asked
+    fn accept_hello_ack(&mut self, peer: PeerId, ack: &UalMessage) {
+        let Some(request_id) = self
+            .pending_negotiations
+            .iter()
+            .find(|(_, pending)| pending.peer == peer && ack.correlation_id() == Some(pending.hello_id.as_str()))
+            .map(|(request_id, _)| *request_id)
+        else {
+            return;
+        };
+        let Some(PendingNegotiation { reply, .. }) = self.pending_negotiations.remove(&request_id) else {
+            return;
+        };
+        let result = negotiate::accepted(ack).map_err(|e| e.to_string());
+        if let Ok(mode) = &result {
+            self.peer_modes.insert(peer, mode.clone());
+        }
+        let _ = reply.send(result);
+    }
+
+    fn handle_blobs(&mut self, event: request_response::Event<ChunkRequest, ChunkResponse>) {
+        match event {
+            request_response::Event::Message { peer, message } => match message {
+                request_response::Message::Request { request, channel, .. } => {
+                    // Turned away before touching the disk; honest downloaders slow down and retry
+                    if !self.guard.within_chunk_rate(&peer) {
+                        let _ = self.swarm.behaviour_mut().blobs.send_response(channel, ChunkResponse::Busy);
+                        return;
+                    }
+                    let Some(path) = self.shared.get(&request.hash).cloned() else {
+                        let _ = self.swarm.behaviour_mut().blobs.send_response(channel, ChunkResponse::NotFound);
+                        return;
+                    };
+                    // Read off the event loop so large chunks do not stall the swarm
+                    let chunks_tx = self.chunks_tx.clone();
+                    tokio::spawn(async move {
+                        let chunk = transfer::read_chunk(&path, request.offset, request.len)
+                            .await
+                            .unwrap_or_else(|e| {
+                                eprintln!("Failed to read {} for {:?}: {}", path.display(), peer, e);
+                                ChunkResponse::NotFound
+                            });
+                        let _ = chunks_tx.send((channel, chunk));
+                    });
+                }
+                request_response::Message::Response { request_id, response } => {
+                    if let Some(reply) = self.pending_chunks.remove(&request_id) {
+                        let _ = reply.send(Ok(response));
+                    }
+                }
+            },
+            request_response::Event::OutboundFailure { request_id, error, .. } => {
+                if let Some(reply) = self.pending_chunks.remove(&request_id) {
+                    let _ = reply.send(Err(error.to_string()));
+                }
+            }
+            request_response::Event::InboundFailure { peer, error, .. } => {
+                eprintln!("Failed to serve a chunk to {:?}: {}", peer, error);
+            }
+            request_response::Event::ResponseSent { .. } => {}
+        }
+    }
+
+    /// Asks a relay for a reservation by listening on a circuit through it
+    fn listen_via_relay(&mut self, circuit: Multiaddr) {
+        match self.swarm.listen_on(circuit.clone()) {
+            Ok(listener_id) => {
+                self.relay_listeners.insert(listener_id, circuit);
+            }
+            Err(e) => {
+                eprintln!("Failed to listen via {}: {}", circuit, e);
+                self.relay_retries.insert(circuit, Instant::now() + RELAY_RETRY_DELAY);
+            }
+        }
+    }
+
+    /// Lowers a peer's reputation, banning and disconnecting it if that sinks it below the ban threshold
+    fn penalize(&mut self, peer: &PeerId, delta: i32) {
+        if self.peers.adjust_reputation(peer, delta) {
+            eprintln!("Banned {:?} for {:?} after repeated abuse", peer, peer_store::BAN_DURATION);
+            self.swarm.behaviour_mut().blocked.block_peer(*peer);
+            self.redials.remove(peer);
+        }
+    }
+
+    /// Whether the allow and deny lists and any ban let us connect to a peer
+    fn may_connect(&self, peer: &PeerId) -> bool {
+        self.guard.permits(peer) && !self.peers.get(peer).is_some_and(|record| record.is_banned())
+    }
+
+    /// Dials peers whose reconnection delay has passed, renews lost relay reservations and lifts expired bans
+    fn redial_due(&mut self) {
+        let now = Instant::now();
+        for peer_id in self.peers.lift_expired_bans() {
+            if !self.guard.denied().contains(&peer_id) {
+                self.swarm.behaviour_mut().blocked.unblock_peer(peer_id);
+            }
+        }
+        let due: Vec<Multiaddr> = self
+            .relay_retries
+            .iter()
+            .filter(|(_, at)| **at <= now)
+            .map(|(circuit, _)| circuit.clone())
+            .collect();
+        for circuit in due {
+            self.relay_retries.remove(&circuit);
+            self.listen_via_relay(circuit);
+        }
+
+        let due: Vec<PeerId> = self
+            .redials
+            .iter()
+            .filter(|(_, at)| **at <= now)
+            .map(|(peer, _)| *peer)
+            .collect();
+        for peer_id in due {
+            self.redials.remove(&peer_id);
+            let Some(record) = self.peers.get(&peer_id) else {
+                continue;
+            };
+            if self.swarm.is_connected(&peer_id) || record.addresses.is_empty() || !self.may_connect(&peer_id) {
+                continue;
+            }
+            let opts = DialOpts::peer_id(peer_id).addresses(record.multiaddrs()).build();
+            if let Err(e) = self.swarm.dial(opts) {
+                eprintln!("Failed to redial {:?}: {}", peer_id, e);
+            }
+        }
+    }
+
+    fn deliver(&self, peer: PeerId, msg: UalMessage) {
+        // No subscribers just means nobody is listening yet
+        let _ = self.incoming_tx.send((peer, msg));
+    }
+}
@@ -0,0 +1,146 @@
+// Abuse protection for a MAP node: allow and deny lists and per-peer message and chunk rate limits
+// © 2025 Finalverse Inc. All rights reserved.
+
+use crate::MapConfig;
+use libp2p::PeerId;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::time::{Duration, Instant};
+
+/// Messages a peer may send per second unless configured otherwise
+pub const DEFAULT_MAX_MESSAGES_PER_SEC: u32 = 100;
+
+/// Blob chunks a peer may request per second unless configured otherwise (16 MiB/s at `CHUNK_SIZE`)
+pub const DEFAULT_MAX_CHUNK_REQUESTS_PER_SEC: u32 = 64;
+
+/// Token bucket refilled at the configured rate, holding at most one second's worth
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// Token buckets for one kind of request, one per peer
+struct RateLimiter {
+    rate: u32, // Requests per second; 0 disables the limit
+    buckets: HashMap<PeerId, Bucket>,
+}
+
+impl RateLimiter {
+    fn new(rate: u32) -> Self {
+        RateLimiter {
+            rate,
+            buckets: HashMap::new(),
+        }
+    }
+
+    fn take(&mut self, peer: &PeerId) -> bool {
+        if self.rate == 0 {
+            return true;
+        }
+        let now = Instant::now();
+        let rate = f64::from(self.rate);
+        let bucket = self.buckets.entry(*peer).or_insert(Bucket { tokens: rate, updated: now });
+        bucket.tokens = (bucket.tokens + now.duration_since(bucket.updated).as_secs_f64() * rate).min(rate);
+        bucket.updated = now;
+        if bucket.tokens < 1.0 {
+            return false;
+        }
+        bucket.tokens -= 1.0;
+        true
+    }
+
+    /// Drops buckets untouched for long enough to have refilled, as a fresh one would be full too
+    fn prune(&mut self) {
+        let now = Instant::now();
+        self.buckets.retain(|_, bucket| now.duration_since(bucket.updated) < Duration::from_secs(1));
+    }
+}
+
+/// Decides which peers may connect and how fast they may send
+pub(crate) struct PeerGuard {
+    allowed: Option<HashSet<PeerId>>, // None lets anyone connect who is not denied
+    denied: HashSet<PeerId>,
+    messages: RateLimiter, // Direct messages from a peer and gossip it published
+    chunks: RateLimiter, // Blob chunk requests
+}
+
+impl PeerGuard {
+    pub fn new(config: &MapConfig) -> Result<Self, Box<dyn Error>> {
+        let parse = |peers: &[String]| -> Result<HashSet<PeerId>, Box<dyn Error>> {
+            peers.iter().map(|peer| Ok(peer.parse()?)).collect()
+        };
+        Ok(PeerGuard {
+            allowed: (!config.allow_peers.is_empty()).then(|| parse(&config.allow_peers)).transpose()?,
+            denied: parse(&config.deny_peers)?,
+            messages: RateLimiter::new(config.max_messages_per_sec),
+            chunks: RateLimiter::new(config.max_chunk_requests_per_sec),
+        })
+    }
+
+    pub fn allowed(&self) -> Option<&HashSet<PeerId>> {
+        self.allowed.as_ref()
+    }
+
+    pub fn denied(&self) -> &HashSet<PeerId> {
+        &self.denied
+    }
+
+    /// Whether the lists let a peer connect at all; bans are tracked by the peer store
+    pub fn permits(&self, peer: &PeerId) -> bool {
+        !self.denied.contains(peer) && self.allowed.as_ref().is_none_or(|allowed| allowed.contains(peer))
+    }
+
+    /// Takes a token for one message from the peer, or returns false if it is sending too fast
+    pub fn within_rate(&mut self, peer: &PeerId) -> bool {
+        self.messages.take(peer)
+    }
+
+    /// Takes a token for one blob chunk request from the peer, or returns false if it asks too fast
+    pub fn within_chunk_rate(&mut self, peer: &PeerId) -> bool {
+        self.chunks.take(peer)
+    }
+
+    /// Drops a disconnected peer's buckets
+    pub fn forget(&mut self, peer: &PeerId) {
+        self.messages.buckets.remove(peer);
+        self.chunks.buckets.remove(peer);
+    }
+
+    /// Drops idle buckets, including those of gossip publishers we are not connected to
+    pub fn prune(&mut self) {
+        self.messages.prune();
+        self.chunks.prune();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lists_and_rate_limit() {
+        let (friend, foe, stranger) = (PeerId::random(), PeerId::random(), PeerId::random());
+        let config = MapConfig::new("/ip4/127.0.0.1/tcp/0")
+            .with_allowed_peer(&friend.to_string())
+            .with_allowed_peer(&foe.to_string())
+            .with_denied_peer(&foe.to_string())
+            .with_rate_limit(3)
+            .with_chunk_rate_limit(2);
+        let mut guard = PeerGuard::new(&config).unwrap();
+        assert!(guard.permits(&friend));
+        assert!(!guard.permits(&foe) && !guard.permits(&stranger));
+
+        assert!((0..3).all(|_| guard.within_rate(&friend)));
+        assert!(!guard.within_rate(&friend));
+        assert!(guard.within_rate(&stranger)); // Buckets are per peer
+        assert!(guard.within_chunk_rate(&friend) && guard.within_chunk_rate(&friend)); // and per kind of request
+        assert!(!guard.within_chunk_rate(&friend));
+        guard.prune();
+        assert_eq!(guard.messages.buckets.len(), 2); // Still in use
+
+        let unlimited = MapConfig::new("/ip4/127.0.0.1/tcp/0").with_rate_limit(0);
+        let mut guard = PeerGuard::new(&unlimited).unwrap();
+        assert!((0..1000).all(|_| guard.within_rate(&friend)) && guard.permits(&stranger));
+        assert!(PeerGuard::new(&MapConfig::new("/ip4/127.0.0.1/tcp/0").with_denied_peer("not a peer id")).is_err());
+    }
+}
@@ -1,132 +1,784 @@
 // Multi-Agent Protocol (MAP) for decentralized P2P messaging in MAPLE
 // © 2025 Finalverse Inc. All rights reserved.
 
-use libp2p::{
-    identity, mdns, noise, swarm::SwarmEvent, tcp, yamux, PeerId, Swarm, Transport,
-};
+use libp2p::multiaddr::Protocol;
+use libp2p::{noise, yamux};
+use maple_ual::{negotiate, Mode, UalMessage};
+use mapledb::MapleDb;
 use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 use std::error::Error;
-use tokio::sync::mpsc;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{broadcast, mpsc, oneshot};
+
+mod behaviour;
+mod codec;
+mod event_loop;
+mod guard;
+pub mod node_key;
+pub mod peer_store;
+pub mod topic;
+pub mod transfer;
+pub mod transport;
+
+pub use behaviour::{BOOTSTRAP_INTERVAL, BROADCAST_TOPIC, KAD_PROTOCOL, MAX_BROADCAST_LEN};
+pub use codec::{DIRECT_PROTOCOL, MAX_DIRECT_MESSAGE_LEN};
+pub use guard::{DEFAULT_MAX_CHUNK_REQUESTS_PER_SEC, DEFAULT_MAX_MESSAGES_PER_SEC};
+pub use libp2p::autonat::NatStatus;
+pub use libp2p::identity::Keypair;
+pub use libp2p::{Multiaddr, PeerId};
+pub use peer_store::PeerRecord;
+pub use node_key::{create_key, load_key, load_or_create_key, rotate_key, DEFAULT_KEY_FILE};
+pub use topic::{mall_topic, role_topic, TopicValidator, REGISTRY_TOPIC};
+pub use transfer::{MapFileAnnouncement, TransferEvent, BLOB_PROTOCOL, MAP_FILE_ACTION};
+pub use transport::{MapTransport, ALL_TRANSPORTS};
+
+/// Inbound messages buffered per subscriber; slow subscribers see `Lagged`
+pub const INCOMING_CAPACITY: usize = 1024;
+
+/// Established connections allowed unless configured otherwise
+pub const DEFAULT_MAX_CONNECTIONS: u32 = 256;
+
+/// Established connections allowed to a single peer unless configured otherwise
+pub const DEFAULT_MAX_CONNECTIONS_PER_PEER: u32 = 2;
+
+/// Transfer events buffered per subscriber
+pub const TRANSFER_EVENT_CAPACITY: usize = 256;
+
+/// How long `MapProtocol::negotiate` waits for the peer's answer
+pub const NEGOTIATION_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long an idle connection stays open
+const IDLE_CONNECTION_TIMEOUT: Duration = Duration::from_secs(60);
 
 /// Configuration for the MAP Protocol
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MapConfig {
-    listen_addr: String, // e.g., "/ip4/0.0.0.0/tcp/0"
+    pub listen_addrs: Vec<String>, // e.g., ["/ip4/0.0.0.0/tcp/0", "/ip4/0.0.0.0/udp/0/quic-v1"]
+    #[serde(default = "default_transports")]
+    pub transports: Vec<MapTransport>, // Transports to listen and dial on
+    #[serde(default)]
+    pub bootstrap_peers: Vec<String>, // DHT entry points, e.g., "/ip4/203.0.113.7/tcp/4001/p2p/12D3KooW..."
+    #[serde(default)]
+    pub key_file: Option<String>, // Node identity, created on first run; None uses a fresh key every start
+    #[serde(default = "default_max_connections")]
+    pub max_connections: u32,
+    #[serde(default = "default_max_connections_per_peer")]
+    pub max_connections_per_peer: u32,
+    #[serde(default = "default_mdns")]
+    pub mdns: bool, // LAN discovery; pointless on cloud networks without multicast
+    #[serde(default)]
+    pub external_addrs: Vec<String>, // Publicly reachable addresses to announce, e.g., a relay server's
+    #[serde(default)]
+    pub relays: Vec<String>, // Relays to reserve a slot on so peers can reach us through NAT, e.g., ".../p2p/12D3KooW..."
+    #[serde(default)]
+    pub relay_server: bool, // Relay circuits for other nodes; needs a public address
+    #[serde(default)]
+    pub private_nat_probes: bool, // AutoNAT between peers on private addresses, e.g., nodes sharing a LAN
+    #[serde(default = "default_max_messages_per_sec")]
+    pub max_messages_per_sec: u32, // Per peer, direct and gossiped; 0 disables the limit
+    #[serde(default = "default_max_chunk_requests_per_sec")]
+    pub max_chunk_requests_per_sec: u32, // Per peer, blob chunks served; 0 disables the limit
+    #[serde(default)]
+    pub allow_peers: Vec<String>, // When set, only these peer IDs may connect
+    #[serde(default)]
+    pub deny_peers: Vec<String>, // Peer IDs never allowed to connect
+    #[serde(default = "default_modes")]
+    pub modes: Vec<Mode>, // Payload encodings offered and accepted in mode negotiation, most preferred first
+}
+
+fn default_modes() -> Vec<Mode> {
+    negotiate::DEFAULT_PREFERENCE.to_vec()
+}
+
+fn default_max_messages_per_sec() -> u32 {
+    DEFAULT_MAX_MESSAGES_PER_SEC
 }
 
-/// MAP Protocol instance managing P2P communication
+fn default_max_chunk_requests_per_sec() -> u32 {
+    DEFAULT_MAX_CHUNK_REQUESTS_PER_SEC
+}
+
+fn default_transports() -> Vec<MapTransport> {
+    ALL_TRANSPORTS.to_vec()
+}
+
+fn default_mdns() -> bool {
+    true
+}
+
+fn default_max_connections() -> u32 {
+    DEFAULT_MAX_CONNECTIONS
+}
+
+fn default_max_connections_per_peer() -> u32 {
+    DEFAULT_MAX_CONNECTIONS_PER_PEER
+}
+
+impl MapConfig {
+    /// Creates a configuration listening on one address, with every transport enabled
+    pub fn new(listen_addr: &str) -> Self {
+        MapConfig {
+            listen_addrs: vec![listen_addr.to_string()],
+            transports: default_transports(),
+            bootstrap_peers: Vec::new(),
+            key_file: None,
+            max_connections: DEFAULT_MAX_CONNECTIONS,
+            max_connections_per_peer: DEFAULT_MAX_CONNECTIONS_PER_PEER,
+            mdns: true,
+            external_addrs: Vec::new(),
+            relays: Vec::new(),
+            relay_server: false,
+            private_nat_probes: false,
+            max_messages_per_sec: DEFAULT_MAX_MESSAGES_PER_SEC,
+            max_chunk_requests_per_sec: DEFAULT_MAX_CHUNK_REQUESTS_PER_SEC,
+            allow_peers: Vec::new(),
+            deny_peers: Vec::new(),
+            modes: default_modes(),
+        }
+    }
+
+    /// Sets the payload encodings this node negotiates, most preferred first
+    pub fn with_modes(mut self, modes: &[Mode]) -> Self {
+        self.modes = modes.to_vec();
+        self
+    }
+
+    /// Limits how many messages each peer may send per second; peers over the limit lose reputation
+    pub fn with_rate_limit(mut self, max_messages_per_sec: u32) -> Self {
+        self.max_messages_per_sec = max_messages_per_sec;
+        self
+    }
+
+    /// Limits how many blob chunks each peer may request per second; faster requests are told to retry
+    pub fn with_chunk_rate_limit(mut self, max_chunk_requests_per_sec: u32) -> Self {
+        self.max_chunk_requests_per_sec = max_chunk_requests_per_sec;
+        self
+    }
+
+    /// Adds a peer to the allow list; once it has any entry, only listed peers may connect
+    pub fn with_allowed_peer(mut self, peer_id: &str) -> Self {
+        self.allow_peers.push(peer_id.to_string());
+        self
+    }
+
+    /// Refuses connections from and to a peer
+    pub fn with_denied_peer(mut self, peer_id: &str) -> Self {
+        self.deny_peers.push(peer_id.to_string());
+        self
+    }
+
+    /// Also listens on another address, e.g., a QUIC or WebSocket one
+    pub fn with_listen_addr(mut self, addr: &str) -> Self {
+        self.listen_addrs.push(addr.to_string());
+        self
+    }
+
+    /// Restricts the node to some transports; addresses of other transports can be neither listened on nor dialled
+    pub fn with_transports(mut self, transports: &[MapTransport]) -> Self {
+        self.transports = transports.to_vec();
+        self
+    }
+
+    /// Turns LAN discovery over mDNS on or off
+    pub fn with_mdns(mut self, enabled: bool) -> Self {
+        self.mdns = enabled;
+        self
+    }
+
+    /// Announces an address peers can reach this node on, as seen from outside any NAT
+    pub fn with_external_addr(mut self, addr: &str) -> Self {
+        self.external_addrs.push(addr.to_string());
+        self
+    }
+
+    /// Listens through a relay so peers can reach this node behind NAT; the address must end in `/p2p/<peer id>`
+    pub fn with_relay(mut self, addr: &str) -> Self {
+        self.relays.push(addr.to_string());
+        self
+    }
+
+    /// Lets other nodes reserve circuits through this one
+    pub fn with_relay_server(mut self) -> Self {
+        self.relay_server = true;
+        self
+    }
+
+    /// Runs AutoNAT probes with peers on private addresses, which are otherwise neither asked nor answered
+    pub fn with_private_nat_probes(mut self) -> Self {
+        self.private_nat_probes = true;
+        self
+    }
+
+    /// Caps established connections overall and to any one peer
+    pub fn with_connection_limits(mut self, max_connections: u32, max_per_peer: u32) -> Self {
+        self.max_connections = max_connections;
+        self.max_connections_per_peer = max_per_peer;
+        self
+    }
+
+    /// Keeps the node's identity in a key file so its PeerId survives restarts
+    pub fn with_key_file(mut self, path: &str) -> Self {
+        self.key_file = Some(path.to_string());
+        self
+    }
+
+    /// Adds a peer to join the DHT through; the address must end in `/p2p/<peer id>`
+    pub fn with_bootstrap_peer(mut self, addr: &str) -> Self {
+        self.bootstrap_peers.push(addr.to_string());
+        self
+    }
+}
+
+/// Splits a bootstrap address into the peer ID it names and the address to reach it on
+fn parse_bootstrap_peer(addr: &str) -> Result<(PeerId, Multiaddr), Box<dyn Error>> {
+    let mut addr: Multiaddr = addr.parse()?;
+    match addr.pop() {
+        Some(Protocol::P2p(peer_id)) => Ok((peer_id, addr)),
+        _ => Err(format!("Peer address must end in /p2p/<peer id>: {}", addr).into()),
+    }
+}
+
+/// Messages published to one topic, with the peer that published each
+pub type TopicReceiver = broadcast::Receiver<(PeerId, UalMessage)>;
+
+/// MAP Protocol instance managing P2P communication; clones share the same node
+#[derive(Clone)]
 pub struct MapProtocol {
-    swarm: Swarm<mdns::Behaviour>,
+    local_peer_id: PeerId,
     command_tx: mpsc::Sender<MapCommand>,
+    incoming_tx: broadcast::Sender<(PeerId, UalMessage)>,
+    transfer_tx: broadcast::Sender<TransferEvent>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub enum MapCommand {
-    SendMessage(PeerId, String), // Send message to a peer
-    Broadcast(String), // Broadcast to all peers
+/// Requests from `MapProtocol` handles to the swarm task
+#[derive(Debug)]
+pub(crate) enum MapCommand {
+    SendMessage(PeerId, UalMessage, oneshot::Sender<Result<(), String>>), // Direct message, acknowledged by the peer
+    Publish(String, UalMessage, oneshot::Sender<Result<(), String>>), // Gossip to every peer subscribed to the topic
+    Subscribe(String, oneshot::Sender<Result<TopicReceiver, String>>),
+    Unsubscribe(String, oneshot::Sender<Result<(), String>>),
+    SetValidator(String, Option<topic::Hook>), // Replaces or clears the topic's validation hook
+    StartProviding(String, oneshot::Sender<Result<(), String>>), // Announce this node as a provider of a key
+    StopProviding(String),
+    GetProviders(String, oneshot::Sender<Result<HashSet<PeerId>, String>>),
+    Dial(Multiaddr, oneshot::Sender<Result<(), String>>),
+    NatStatus(oneshot::Sender<NatStatus>),
+    ListenAddrs(oneshot::Sender<Vec<Multiaddr>>),
+    ConnectedPeers(oneshot::Sender<Vec<PeerId>>),
+    KnownPeers(oneshot::Sender<Vec<PeerRecord>>),
+    KnownPeer(PeerId, oneshot::Sender<Option<PeerRecord>>),
+    BanPeer(PeerId, Duration), // Disconnects the peer and refuses it until the ban runs out
+    UnbanPeer(PeerId),
+    ShareBlob([u8; 32], PathBuf), // Serve a file's chunks under its content hash
+    FetchChunk(PeerId, transfer::ChunkRequest, oneshot::Sender<Result<transfer::ChunkResponse, String>>),
+    Negotiate(PeerId, oneshot::Sender<Result<Mode, String>>), // Agree on a payload encoding with a peer
+    PeerMode(PeerId, oneshot::Sender<Option<Mode>>),
 }
 
 impl MapProtocol {
-    /// Initializes a new MAP Protocol instance
+    /// Initializes a new MAP Protocol instance that forgets its peers when stopped
     pub async fn new(config: MapConfig) -> Result<Self, Box<dyn Error>> {
-        // Generate a local keypair for this node
-        let local_key = identity::Keypair::generate_ed25519();
+        Self::start(config, peer_store::PeerStore::in_memory()).await
+    }
+
+    /// Initializes a MAP Protocol instance that keeps its peer store in MapleDB and redials known peers
+    pub async fn with_peer_store(config: MapConfig, db: Arc<MapleDb>) -> Result<Self, Box<dyn Error>> {
+        Self::start(config, peer_store::PeerStore::open(db)?).await
+    }
+
+    async fn start(config: MapConfig, peers: peer_store::PeerStore) -> Result<Self, Box<dyn Error>> {
+        let local_key = match &config.key_file {
+            Some(path) => load_or_create_key(path)?,
+            None => Keypair::generate_ed25519(),
+        };
         let local_peer_id = PeerId::from(local_key.public());
         println!("Local peer ID: {:?}", local_peer_id);
+        let guard = guard::PeerGuard::new(&config)?;
+
+        // The configured transports, plus circuits through relays
+        let mut swarm = libp2p::SwarmBuilder::with_existing_identity(local_key)
+            .with_tokio()
+            .with_other_transport(|key| transport::build(key, &config.transports))?
+            .with_relay_client(noise::Config::new, yamux::Config::default)?
+            .with_behaviour(|key, relay_client| behaviour::MapBehaviour::new(key, relay_client, &config))?
+            .with_swarm_config(|c| c.with_idle_connection_timeout(IDLE_CONNECTION_TIMEOUT))
+            .build();
 
-        // Set up the transport with TCP, noise, and yamux
-        let transport = tcp::async_io::Transport::default()
-            .upgrade(libp2p::core::upgrade::Version::V1)
-            .authenticate(noise::Config::new(&local_key)?)
-            .multiplex(yamux::Config::default())
-            .boxed();
+        swarm.behaviour_mut().gossipsub.subscribe(&topic::ident(BROADCAST_TOPIC))?;
+        for addr in &config.listen_addrs {
+            swarm.listen_on(addr.parse()?)?;
+        }
+        for peer in guard.denied() {
+            swarm.behaviour_mut().blocked.block_peer(*peer);
+        }
+        if let Some(allowed) = swarm.behaviour_mut().allowed.as_mut() {
+            for peer in guard.allowed().into_iter().flatten() {
+                allowed.allow_peer(*peer);
+            }
+        }
+        for addr in &config.external_addrs {
+            swarm.add_external_address(addr.parse()?);
+        }
+        let mut relays = Vec::new();
+        for addr in &config.relays {
+            let (relay_id, addr) = parse_bootstrap_peer(addr)?;
+            relays.push(addr.with(Protocol::P2p(relay_id)).with(Protocol::P2pCircuit));
+        }
+        for addr in &config.bootstrap_peers {
+            let (peer_id, addr) = parse_bootstrap_peer(addr)?;
+            swarm.behaviour_mut().kademlia.add_address(&peer_id, addr);
+        }
+        if !config.bootstrap_peers.is_empty() {
+            // Later refreshes run every BOOTSTRAP_INTERVAL
+            swarm.behaviour_mut().kademlia.bootstrap()?;
+        }
 
-        // Create an mDNS behaviour for peer discovery
-        let behaviour = mdns::Behaviour::new(mdns::Config::default(), local_peer_id)?;
+        // Channel for sending commands to the swarm
+        let (command_tx, command_rx) = mpsc::channel(100);
+        let (incoming_tx, _) = broadcast::channel(INCOMING_CAPACITY);
+        let (transfer_tx, _) = broadcast::channel(TRANSFER_EVENT_CAPACITY);
+        let event_loop = event_loop::EventLoop::new(swarm, command_rx, incoming_tx.clone(), peers, relays, guard, config.modes);
+        tokio::spawn(event_loop.run());
 
-        // Build the swarm
-        let mut swarm = Swarm::new(
-            transport,
-            behaviour,
+        Ok(MapProtocol {
             local_peer_id,
-            libp2p::swarm::Config::with_tokio_executor(),
-        );
+            command_tx,
+            incoming_tx,
+            transfer_tx,
+        })
+    }
 
-        // Listen on the specified address
-        swarm.listen_on(config.listen_addr.parse()?)?;
+    /// Returns this node's peer ID
+    pub fn local_peer_id(&self) -> PeerId {
+        self.local_peer_id
+    }
 
-        // Channel for sending commands to the swarm
-        let (command_tx, mut command_rx) = mpsc::channel(100);
+    /// Messages other peers send or broadcast to this node
+    pub fn incoming(&self) -> broadcast::Receiver<(PeerId, UalMessage)> {
+        self.incoming_tx.subscribe()
+    }
 
-        // Spawn the swarm event loop
-        tokio::spawn(async move {
-            loop {
-                tokio::select! {
-                    event = swarm.select_next_some() => match event {
-                        SwarmEvent::NewListenAddr { address, .. } => {
-                            println!("Listening on {:?}", address);
-                        }
-                        SwarmEvent::Behaviour(mdns::Event::Discovered(peers)) => {
-                            for (peer, addr) in peers {
-                                println!("Discovered peer {} at {}", peer, addr);
-                                swarm.dial(addr).unwrap(); // Dial discovered peers
-                            }
-                        }
-                        _ => {}
-                    },
-                    Some(cmd) = command_rx.recv() => match cmd {
-                        MapCommand::SendMessage(peer, msg) => {
-                            println!("Sending to {}: {}", peer, msg);
-                            // TODO: Implement message sending
-                        }
-                        MapCommand::Broadcast(msg) => {
-                            println!("Broadcasting: {}", msg);
-                            // TODO: Implement broadcast
-                        }
-                    }
-                }
-            }
-        });
+    /// Progress, completion and failure of blob downloads started by this node
+    pub fn transfer_events(&self) -> broadcast::Receiver<TransferEvent> {
+        self.transfer_tx.subscribe()
+    }
+
+    /// Sends a message to a specific peer, waiting for it to acknowledge receipt
+    pub async fn send_message(&self, peer: PeerId, message: UalMessage) -> Result<(), Box<dyn Error>> {
+        let (tx, rx) = oneshot::channel();
+        self.command_tx
+            .send(MapCommand::SendMessage(peer, message, tx))
+            .await?;
+        Ok(rx.await??)
+    }
 
-        Ok(MapProtocol { swarm, command_tx })
+    /// Broadcasts a message to all connected peers
+    pub async fn broadcast(&self, message: UalMessage) -> Result<(), Box<dyn Error>> {
+        self.publish(BROADCAST_TOPIC, message).await
     }
 
-    /// Sends a message to a specific peer
-    pub async fn send_message(&self, peer: PeerId, message: String) -> Result<(), Box<dyn Error>> {
+    /// Publishes a message to everyone subscribed to a topic; this node need not be subscribed
+    pub async fn publish(&self, topic: &str, message: UalMessage) -> Result<(), Box<dyn Error>> {
+        let (tx, rx) = oneshot::channel();
         self.command_tx
-            .send(MapCommand::SendMessage(peer, message))
+            .send(MapCommand::Publish(topic.to_string(), message, tx))
+            .await?;
+        Ok(rx.await??)
+    }
+
+    /// Joins a topic, returning a receiver for messages other peers publish to it
+    pub async fn subscribe(&self, topic: &str) -> Result<TopicReceiver, Box<dyn Error>> {
+        let (tx, rx) = oneshot::channel();
+        self.command_tx
+            .send(MapCommand::Subscribe(topic.to_string(), tx))
+            .await?;
+        Ok(rx.await??)
+    }
+
+    /// Leaves a topic, closing every receiver returned by `subscribe`
+    pub async fn unsubscribe(&self, topic: &str) -> Result<(), Box<dyn Error>> {
+        let (tx, rx) = oneshot::channel();
+        self.command_tx
+            .send(MapCommand::Unsubscribe(topic.to_string(), tx))
+            .await?;
+        Ok(rx.await??)
+    }
+
+    /// Checks every message arriving on a topic before it is delivered or forwarded to other peers
+    pub async fn set_validator(&self, topic: &str, validator: TopicValidator) -> Result<(), Box<dyn Error>> {
+        self.command_tx
+            .send(MapCommand::SetValidator(topic.to_string(), Some(topic::Hook(validator))))
             .await?;
         Ok(())
     }
 
-    /// Broadcasts a message to all connected peers
-    pub async fn broadcast(&self, message: String) -> Result<(), Box<dyn Error>> {
+    /// Removes a topic's validator, accepting any well-formed UAL message again
+    pub async fn clear_validator(&self, topic: &str) -> Result<(), Box<dyn Error>> {
         self.command_tx
-            .send(MapCommand::Broadcast(message))
+            .send(MapCommand::SetValidator(topic.to_string(), None))
             .await?;
         Ok(())
     }
 
-    /// Broadcasts a .map file to all peers
-    pub async fn broadcast_map_file(&self, path: &str) -> Result<(), Box<dyn Error>> {
-        let mut file = tokio::fs::File::open(path).await?;
-        let mut buffer = Vec::new();
-        file.read_to_end(&mut buffer).await?;
-        self.broadcast(String::from_utf8_lossy(&buffer).to_string()).await?;
+    /// Announces on the DHT that this node provides a key, e.g., the DID of an agent it hosts
+    pub async fn start_providing(&self, key: &str) -> Result<(), Box<dyn Error>> {
+        let (tx, rx) = oneshot::channel();
+        self.command_tx
+            .send(MapCommand::StartProviding(key.to_string(), tx))
+            .await?;
+        Ok(rx.await??)
+    }
+
+    /// Withdraws this node's provider record for a key; copies held by other peers expire on their own
+    pub async fn stop_providing(&self, key: &str) -> Result<(), Box<dyn Error>> {
+        self.command_tx
+            .send(MapCommand::StopProviding(key.to_string()))
+            .await?;
+        Ok(())
+    }
+
+    /// Looks up the peers providing a key, e.g., who hosts a DID
+    pub async fn get_providers(&self, key: &str) -> Result<HashSet<PeerId>, Box<dyn Error>> {
+        let (tx, rx) = oneshot::channel();
+        self.command_tx
+            .send(MapCommand::GetProviders(key.to_string(), tx))
+            .await?;
+        Ok(rx.await??)
+    }
+
+    /// Connects to a peer at a known address
+    pub async fn dial(&self, addr: Multiaddr) -> Result<(), Box<dyn Error>> {
+        let (tx, rx) = oneshot::channel();
+        self.command_tx.send(MapCommand::Dial(addr, tx)).await?;
+        Ok(rx.await??)
+    }
+
+    /// Whether AutoNAT probes found this node publicly reachable, and on which address
+    pub async fn nat_status(&self) -> Result<NatStatus, Box<dyn Error>> {
+        let (tx, rx) = oneshot::channel();
+        self.command_tx.send(MapCommand::NatStatus(tx)).await?;
+        Ok(rx.await?)
+    }
+
+    /// Addresses this node is listening on, including circuits reserved on relays
+    pub async fn listen_addrs(&self) -> Result<Vec<Multiaddr>, Box<dyn Error>> {
+        let (tx, rx) = oneshot::channel();
+        self.command_tx.send(MapCommand::ListenAddrs(tx)).await?;
+        Ok(rx.await?)
+    }
+
+    /// Every peer this node has discovered or connected to, with its health and reputation
+    pub async fn known_peers(&self) -> Result<Vec<PeerRecord>, Box<dyn Error>> {
+        let (tx, rx) = oneshot::channel();
+        self.command_tx.send(MapCommand::KnownPeers(tx)).await?;
+        Ok(rx.await?)
+    }
+
+    /// What this node knows about one peer
+    pub async fn known_peer(&self, peer: PeerId) -> Result<Option<PeerRecord>, Box<dyn Error>> {
+        let (tx, rx) = oneshot::channel();
+        self.command_tx.send(MapCommand::KnownPeer(peer, tx)).await?;
+        Ok(rx.await?)
+    }
+
+    /// Disconnects a peer and refuses it for a while; the ban is kept in the peer store
+    pub async fn ban_peer(&self, peer: PeerId, duration: Duration) -> Result<(), Box<dyn Error>> {
+        self.command_tx.send(MapCommand::BanPeer(peer, duration)).await?;
         Ok(())
     }
+
+    /// Lifts a ban early; peers on the deny list stay refused
+    pub async fn unban_peer(&self, peer: PeerId) -> Result<(), Box<dyn Error>> {
+        self.command_tx.send(MapCommand::UnbanPeer(peer)).await?;
+        Ok(())
+    }
+
+    /// Agrees with a peer on the payload encoding to use, running the UAL hello handshake over
+    /// direct messages; the peer picks the first of this node's modes it also supports
+    pub async fn negotiate(&self, peer: PeerId) -> Result<Mode, Box<dyn Error>> {
+        let (tx, rx) = oneshot::channel();
+        self.command_tx.send(MapCommand::Negotiate(peer, tx)).await?;
+        let mode = tokio::time::timeout(NEGOTIATION_TIMEOUT, rx)
+            .await
+            .map_err(|_| format!("Peer {} did not answer mode negotiation", peer))???;
+        Ok(mode)
+    }
+
+    /// The encoding last negotiated with a peer, by either side
+    pub async fn peer_mode(&self, peer: PeerId) -> Result<Option<Mode>, Box<dyn Error>> {
+        let (tx, rx) = oneshot::channel();
+        self.command_tx.send(MapCommand::PeerMode(peer, tx)).await?;
+        Ok(rx.await?)
+    }
+
+    /// Peers with an open connection to this node
+    pub async fn connected_peers(&self) -> Result<Vec<PeerId>, Box<dyn Error>> {
+        let (tx, rx) = oneshot::channel();
+        self.command_tx.send(MapCommand::ConnectedPeers(tx)).await?;
+        Ok(rx.await?)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use maple_ual::Mode;
 
     #[tokio::test]
     async fn test_map_init() {
-        let config = MapConfig {
-            listen_addr: "/ip4/127.0.0.1/tcp/0".to_string(),
-        };
+        let config = MapConfig::new("/ip4/127.0.0.1/tcp/0");
         let map = MapProtocol::new(config).await;
         assert!(map.is_ok());
     }
-}
\ No newline at end of file
+
+    /// Starts a node and waits until it has a listen address
+    async fn node() -> (MapProtocol, Multiaddr) {
+        node_with(MapConfig::new("/ip4/127.0.0.1/tcp/0")).await
+    }
+
+    async fn node_with(config: MapConfig) -> (MapProtocol, Multiaddr) {
+        let map = MapProtocol::new(config).await.unwrap();
+        loop {
+            if let Some(addr) = map.listen_addrs().await.unwrap().pop() {
+                return (map, addr);
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    /// Starts two connected nodes
+    async fn pair() -> (MapProtocol, MapProtocol) {
+        let (alice, alice_addr) = node().await;
+        let (bob, _) = node().await;
+        bob.dial(alice_addr).await.unwrap();
+        while !alice.connected_peers().await.unwrap().contains(&bob.local_peer_id()) {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        (alice, bob)
+    }
+
+    #[tokio::test]
+    async fn test_direct_send_and_broadcast_reach_incoming() {
+        let (alice, bob) = pair().await;
+        let mut bob_incoming = bob.incoming();
+
+        let direct = UalMessage::new("ping", Mode::ByteLevel).with_byte_payload(vec![1, 2, 3]);
+        alice.send_message(bob.local_peer_id(), direct.clone()).await.unwrap();
+        let (from, received) = bob_incoming.recv().await.unwrap();
+        assert_eq!(from, alice.local_peer_id());
+        assert_eq!((received.id(), received.payload()), (direct.id(), direct.payload()));
+        let record = alice.known_peer(bob.local_peer_id()).await.unwrap().unwrap();
+        assert!(record.connected && record.latency_ms.is_some());
+
+        // Gossip needs the subscription exchange to finish before a publish has anyone to reach
+        let gossip = UalMessage::new("announce", Mode::ByteLevel).with_byte_payload(vec![4]);
+        let received = tokio::time::timeout(Duration::from_secs(10), async {
+            loop {
+                if alice.broadcast(gossip.clone()).await.is_ok() {
+                    return bob_incoming.recv().await.unwrap();
+                }
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!((received.0, received.1.id()), (alice.local_peer_id(), gossip.id()));
+    }
+
+    #[tokio::test]
+    async fn test_mode_negotiation_over_direct_messages() {
+        let (alice, alice_addr) =
+            node_with(MapConfig::new("/ip4/127.0.0.1/tcp/0").with_mdns(false).with_modes(&[Mode::Cbor, Mode::Json])).await;
+        let config = MapConfig::new("/ip4/127.0.0.1/tcp/0").with_mdns(false);
+        let (bob, _) = node_with(config.clone().with_modes(&[Mode::CapnProto, Mode::Json, Mode::Cbor])).await;
+        bob.dial(alice_addr.clone()).await.unwrap();
+        let mut alice_incoming = alice.incoming();
+
+        // Alice answers with the first of bob's modes she supports
+        let mode = tokio::time::timeout(Duration::from_secs(10), async {
+            loop {
+                match bob.negotiate(alice.local_peer_id()).await {
+                    Ok(mode) => return mode,
+                    Err(_) => tokio::time::sleep(Duration::from_millis(50)).await,
+                }
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(mode, Mode::Json);
+        assert_eq!(bob.peer_mode(alice.local_peer_id()).await.unwrap(), Some(Mode::Json));
+        assert_eq!(alice.peer_mode(bob.local_peer_id()).await.unwrap(), Some(Mode::Json));
+        assert!(alice_incoming.try_recv().is_err()); // The handshake is not handed to agents
+
+        let (carol, _) = node_with(config.with_modes(&[Mode::Grpc])).await;
+        carol.dial(alice_addr).await.unwrap();
+        let err = tokio::time::timeout(Duration::from_secs(10), async {
+            loop {
+                match carol.negotiate(alice.local_peer_id()).await {
+                    Err(e) if e.to_string().contains("no mode") => return e,
+                    _ => tokio::time::sleep(Duration::from_millis(50)).await,
+                }
+            }
+        })
+        .await
+        .unwrap();
+        assert!(err.to_string().contains("no mode supported by both peers"));
+    }
+
+    #[tokio::test]
+    async fn test_topic_validator_filters_messages() {
+        let (alice, bob) = pair().await;
+        let topic = role_topic("translator");
+        let mut translators = bob.subscribe(&topic).await.unwrap();
+        let translate_only: TopicValidator = std::sync::Arc::new(|_, msg: &UalMessage| match msg.action() {
+            "translate" => Ok(()),
+            other => Err(format!("unexpected action {}", other)),
+        });
+        bob.set_validator(&topic, translate_only).await.unwrap();
+
+        // Publishing fails until alice has learned of bob's subscription
+        let spam = UalMessage::new("spam", Mode::ByteLevel).with_byte_payload(vec![0]);
+        tokio::time::timeout(Duration::from_secs(10), async {
+            while alice.publish(&topic, spam.clone()).await.is_err() {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+        })
+        .await
+        .unwrap();
+        let job = UalMessage::new("translate", Mode::ByteLevel).with_byte_payload(vec![1]);
+        alice.publish(&topic, job.clone()).await.unwrap();
+
+        let (from, received) = translators.recv().await.unwrap();
+        assert_eq!((from, received.id()), (alice.local_peer_id(), job.id()));
+        // The same frame again is a duplicate and never published
+        assert!(alice.publish(&topic, job).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_provider_records_resolve_through_bootstrap_peer() {
+        let (alice, alice_addr) = node().await;
+        alice.start_providing("did:maple:agent:1").await.unwrap();
+
+        let bootstrap = alice_addr.with(Protocol::P2p(alice.local_peer_id())).to_string();
+        let (bob, _) = node_with(MapConfig::new("/ip4/127.0.0.1/tcp/0").with_bootstrap_peer(&bootstrap)).await;
+        // A lookup racing the first dial to alice can come back empty, so retry briefly
+        let providers = tokio::time::timeout(Duration::from_secs(10), async {
+            loop {
+                let providers = bob.get_providers("did:maple:agent:1").await.unwrap();
+                if !providers.is_empty() {
+                    return providers;
+                }
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(providers, HashSet::from([alice.local_peer_id()]));
+        assert!(bob.get_providers("did:maple:agent:2").await.unwrap().is_empty());
+
+        assert!(MapProtocol::new(MapConfig::new("/ip4/127.0.0.1/tcp/0").with_bootstrap_peer("/ip4/127.0.0.1/tcp/1"))
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_peers_behind_nat_connect_through_relay() {
+        // A relay must announce an address it is reachable on, so it gets a fixed port
+        let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let relay_addr = format!("/ip4/127.0.0.1/tcp/{}", port);
+        let relay_config = MapConfig::new(&relay_addr)
+            .with_mdns(false)
+            .with_external_addr(&relay_addr)
+            .with_relay_server();
+        let (relay, _) = node_with(relay_config).await;
+        let relay_addr = format!("{}/p2p/{}", relay_addr, relay.local_peer_id());
+
+        // Without mDNS, alice is only reachable through her reservation on the relay
+        let alice = MapProtocol::new(MapConfig::new("/ip4/127.0.0.1/tcp/0").with_mdns(false).with_relay(&relay_addr))
+            .await
+            .unwrap();
+        let circuit = tokio::time::timeout(Duration::from_secs(10), async {
+            loop {
+                let addrs = alice.listen_addrs().await.unwrap();
+                if let Some(addr) = addrs.into_iter().find(|a| a.iter().any(|p| p == Protocol::P2pCircuit)) {
+                    return addr;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+        let mut alice_incoming = alice.incoming();
+
+        let (bob, _) = node_with(MapConfig::new("/ip4/127.0.0.1/tcp/0").with_mdns(false)).await;
+        bob.dial(circuit).await.unwrap(); // Already ends in /p2p/<alice>
+        let hello = UalMessage::new("hello", Mode::ByteLevel).with_byte_payload(vec![7]);
+        tokio::time::timeout(Duration::from_secs(10), async {
+            while bob.send_message(alice.local_peer_id(), hello.clone()).await.is_err() {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+        })
+        .await
+        .unwrap();
+        let (from, received) = alice_incoming.recv().await.unwrap();
+        assert_eq!((from, received.id()), (bob.local_peer_id(), hello.id()));
+        assert!(matches!(bob.nat_status().await.unwrap(), NatStatus::Unknown));
+    }
+
+    #[tokio::test]
+    async fn test_reachable_node_is_public() {
+        // Each dial-back opens a connection of its own, so probes in both directions need room beyond the default
+        let config = MapConfig::new("/ip4/127.0.0.1/tcp/0")
+            .with_mdns(false)
+            .with_private_nat_probes()
+            .with_connection_limits(DEFAULT_MAX_CONNECTIONS, 4);
+        let (_alice, alice_addr) = node_with(config.clone()).await;
+        let (bob, _) = node_with(config).await;
+        bob.dial(alice_addr).await.unwrap();
+        // Alice dials back the address bob listens on, which succeeds as nothing stands in between
+        tokio::time::timeout(Duration::from_secs(20), async {
+            while !matches!(bob.nat_status().await.unwrap(), NatStatus::Public(_)) {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+        })
+        .await
+        .unwrap();
+        assert!(parse_bootstrap_peer("/ip4/127.0.0.1/tcp/4001").unwrap_err().to_string().starts_with("Peer address"));
+    }
+
+    #[tokio::test]
+    async fn test_flooding_peer_is_banned_until_unbanned() {
+        let (alice, alice_addr) = node_with(MapConfig::new("/ip4/127.0.0.1/tcp/0").with_mdns(false).with_rate_limit(5)).await;
+        let (bob, _) = node_with(MapConfig::new("/ip4/127.0.0.1/tcp/0").with_mdns(false)).await;
+        bob.dial(alice_addr.clone()).await.unwrap();
+        while !alice.connected_peers().await.unwrap().contains(&bob.local_peer_id()) {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        // Messages over the limit are dropped and cost reputation until bob is banned
+        let flood = UalMessage::new("flood", Mode::ByteLevel).with_byte_payload(vec![0]);
+        let mut delivered = 0;
+        for _ in 0..40 {
+            if bob.send_message(alice.local_peer_id(), flood.clone()).await.is_ok() {
+                delivered += 1;
+            }
+        }
+        assert!(delivered < 40);
+        let record = alice.known_peer(bob.local_peer_id()).await.unwrap().unwrap();
+        assert!(record.is_banned() && record.reputation <= peer_store::BAN_THRESHOLD);
+        assert!(!alice.connected_peers().await.unwrap().contains(&bob.local_peer_id()));
+
+        alice.unban_peer(bob.local_peer_id()).await.unwrap();
+        bob.dial(alice_addr).await.unwrap();
+        tokio::time::timeout(Duration::from_secs(10), async {
+            while !alice.connected_peers().await.unwrap().contains(&bob.local_peer_id()) {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+    }
+}
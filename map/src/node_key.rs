@@ -0,0 +1,117 @@
+// Persistent node identity: the keypair behind a MAP node's PeerId, kept in a key file
+// © 2025 Finalverse Inc. All rights reserved.
+
+use libp2p::identity::Keypair;
+use std::error::Error;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+
+/// Key file used when none is configured
+pub const DEFAULT_KEY_FILE: &str = "maple_node.key";
+
+/// Loads the node key at `path`, generating and saving a new one on first run
+pub fn load_or_create_key(path: &str) -> Result<Keypair, Box<dyn Error>> {
+    if Path::new(path).exists() {
+        load_key(path)
+    } else {
+        create_key(path, false)
+    }
+}
+
+/// Reads a protobuf-encoded keypair, refusing files other users can read
+pub fn load_key(path: &str) -> Result<Keypair, Box<dyn Error>> {
+    check_permissions(path)?;
+    Ok(Keypair::from_protobuf_encoding(&fs::read(path)?)?)
+}
+
+/// Generates an Ed25519 node key and saves it; an existing key is only replaced if `overwrite` is set
+pub fn create_key(path: &str, overwrite: bool) -> Result<Keypair, Box<dyn Error>> {
+    if !overwrite && Path::new(path).exists() {
+        return Err(format!("Key file already exists: {}", path).into());
+    }
+    let key = Keypair::generate_ed25519();
+    write_key(path, &key)?;
+    Ok(key)
+}
+
+/// Replaces the node key with a new one, keeping the old key as `<path>.prev`.
+///
+/// Rotation changes the node's PeerId, so peers pinned to the old one must be told.
+pub fn rotate_key(path: &str) -> Result<Keypair, Box<dyn Error>> {
+    let previous = load_key(path)?;
+    write_key(&format!("{}.prev", path), &previous)?;
+    create_key(path, true)
+}
+
+/// Writes the key to a temporary file readable only by the owner, then moves it into place
+fn write_key(path: &str, key: &Keypair) -> Result<(), Box<dyn Error>> {
+    let tmp = format!("{}.tmp", path);
+    // The mode only applies when the file is created, so never reuse a leftover temp file
+    match fs::remove_file(&tmp) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+        _ => {}
+    }
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(&tmp)?;
+    file.write_all(&key.to_protobuf_encoding()?)?;
+    file.sync_all()?;
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+#[cfg(unix)]
+fn check_permissions(path: &str) -> Result<(), Box<dyn Error>> {
+    use std::os::unix::fs::PermissionsExt;
+    let mode = fs::metadata(path)?.permissions().mode();
+    if mode & 0o077 != 0 {
+        return Err(format!("Key file {} is accessible by other users (mode {:o}); run chmod 600", path, mode & 0o777).into());
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn check_permissions(_path: &str) -> Result<(), Box<dyn Error>> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_file_create_load_rotate() {
+        let path = "test_node.key";
+        let created = load_or_create_key(path).unwrap();
+        let loaded = load_or_create_key(path).unwrap();
+        assert_eq!(created.public().to_peer_id(), loaded.public().to_peer_id());
+        assert!(create_key(path, false).is_err());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(fs::metadata(path).unwrap().permissions().mode() & 0o777, 0o600);
+        }
+
+        // A world-readable temp file left by a crash must not leak the rotated key
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::write(format!("{}.tmp", path), b"stale").unwrap();
+            fs::set_permissions(format!("{}.tmp", path), fs::Permissions::from_mode(0o644)).unwrap();
+        }
+        let rotated = rotate_key(path).unwrap();
+        assert!(load_key(path).is_ok());
+        assert_ne!(rotated.public().to_peer_id(), created.public().to_peer_id());
+        let previous = load_key(&format!("{}.prev", path)).unwrap();
+        assert_eq!(previous.public().to_peer_id(), created.public().to_peer_id());
+
+        fs::remove_file(path).unwrap();
+        fs::remove_file(format!("{}.prev", path)).unwrap();
+    }
+}
@@ -0,0 +1,287 @@
+// Known peers with their addresses, health and reputation, persisted in MapleDB
+// © 2025 Finalverse Inc. All rights reserved.
+
+use libp2p::{Multiaddr, PeerId};
+use mapledb::MapleDb;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::error::Error;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const KEY_PREFIX: &str = "peer:";
+
+/// Addresses remembered per peer; older ones are dropped first
+const MAX_ADDRESSES: usize = 8;
+
+/// Reputation bounds; peers below zero are not redialled on startup
+pub const MIN_REPUTATION: i32 = -100;
+pub const MAX_REPUTATION: i32 = 100;
+
+/// Reputation at or below which a peer is banned
+pub const BAN_THRESHOLD: i32 = -50;
+
+/// How long an automatic ban lasts
+pub const BAN_DURATION: Duration = Duration::from_secs(60 * 60);
+
+/// First reconnection delay, doubled after every failed attempt
+const RECONNECT_BASE_DELAY: Duration = Duration::from_secs(1);
+
+/// Longest wait between reconnection attempts
+const RECONNECT_MAX_DELAY: Duration = Duration::from_secs(5 * 60);
+
+/// Failed attempts in a row after which a peer is left alone until it is rediscovered
+pub const MAX_RECONNECT_ATTEMPTS: u32 = 10;
+
+/// What this node knows about another peer
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PeerRecord {
+    pub peer_id: String,
+    pub addresses: Vec<String>, // Most recently seen last
+    pub last_seen: u64, // Unix time in milliseconds of the last connection or message
+    pub latency_ms: Option<u64>, // Smoothed round trip time
+    pub reputation: i32, // MIN_REPUTATION..=MAX_REPUTATION
+    pub failures: u32, // Failed dials since the last successful connection
+    #[serde(default)]
+    pub banned_until: Option<u64>, // Unix time in milliseconds when the peer may connect again
+    #[serde(skip)]
+    pub connected: bool,
+}
+
+impl PeerRecord {
+    fn new(peer_id: &PeerId) -> Self {
+        PeerRecord {
+            peer_id: peer_id.to_string(),
+            addresses: Vec::new(),
+            last_seen: 0,
+            latency_ms: None,
+            reputation: 0,
+            failures: 0,
+            banned_until: None,
+            connected: false,
+        }
+    }
+
+    pub fn is_banned(&self) -> bool {
+        self.banned_until.is_some_and(|until| until > unix_millis())
+    }
+
+    /// Addresses that still parse, most recent first
+    pub fn multiaddrs(&self) -> Vec<Multiaddr> {
+        self.addresses.iter().rev().filter_map(|a| a.parse().ok()).collect()
+    }
+}
+
+/// Peer records cached in memory and written through to MapleDB when one is attached
+pub(crate) struct PeerStore {
+    peers: HashMap<PeerId, PeerRecord>,
+    db: Option<Arc<MapleDb>>,
+}
+
+impl PeerStore {
+    /// A store that forgets everything when the node stops
+    pub fn in_memory() -> Self {
+        PeerStore {
+            peers: HashMap::new(),
+            db: None,
+        }
+    }
+
+    /// Loads every peer saved in MapleDB
+    pub fn open(db: Arc<MapleDb>) -> Result<Self, Box<dyn Error>> {
+        let mut peers = HashMap::new();
+        for (_, bytes) in db.scan_prefix(KEY_PREFIX)? {
+            let record: PeerRecord = serde_json::from_slice(&bytes)?;
+            peers.insert(record.peer_id.parse()?, record);
+        }
+        Ok(PeerStore { peers, db: Some(db) })
+    }
+
+    pub fn get(&self, peer: &PeerId) -> Option<&PeerRecord> {
+        self.peers.get(peer)
+    }
+
+    pub fn all(&self) -> Vec<PeerRecord> {
+        self.peers.values().cloned().collect()
+    }
+
+    /// Remembers an address a peer can be reached on
+    pub fn add_address(&mut self, peer: &PeerId, addr: &Multiaddr) {
+        self.update(peer, |record| {
+            let addr = addr.to_string();
+            record.addresses.retain(|a| *a != addr);
+            record.addresses.push(addr);
+            if record.addresses.len() > MAX_ADDRESSES {
+                record.addresses.remove(0);
+            }
+        });
+    }
+
+    pub fn connected(&mut self, peer: &PeerId, established_in: Duration) {
+        self.update(peer, |record| {
+            record.connected = true;
+            record.last_seen = unix_millis();
+            record.failures = 0;
+            record.reputation = (record.reputation + 1).min(MAX_REPUTATION);
+            // Connection setup takes a few round trips, so it only seeds the estimate
+            if record.latency_ms.is_none() {
+                record.latency_ms = Some(established_in.as_millis() as u64);
+            }
+        });
+    }
+
+    pub fn disconnected(&mut self, peer: &PeerId) {
+        self.update(peer, |record| {
+            record.connected = false;
+            record.last_seen = unix_millis();
+        });
+    }
+
+    /// Counts a failed dial and returns how long to wait before the next, or None to stop trying
+    pub fn dial_failed(&mut self, peer: &PeerId) -> Option<Duration> {
+        let record = self.update(peer, |record| {
+            record.failures += 1;
+            record.reputation = (record.reputation - 2).max(MIN_REPUTATION);
+        });
+        (record.failures < MAX_RECONNECT_ATTEMPTS).then(|| backoff(record.failures))
+    }
+
+    /// Folds a measured round trip into the peer's latency
+    pub fn observed_rtt(&mut self, peer: &PeerId, rtt: Duration) {
+        self.update(peer, |record| {
+            let rtt = rtt.as_millis() as u64;
+            record.latency_ms = Some(match record.latency_ms {
+                Some(latency) => (latency * 7 + rtt) / 8,
+                None => rtt,
+            });
+            record.last_seen = unix_millis();
+        });
+    }
+
+    /// Adjusts a peer's reputation, e.g., down for invalid messages, and returns true if that got it banned
+    pub fn adjust_reputation(&mut self, peer: &PeerId, delta: i32) -> bool {
+        let was_banned = self.get(peer).is_some_and(PeerRecord::is_banned);
+        let record = self.update(peer, |record| {
+            record.reputation = (record.reputation + delta).clamp(MIN_REPUTATION, MAX_REPUTATION);
+            if !was_banned && record.reputation <= BAN_THRESHOLD {
+                record.banned_until = Some(unix_millis() + BAN_DURATION.as_millis() as u64);
+            }
+        });
+        !was_banned && record.is_banned()
+    }
+
+    /// Keeps a peer from connecting for a while
+    pub fn ban(&mut self, peer: &PeerId, duration: Duration) {
+        self.update(peer, |record| {
+            record.banned_until = Some(unix_millis() + duration.as_millis() as u64);
+        });
+    }
+
+    pub fn unban(&mut self, peer: &PeerId) {
+        self.update(peer, |record| record.banned_until = None);
+    }
+
+    /// Peers whose ban has not run out yet
+    pub fn banned(&self) -> Vec<PeerId> {
+        self.peers
+            .iter()
+            .filter(|(_, record)| record.is_banned())
+            .map(|(peer, _)| *peer)
+            .collect()
+    }
+
+    /// Clears bans that have run out and returns the peers they held; their reputation stays low
+    pub fn lift_expired_bans(&mut self) -> Vec<PeerId> {
+        let expired: Vec<PeerId> = self
+            .peers
+            .iter()
+            .filter(|(_, record)| record.banned_until.is_some() && !record.is_banned())
+            .map(|(peer, _)| *peer)
+            .collect();
+        for peer in &expired {
+            self.unban(peer);
+        }
+        expired
+    }
+
+    /// Peers worth dialling on startup, best reputation first
+    pub fn redial_candidates(&self) -> Vec<(PeerId, Vec<Multiaddr>)> {
+        let mut candidates: Vec<_> = self
+            .peers
+            .iter()
+            .filter(|(_, record)| record.reputation >= 0 && !record.addresses.is_empty())
+            .collect();
+        candidates.sort_by_key(|(_, record)| -record.reputation);
+        candidates
+            .into_iter()
+            .map(|(peer, record)| (*peer, record.multiaddrs()))
+            .collect()
+    }
+
+    fn update(&mut self, peer: &PeerId, change: impl FnOnce(&mut PeerRecord)) -> &PeerRecord {
+        let record = self.peers.entry(*peer).or_insert_with(|| PeerRecord::new(peer));
+        change(record);
+        if let Some(db) = &self.db {
+            let saved = serde_json::to_vec(&*record)
+                .map_err(|e| e.to_string())
+                .and_then(|bytes| db.store(&format!("{}{}", KEY_PREFIX, peer), &bytes).map_err(|e| e.to_string()));
+            if let Err(e) = saved {
+                eprintln!("Failed to save peer {:?}: {}", peer, e);
+            }
+        }
+        record
+    }
+}
+
+/// Delay before reconnection attempt number `failures`
+pub(crate) fn backoff(failures: u32) -> Duration {
+    RECONNECT_BASE_DELAY
+        .saturating_mul(2u32.saturating_pow(failures.saturating_sub(1)))
+        .min(RECONNECT_MAX_DELAY)
+}
+
+fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_peer_store_persists_and_backs_off() {
+        let db = Arc::new(MapleDb::new("test_peer_store_db").unwrap());
+        let peer = PeerId::random();
+        let addr: Multiaddr = "/ip4/127.0.0.1/tcp/4001".parse().unwrap();
+        {
+            let mut store = PeerStore::open(db.clone()).unwrap();
+            store.add_address(&peer, &addr);
+            store.connected(&peer, Duration::from_millis(40));
+            store.observed_rtt(&peer, Duration::from_millis(8));
+            assert_eq!(store.dial_failed(&peer), Some(Duration::from_secs(1)));
+            assert_eq!(store.dial_failed(&peer), Some(Duration::from_secs(2)));
+        }
+
+        let store = PeerStore::open(db).unwrap();
+        let record = store.get(&peer).unwrap();
+        assert_eq!(record.multiaddrs(), vec![addr]);
+        assert_eq!((record.latency_ms, record.failures, record.reputation), (Some(36), 2, -3));
+        assert!(!record.connected);
+        assert!(store.redial_candidates().is_empty()); // Negative reputation
+        assert_eq!(backoff(30), RECONNECT_MAX_DELAY);
+
+        let mut store = store;
+        assert!(!store.adjust_reputation(&peer, -40));
+        assert!(store.adjust_reputation(&peer, -10)); // Crosses BAN_THRESHOLD
+        assert!(!store.adjust_reputation(&peer, -10)); // Already banned
+        assert_eq!(store.banned(), vec![peer]);
+        store.ban(&peer, Duration::ZERO);
+        assert_eq!(store.lift_expired_bans(), vec![peer]);
+        assert!(store.banned().is_empty() && store.lift_expired_bans().is_empty());
+        drop(store);
+        std::fs::remove_dir_all("test_peer_store_db").unwrap();
+    }
+}
@@ -0,0 +1,94 @@
+// Gossipsub topics used across MAPLE and the validation hooks guarding them
+// © 2025 Finalverse Inc. All rights reserved.
+
+use libp2p::gossipsub::{IdentTopic, Message, MessageId, TopicScoreParams};
+use libp2p::PeerId;
+use maple_ual::UalMessage;
+use std::sync::Arc;
+
+/// Registry updates (agent registrations and removals)
+pub const REGISTRY_TOPIC: &str = "maple/registry";
+
+/// Prefix of per-role channels, e.g., "maple/role/translator"
+pub const ROLE_TOPIC_PREFIX: &str = "maple/role/";
+
+/// Prefix of MALL experiment channels, e.g., "maple/mall/exp-42"
+pub const MALL_TOPIC_PREFIX: &str = "maple/mall/";
+
+/// Topic messages buffered per subscriber; slow subscribers see `Lagged`
+pub const TOPIC_CAPACITY: usize = 256;
+
+/// Decides whether a message published to a topic is delivered and forwarded.
+///
+/// Returning `Err` rejects the message, which also counts against the peer that relayed it.
+pub type TopicValidator = Arc<dyn Fn(&PeerId, &UalMessage) -> Result<(), String> + Send + Sync>;
+
+/// Channel for agents with the given role
+pub fn role_topic(role: &str) -> String {
+    format!("{}{}", ROLE_TOPIC_PREFIX, role)
+}
+
+/// Channel for a MALL experiment
+pub fn mall_topic(experiment: &str) -> String {
+    format!("{}{}", MALL_TOPIC_PREFIX, experiment)
+}
+
+pub(crate) fn ident(topic: &str) -> IdentTopic {
+    IdentTopic::new(topic)
+}
+
+/// Carries a validator through the command channel, which needs `Debug`
+pub(crate) struct Hook(pub TopicValidator);
+
+impl std::fmt::Debug for Hook {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("TopicValidator")
+    }
+}
+
+/// Identifies a message by topic and content, so the same UAL frame republished by
+/// another node is recognised as a duplicate and not delivered twice
+pub(crate) fn message_id(message: &Message) -> MessageId {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(message.topic.as_str().as_bytes());
+    hasher.update(&message.data);
+    MessageId::from(hasher.finalize().to_hex().to_string())
+}
+
+/// How gossipsub scores peers on a topic, mostly by the invalid messages they forward
+pub(crate) fn score_params() -> TopicScoreParams {
+    TopicScoreParams {
+        topic_weight: 1.0,
+        invalid_message_deliveries_weight: -10.0, // Squared, so a few rejected messages graylist a peer
+        // Agent topics are often quiet, so delivering few messages is no offence
+        mesh_message_deliveries_weight: 0.0,
+        mesh_failure_penalty_weight: 0.0,
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libp2p::gossipsub::TopicHash;
+
+    #[test]
+    fn test_message_id_ignores_publisher() {
+        let message = |source: PeerId, topic: &str| Message {
+            source: Some(source),
+            data: b"frame".to_vec(),
+            sequence_number: Some(1),
+            topic: TopicHash::from_raw(topic),
+        };
+        let topic = role_topic("translator");
+        assert_eq!(topic, "maple/role/translator");
+        assert_eq!(
+            message_id(&message(PeerId::random(), &topic)),
+            message_id(&message(PeerId::random(), &topic))
+        );
+        assert_ne!(
+            message_id(&message(PeerId::random(), &topic)),
+            message_id(&message(PeerId::random(), &mall_topic("exp-1")))
+        );
+    }
+}
@@ -0,0 +1,346 @@
+// Content-addressed, resumable transfer of `.map` files and other blobs between MAP peers
+// © 2025 Finalverse Inc. All rights reserved.
+
+use crate::{MapCommand, MapProtocol};
+use async_trait::async_trait;
+use futures::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use libp2p::request_response::Codec;
+use libp2p::{PeerId, StreamProtocol};
+use maple_ual::{Mode, UalMessage};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::io::{self, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt as _, AsyncSeekExt, AsyncWriteExt as _};
+use tokio::sync::oneshot;
+
+/// Protocol for fetching blob chunks from a peer
+pub const BLOB_PROTOCOL: StreamProtocol = StreamProtocol::new("/maple/blob/1.0.0");
+
+/// Bytes requested per chunk
+pub const CHUNK_SIZE: u32 = 256 * 1024;
+
+/// Largest chunk a peer will serve or accept
+pub const MAX_CHUNK_SIZE: u32 = 1024 * 1024;
+
+/// Action of the broadcast announcing a shared `.map` file
+pub const MAP_FILE_ACTION: &str = "map.file";
+
+/// How long a download waits before asking again for a chunk the peer was too busy to serve
+const BUSY_RETRY_DELAY: Duration = Duration::from_millis(250);
+
+/// Suffix of partially downloaded files, kept so a later fetch resumes where this one stopped
+pub const PARTIAL_SUFFIX: &str = ".part";
+
+/// Progress of blob downloads, reported on `MapProtocol::transfer_events`
+#[derive(Debug, Clone, PartialEq)]
+pub enum TransferEvent {
+    Progress { hash: String, peer: PeerId, received: u64, total: u64 },
+    Completed { hash: String, peer: PeerId, path: PathBuf },
+    Failed { hash: String, peer: PeerId, error: String },
+}
+
+/// Payload of a `map.file` announcement
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MapFileAnnouncement {
+    pub hash: String, // BLAKE3 of the file contents, hex
+    pub name: String, // File name without directories, e.g., "logistics-bot.map"
+    pub size: u64,
+}
+
+/// Asks for `len` bytes of the blob with `hash`, starting at `offset`
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct ChunkRequest {
+    pub hash: [u8; 32],
+    pub offset: u64,
+    pub len: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum ChunkResponse {
+    Chunk { total: u64, data: Vec<u8> }, // Empty data means the offset is at or past the end
+    NotFound,
+    Busy, // The peer asked for chunks faster than its rate limit; retry later
+}
+
+/// Request: hash | offset u64 | len u32. Response: status u8 | total u64 | data to end of stream
+#[derive(Debug, Clone, Default)]
+pub(crate) struct BlobCodec;
+
+const STATUS_OK: u8 = 0;
+const STATUS_NOT_FOUND: u8 = 1;
+const STATUS_BUSY: u8 = 2;
+
+#[async_trait]
+impl Codec for BlobCodec {
+    type Protocol = StreamProtocol;
+    type Request = ChunkRequest;
+    type Response = ChunkResponse;
+
+    async fn read_request<T>(&mut self, _: &StreamProtocol, io: &mut T) -> io::Result<ChunkRequest>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        let mut buf = [0u8; 44];
+        io.read_exact(&mut buf).await?;
+        let request = ChunkRequest {
+            hash: buf[..32].try_into().expect("32 bytes"),
+            offset: u64::from_be_bytes(buf[32..40].try_into().expect("8 bytes")),
+            len: u32::from_be_bytes(buf[40..].try_into().expect("4 bytes")),
+        };
+        if request.len > MAX_CHUNK_SIZE {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "chunk larger than MAX_CHUNK_SIZE"));
+        }
+        Ok(request)
+    }
+
+    async fn read_response<T>(&mut self, _: &StreamProtocol, io: &mut T) -> io::Result<ChunkResponse>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        let mut status = [0u8; 1];
+        io.read_exact(&mut status).await?;
+        match status[0] {
+            STATUS_NOT_FOUND => return Ok(ChunkResponse::NotFound),
+            STATUS_BUSY => return Ok(ChunkResponse::Busy),
+            _ => {}
+        }
+        let mut total = [0u8; 8];
+        io.read_exact(&mut total).await?;
+        let mut data = Vec::new();
+        io.take(MAX_CHUNK_SIZE as u64).read_to_end(&mut data).await?;
+        Ok(ChunkResponse::Chunk {
+            total: u64::from_be_bytes(total),
+            data,
+        })
+    }
+
+    async fn write_request<T>(&mut self, _: &StreamProtocol, io: &mut T, req: ChunkRequest) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        io.write_all(&req.hash).await?;
+        io.write_all(&req.offset.to_be_bytes()).await?;
+        io.write_all(&req.len.to_be_bytes()).await?;
+        io.close().await
+    }
+
+    async fn write_response<T>(&mut self, _: &StreamProtocol, io: &mut T, res: ChunkResponse) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        match res {
+            ChunkResponse::NotFound => io.write_all(&[STATUS_NOT_FOUND]).await?,
+            ChunkResponse::Busy => io.write_all(&[STATUS_BUSY]).await?,
+            ChunkResponse::Chunk { total, data } => {
+                io.write_all(&[STATUS_OK]).await?;
+                io.write_all(&total.to_be_bytes()).await?;
+                io.write_all(&data).await?;
+            }
+        }
+        io.close().await
+    }
+}
+
+/// BLAKE3 of a file's contents, read in chunks so large files need not fit in memory
+pub async fn hash_file(path: &Path) -> io::Result<[u8; 32]> {
+    let mut file = tokio::fs::File::open(path).await?;
+    let mut hasher = blake3::Hasher::new();
+    let mut buf = vec![0u8; CHUNK_SIZE as usize];
+    loop {
+        let n = file.read(&mut buf).await?;
+        if n == 0 {
+            return Ok(*hasher.finalize().as_bytes());
+        }
+        hasher.update(&buf[..n]);
+    }
+}
+
+/// Reads the chunk a peer asked for from a shared file
+pub(crate) async fn read_chunk(path: &Path, offset: u64, len: u32) -> io::Result<ChunkResponse> {
+    let mut file = tokio::fs::File::open(path).await?;
+    let total = file.metadata().await?.len();
+    let len = (len as u64).min(total.saturating_sub(offset));
+    let mut data = vec![0u8; len as usize];
+    file.seek(SeekFrom::Start(offset)).await?;
+    file.read_exact(&mut data).await?;
+    Ok(ChunkResponse::Chunk { total, data })
+}
+
+fn parse_hash(hash: &str) -> Result<[u8; 32], Box<dyn Error>> {
+    let bytes = hex::decode(hash)?;
+    Ok(bytes
+        .try_into()
+        .map_err(|_| format!("Content hash must be 32 bytes: {}", hash))?)
+}
+
+impl MapProtocol {
+    /// Serves a file to peers by its content hash and announces it on the DHT, returning the hash
+    pub async fn share_file(&self, path: &str) -> Result<String, Box<dyn Error>> {
+        let hash = hash_file(Path::new(path)).await?;
+        self.command_tx
+            .send(MapCommand::ShareBlob(hash, PathBuf::from(path)))
+            .await?;
+        let hash = hex::encode(hash);
+        self.start_providing(&hash).await?;
+        Ok(hash)
+    }
+
+    /// Shares a .map file and broadcasts its hash so peers can fetch it
+    pub async fn broadcast_map_file(&self, path: &str) -> Result<String, Box<dyn Error>> {
+        let hash = self.share_file(path).await?;
+        let announcement = MapFileAnnouncement {
+            hash: hash.clone(),
+            name: Path::new(path)
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            size: tokio::fs::metadata(path).await?.len(),
+        };
+        self.broadcast(UalMessage::new(MAP_FILE_ACTION, Mode::Json).with_json_payload(&announcement)?)
+            .await?;
+        Ok(hash)
+    }
+
+    /// Downloads a blob from whichever provider of its hash answers first
+    pub async fn fetch(&self, hash: &str, dest: &str) -> Result<(), Box<dyn Error>> {
+        let mut last_error: Box<dyn Error> = format!("No peer provides {}", hash).into();
+        for peer in self.get_providers(hash).await? {
+            if peer == self.local_peer_id {
+                continue;
+            }
+            match self.fetch_from(peer, hash, dest).await {
+                Ok(()) => return Ok(()),
+                Err(e) => last_error = e,
+            }
+        }
+        Err(last_error)
+    }
+
+    /// Downloads a blob from one peer into `dest`, resuming a partial download and verifying the hash
+    pub async fn fetch_from(&self, peer: PeerId, hash: &str, dest: &str) -> Result<(), Box<dyn Error>> {
+        let result = self.download(peer, hash, dest).await;
+        let event = match &result {
+            Ok(()) => TransferEvent::Completed {
+                hash: hash.to_string(),
+                peer,
+                path: PathBuf::from(dest),
+            },
+            Err(e) => TransferEvent::Failed {
+                hash: hash.to_string(),
+                peer,
+                error: e.to_string(),
+            },
+        };
+        let _ = self.transfer_tx.send(event);
+        result
+    }
+
+    async fn download(&self, peer: PeerId, hash: &str, dest: &str) -> Result<(), Box<dyn Error>> {
+        let expected = parse_hash(hash)?;
+        let partial = format!("{}{}", dest, PARTIAL_SUFFIX);
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&partial)
+            .await?;
+        let mut received = file.metadata().await?.len();
+        loop {
+            let (tx, rx) = oneshot::channel();
+            let request = ChunkRequest {
+                hash: expected,
+                offset: received,
+                len: CHUNK_SIZE,
+            };
+            self.command_tx
+                .send(MapCommand::FetchChunk(peer, request, tx))
+                .await?;
+            let (total, data) = match rx.await?? {
+                ChunkResponse::Chunk { total, data } => (total, data),
+                ChunkResponse::NotFound => return Err(format!("Peer {} does not have {}", peer, hash).into()),
+                ChunkResponse::Busy => {
+                    tokio::time::sleep(BUSY_RETRY_DELAY).await;
+                    continue;
+                }
+            };
+            if received > total {
+                // A stale partial file longer than the blob can never verify
+                drop(file);
+                tokio::fs::remove_file(&partial).await?;
+                return Err(format!("Partial download of {} is longer than the blob", hash).into());
+            }
+            file.write_all(&data).await?;
+            received += data.len() as u64;
+            let _ = self.transfer_tx.send(TransferEvent::Progress {
+                hash: hash.to_string(),
+                peer,
+                received,
+                total,
+            });
+            if received == total {
+                break;
+            }
+            if data.is_empty() {
+                return Err(format!("Peer {} stopped sending {} at {} of {} bytes", peer, hash, received, total).into());
+            }
+        }
+        file.flush().await?;
+        drop(file);
+
+        if hash_file(Path::new(&partial)).await? != expected {
+            tokio::fs::remove_file(&partial).await?;
+            return Err(format!("Downloaded data does not match {}", hash).into());
+        }
+        tokio::fs::rename(&partial, dest).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MapConfig;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_fetch_resumes_and_verifies() {
+        let source = "test_transfer_source.map";
+        let dest = "test_transfer_dest.map";
+        let content: Vec<u8> = (0..CHUNK_SIZE * 2 + 17).map(|i| (i % 251) as u8).collect();
+        tokio::fs::write(source, &content).await.unwrap();
+        // A previous attempt already fetched the first 1000 bytes
+        tokio::fs::write(format!("{}{}", dest, PARTIAL_SUFFIX), &content[..1000]).await.unwrap();
+
+        // Alice serves one chunk a second, so bob is told to retry for all but the first
+        let alice = MapProtocol::new(MapConfig::new("/ip4/127.0.0.1/tcp/0").with_chunk_rate_limit(1)).await.unwrap();
+        let bob = MapProtocol::new(MapConfig::new("/ip4/127.0.0.1/tcp/0")).await.unwrap();
+        let hash = alice.share_file(source).await.unwrap();
+        let addr = loop {
+            if let Some(addr) = alice.listen_addrs().await.unwrap().pop() {
+                break addr;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        };
+        bob.dial(addr).await.unwrap();
+        while !bob.connected_peers().await.unwrap().contains(&alice.local_peer_id()) {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        let mut events = bob.transfer_events();
+        let started = std::time::Instant::now();
+        bob.fetch_from(alice.local_peer_id(), &hash, dest).await.unwrap();
+        assert_eq!(tokio::fs::read(dest).await.unwrap(), content);
+        assert!(started.elapsed() >= Duration::from_secs(1));
+        let first = events.recv().await.unwrap();
+        assert!(matches!(first, TransferEvent::Progress { received, .. } if received == 1000 + CHUNK_SIZE as u64));
+
+        let unknown = hex::encode([7u8; 32]);
+        assert!(bob.fetch_from(alice.local_peer_id(), &unknown, dest).await.is_err());
+
+        for path in [source, dest] {
+            tokio::fs::remove_file(path).await.unwrap();
+        }
+        let _ = tokio::fs::remove_file(format!("{}{}", dest, PARTIAL_SUFFIX)).await;
+    }
+}
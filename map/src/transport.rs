@@ -0,0 +1,125 @@
+// Transports a MAP node can listen and dial on: TCP, QUIC and WebSocket
+// © 2025 Finalverse Inc. All rights reserved.
+
+use libp2p::core::muxing::StreamMuxerBox;
+use libp2p::core::transport::{Boxed, OptionalTransport};
+use libp2p::core::upgrade::Version;
+use libp2p::identity::Keypair;
+use libp2p::{dns, noise, quic, tcp, websocket, yamux, PeerId, Transport};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+
+/// A transport a node can be configured with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MapTransport {
+    Tcp, // e.g., "/ip4/0.0.0.0/tcp/4001"
+    Quic, // e.g., "/ip4/0.0.0.0/udp/4001/quic-v1"
+    WebSocket, // e.g., "/ip4/0.0.0.0/tcp/4002/ws", for browsers and HTTP proxies
+}
+
+/// Every transport, the default
+pub const ALL_TRANSPORTS: [MapTransport; 3] = [MapTransport::Tcp, MapTransport::Quic, MapTransport::WebSocket];
+
+type MapStream = Boxed<(PeerId, StreamMuxerBox)>;
+
+/// Combines the selected transports, resolving `/dns` addresses for all of them
+pub(crate) fn build(key: &Keypair, transports: &[MapTransport]) -> Result<MapStream, Box<dyn Error + Send + Sync>> {
+    if transports.is_empty() {
+        return Err("At least one transport must be enabled".into());
+    }
+    let enabled = |transport| transports.contains(&transport);
+    let quic = enabled(MapTransport::Quic).then(|| quic_transport(key));
+    let websocket = enabled(MapTransport::WebSocket).then(|| websocket_transport(key)).transpose()?;
+    let tcp = enabled(MapTransport::Tcp).then(|| tcp_transport(key)).transpose()?;
+    // QUIC and WebSocket first, as plain TCP would otherwise try `/tcp/.../ws` addresses
+    let combined = optional(quic)
+        .or_transport(optional(websocket))
+        .map(|either, _| either.into_inner())
+        .or_transport(optional(tcp))
+        .map(|either, _| either.into_inner())
+        .boxed();
+    Ok(dns::tokio::Transport::system(combined)?.boxed())
+}
+
+fn optional(transport: Option<MapStream>) -> OptionalTransport<MapStream> {
+    transport.map_or_else(OptionalTransport::none, OptionalTransport::some)
+}
+
+/// QUIC brings its own encryption and stream multiplexing
+fn quic_transport(key: &Keypair) -> MapStream {
+    quic::tokio::Transport::new(quic::Config::new(key))
+        .map(|(peer_id, conn), _| (peer_id, StreamMuxerBox::new(conn)))
+        .boxed()
+}
+
+/// TCP secured with noise and multiplexed with yamux
+fn tcp_transport(key: &Keypair) -> Result<MapStream, noise::Error> {
+    Ok(tcp::tokio::Transport::new(tcp::Config::default())
+        .upgrade(Version::V1Lazy)
+        .authenticate(noise::Config::new(key)?)
+        .multiplex(yamux::Config::default())
+        .map(|(peer_id, conn), _| (peer_id, StreamMuxerBox::new(conn)))
+        .boxed())
+}
+
+/// WebSocket over TCP, then the same noise and yamux upgrades as plain TCP
+fn websocket_transport(key: &Keypair) -> Result<MapStream, noise::Error> {
+    Ok(websocket::WsConfig::new(tcp::tokio::Transport::new(tcp::Config::default()))
+        .upgrade(Version::V1Lazy)
+        .authenticate(noise::Config::new(key)?)
+        .multiplex(yamux::Config::default())
+        .map(|(peer_id, conn), _| (peer_id, StreamMuxerBox::new(conn)))
+        .boxed())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MapConfig, MapProtocol};
+    use libp2p::multiaddr::Protocol;
+    use libp2p::Multiaddr;
+    use maple_ual::{Mode, UalMessage};
+    use std::time::Duration;
+
+    /// Sends a message from a node restricted to one transport to a node listening on all of them
+    async fn send_over(transport: MapTransport, listen_addr: &str, server: &MapProtocol, addr: Multiaddr) {
+        let config = MapConfig::new(listen_addr).with_mdns(false).with_transports(&[transport]);
+        let client = MapProtocol::new(config).await.unwrap();
+        let mut incoming = server.incoming();
+        client.dial(addr.with(Protocol::P2p(server.local_peer_id()))).await.unwrap();
+        let msg = UalMessage::new("ping", Mode::ByteLevel).with_byte_payload(vec![1]);
+        tokio::time::timeout(Duration::from_secs(10), async {
+            while client.send_message(server.local_peer_id(), msg.clone()).await.is_err() {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+        })
+        .await
+        .unwrap();
+        let (from, received) = incoming.recv().await.unwrap();
+        assert_eq!((from, received.id()), (client.local_peer_id(), msg.id()));
+    }
+
+    #[tokio::test]
+    async fn test_quic_and_websocket_carry_messages() {
+        let config = MapConfig::new("/ip4/127.0.0.1/tcp/0")
+            .with_listen_addr("/ip4/127.0.0.1/udp/0/quic-v1")
+            .with_listen_addr("/ip4/127.0.0.1/tcp/0/ws")
+            .with_mdns(false);
+        let server = MapProtocol::new(config).await.unwrap();
+        let addrs = loop {
+            let addrs = server.listen_addrs().await.unwrap();
+            if addrs.len() == 3 {
+                break addrs;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        };
+        let find = |protocol: &str| addrs.iter().find(|a| a.to_string().contains(protocol)).unwrap().clone();
+        send_over(MapTransport::Quic, "/ip4/127.0.0.1/udp/0/quic-v1", &server, find("/quic-v1")).await;
+        send_over(MapTransport::WebSocket, "/ip4/127.0.0.1/tcp/0/ws", &server, find("/ws")).await;
+
+        let tcp_only = MapConfig::new("/ip4/127.0.0.1/udp/0/quic-v1").with_transports(&[MapTransport::Tcp]);
+        assert!(MapProtocol::new(tcp_only).await.is_err());
+        assert!(MapProtocol::new(MapConfig::new("/ip4/127.0.0.1/tcp/0").with_transports(&[])).await.is_err());
+    }
+}
@@ -1,36 +1,98 @@
 // MAPLE Registry Service for agent registration and DID management
 // © 2025 Finalverse Inc. All rights reserved.
 
-use maple_agents::AgentConfig;
+use maple_agents::{AgentConfig, Capability, DnaFile, TrustStore};
 use maple_map::{MapConfig, MapProtocol};
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
 use std::error::Error;
-use tokio::sync::mpsc;
+use tokio::sync::{mpsc, oneshot};
 use uuid::Uuid;
 
 /// Configuration for the MRS
 #[derive(Debug, Serialize, Deserialize)]
 pub struct MrsConfig {
-    map_config: MapConfig, // Configuration for underlying MAP Protocol
+    pub map_config: MapConfig, // Configuration for underlying MAP Protocol
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RegisteredAgent {
-    did: String, // Decentralized Identifier, e.g., "did:maple:agent:uuid"
-    config: AgentConfig,
+    pub did: String, // Decentralized Identifier, e.g., "did:maple:agent:uuid"
+    pub config: AgentConfig,
 }
 
 /// MAPLE Registry Service instance
 pub struct Mrs {
-    map: MapProtocol,
-    agents: Vec<RegisteredAgent>,
+    #[allow(dead_code)]
+    map: MapProtocol, // Keeps the registry's MAP node running
     command_tx: mpsc::Sender<MrsCommand>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug)]
 pub enum MrsCommand {
-    Register(AgentConfig), // Register a new agent
-    GetAgent(String), // Retrieve agent by DID
+    Register(String, AgentConfig), // Register an agent under a DID
+    GetAgent(String, oneshot::Sender<Option<RegisteredAgent>>), // Retrieve agent by DID
+    FindCapable(String, u32, oneshot::Sender<Vec<RegisteredAgent>>), // Agents serving an action at a minimum version
+}
+
+/// Registered agents and the actions they declare
+#[derive(Debug, Default)]
+pub struct Registry {
+    agents: HashMap<String, AgentConfig>,
+    capabilities: HashMap<String, BTreeMap<String, Capability>>, // action -> DID -> capability
+}
+
+impl Registry {
+    /// Adds or replaces an agent, reindexing its capabilities
+    pub fn insert(&mut self, did: &str, config: AgentConfig) {
+        self.remove(did);
+        for capability in &config.capabilities {
+            self.capabilities
+                .entry(capability.action.clone())
+                .or_default()
+                .insert(did.to_string(), capability.clone());
+        }
+        self.agents.insert(did.to_string(), config);
+    }
+
+    /// Removes an agent and its capabilities
+    pub fn remove(&mut self, did: &str) -> Option<AgentConfig> {
+        let config = self.agents.remove(did)?;
+        for capability in &config.capabilities {
+            if let Some(providers) = self.capabilities.get_mut(&capability.action) {
+                providers.remove(did);
+                if providers.is_empty() {
+                    self.capabilities.remove(&capability.action);
+                }
+            }
+        }
+        Some(config)
+    }
+
+    /// Looks up an agent by DID
+    pub fn get(&self, did: &str) -> Option<RegisteredAgent> {
+        self.agents.get(did).map(|config| RegisteredAgent {
+            did: did.to_string(),
+            config: config.clone(),
+        })
+    }
+
+    /// Agents declaring the action at `min_version` or above, cheapest first
+    pub fn find_capable(&self, action: &str, min_version: u32) -> Vec<RegisteredAgent> {
+        let Some(providers) = self.capabilities.get(action) else {
+            return Vec::new();
+        };
+        let mut matches: Vec<_> = providers
+            .iter()
+            .filter(|(_, capability)| capability.serves(action, min_version))
+            .collect();
+        // Undeclared cost sorts last; ties keep DID order
+        matches.sort_by_key(|(_, capability)| capability.cost.unwrap_or(u32::MAX));
+        matches
+            .into_iter()
+            .filter_map(|(did, _)| self.get(did))
+            .collect()
+    }
 }
 
 impl Mrs {
@@ -38,54 +100,72 @@ impl Mrs {
     pub async fn new(config: MrsConfig) -> Result<Self, Box<dyn Error>> {
         let map = MapProtocol::new(config.map_config).await?;
         let (command_tx, mut command_rx) = mpsc::channel(100);
-        let mut agents = Vec::new();
 
         tokio::spawn(async move {
+            let mut registry = Registry::default();
             while let Some(cmd) = command_rx.recv().await {
                 match cmd {
-                    MrsCommand::Register(config) => {
-                        let did = format!("did:maple:agent:{}", Uuid::new_v4());
-                        let agent = RegisteredAgent { did: did.clone(), config };
-                        agents.push(agent);
+                    MrsCommand::Register(did, config) => {
+                        registry.insert(&did, config);
                         println!("Registered agent: {}", did);
                     }
-                    MrsCommand::GetAgent(did) => {
-                        if let Some(agent) = agents.iter().find(|a| a.did == did) {
-                            println!("Found agent: {:?}", agent);
-                        }
+                    MrsCommand::GetAgent(did, reply) => {
+                        let _ = reply.send(registry.get(&did));
+                    }
+                    MrsCommand::FindCapable(action, min_version, reply) => {
+                        let _ = reply.send(registry.find_capable(&action, min_version));
                     }
                 }
             }
         });
 
-        Ok(Mrs {
-            map,
-            agents,
-            command_tx,
-        })
+        Ok(Mrs { map, command_tx })
     }
 
     /// Registers a new agent and returns its DID
     pub async fn register_agent(&self, config: AgentConfig) -> Result<String, Box<dyn Error>> {
         let did = format!("did:maple:agent:{}", Uuid::new_v4());
         self.command_tx
-            .send(MrsCommand::Register(config))
+            .send(MrsCommand::Register(did.clone(), config))
+            .await?;
+        Ok(did)
+    }
+
+    /// Registers signed agent DNA after verifying its publisher, returning its DID
+    pub async fn register_dna(&self, dna: &DnaFile, trust: &TrustStore) -> Result<String, Box<dyn Error>> {
+        trust.verify(dna)?;
+        let config = AgentConfig::from_dna(dna)?;
+        let did = dna.did().to_string();
+        self.command_tx
+            .send(MrsCommand::Register(did.clone(), config))
             .await?;
         Ok(did)
     }
 
     /// Retrieves an agent by DID
     pub async fn get_agent(&self, did: String) -> Result<RegisteredAgent, Box<dyn Error>> {
-        let (tx, rx) = tokio::sync::oneshot::channel();
-        self.command_tx.send(MrsCommand::GetAgent(did.clone())).await?;
-        // Simulate retrieval (replace with actual storage lookup)
-        let agent = self
-            .agents
-            .iter()
-            .find(|a| a.did == did)
-            .ok_or("Agent not found")?;
-        tx.send(agent.clone()).unwrap();
-        rx.await.map_err(|e| Box::new(e) as Box<dyn Error>)
+        let (tx, rx) = oneshot::channel();
+        self.command_tx.send(MrsCommand::GetAgent(did.clone(), tx)).await?;
+        Ok(rx.await?.ok_or_else(|| format!("Agent not found: {}", did))?)
+    }
+
+    /// Finds agents that can handle an action, cheapest first
+    pub async fn find_capable(&self, action: &str, min_version: u32) -> Result<Vec<RegisteredAgent>, Box<dyn Error>> {
+        let (tx, rx) = oneshot::channel();
+        self.command_tx
+            .send(MrsCommand::FindCapable(action.to_string(), min_version, tx))
+            .await?;
+        Ok(rx.await?)
+    }
+
+    /// Resolves "any agent that can handle `action`" to the preferred DID
+    pub async fn resolve_action(&self, action: &str) -> Result<String, Box<dyn Error>> {
+        let agents = self.find_capable(action, 1).await?;
+        let agent = agents
+            .into_iter()
+            .next()
+            .ok_or_else(|| format!("No agent registered for action: {}", action))?;
+        Ok(agent.did)
     }
 }
 
@@ -95,16 +175,32 @@ mod tests {
 
     #[tokio::test]
     async fn test_register_agent() {
-        let map_config = MapConfig {
-            listen_addr: "/ip4/127.0.0.1/tcp/0".to_string(),
-        };
+        let map_config = MapConfig::new("/ip4/127.0.0.1/tcp/0");
         let config = MrsConfig { map_config };
         let mrs = Mrs::new(config).await.unwrap();
-        let agent_config = AgentConfig {
-            name: "test-agent".to_string(),
-            // Add more fields as needed
-        };
+        let agent_config = AgentConfig::new("test-agent", "test");
         let did = mrs.register_agent(agent_config).await.unwrap();
         assert!(did.starts_with("did:maple:agent:"));
+        assert_eq!(mrs.get_agent(did.clone()).await.unwrap().did, did);
+    }
+
+    #[tokio::test]
+    async fn test_find_capable_prefers_cheapest_matching_version() {
+        let map_config = MapConfig::new("/ip4/127.0.0.1/tcp/0");
+        let mrs = Mrs::new(MrsConfig { map_config }).await.unwrap();
+        let pricey = AgentConfig::new("pricey", "planner")
+            .with_capability(Capability::new("route.plan").with_version(2).with_cost(10));
+        let cheap = AgentConfig::new("cheap", "planner")
+            .with_capability(Capability::new("route.plan").with_version(2).with_cost(1));
+        let stale = AgentConfig::new("stale", "planner").with_capability(Capability::new("route.plan"));
+        mrs.register_agent(pricey).await.unwrap();
+        let cheap_did = mrs.register_agent(cheap).await.unwrap();
+        mrs.register_agent(stale).await.unwrap();
+
+        let found = mrs.find_capable("route.plan", 2).await.unwrap();
+        let names: Vec<_> = found.iter().map(|a| a.config.name.as_str()).collect();
+        assert_eq!(names, vec!["cheap", "pricey"]);
+        assert_eq!(mrs.resolve_action("route.plan").await.unwrap(), cheap_did);
+        assert!(mrs.find_capable("route.eta", 1).await.unwrap().is_empty());
     }
 }
\ No newline at end of file
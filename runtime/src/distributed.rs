@@ -10,6 +10,9 @@ pub async fn start_distributed(nodes: usize) -> Result<Runtime, Box<dyn Error>>
         mode: RuntimeMode::Distributed,
         map_listen_addr: "/ip4/0.0.0.0/tcp/0".to_string(),
         db_path: "maple_distributed_db".to_string(),
+        trust_store_path: None,
+        map_key_file: Some(maple_map::DEFAULT_KEY_FILE.to_string()),
+        remote_peers: None,
     };
     let runtime = Runtime::new(config).await?;
     println!("Started distributed runtime with {} nodes", nodes);
@@ -11,6 +11,9 @@ pub async fn start_enterprise(config_path: &str) -> Result<Runtime, Box<dyn Erro
         mode: RuntimeMode::Enterprise,
         map_listen_addr: "/ip4/0.0.0.0/tcp/0".to_string(),
         db_path: "maple_enterprise_db".to_string(),
+        trust_store_path: None,
+        map_key_file: Some(maple_map::DEFAULT_KEY_FILE.to_string()),
+        remote_peers: None,
     };
     let runtime = Runtime::new(config).await?;
     println!("Started enterprise runtime with config: {}", config_path);
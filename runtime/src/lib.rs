@@ -1,13 +1,36 @@
 // Runtime environment core logic for MAPLE nodes
 // © 2025 Finalverse Inc. All rights reserved.
 
-use maple_agents::{Agent, AgentConfig};
-use maple_map::{MapConfig, MapProtocol};
+use maple_agents::{
+    Agent, AgentConfig, AgentControl, AgentMemory, AgentState, BehaviourRegistry, Checkpoint, CheckpointStore,
+    DnaFile, EpisodicLog, MailboxStats, SectionKind, SemanticMemory, TrustStore, DEFAULT_REQUEST_TIMEOUT,
+};
+use maple_core::{AgentCommand, MapleCore};
+use maple_ual::{ActionSchema, SchemaLease, SchemaRegistry, UalMessage};
+use maple_map::{MapConfig, MapProtocol, PeerId};
 use maple_mrs::{Mrs, MrsConfig};
+use maple_vectordb::VectorDb;
 use mapledb::MapleDb;
 use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
-use tokio::sync::mpsc;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{broadcast, mpsc, oneshot, watch};
+
+pub mod lifecycle;
+pub mod supervisor;
+
+pub use lifecycle::{LifecycleError, LifecycleEvent};
+pub use supervisor::{RestartStrategy, SupervisionError, SupervisorSpec, ROOT_SUPERVISOR};
+
+use supervisor::{Decision, SupervisionTree};
+
+mod distributed;
+mod enterprise;
+
+pub use distributed::start_distributed;
+pub use enterprise::start_enterprise;
 
 /// Configuration for the runtime
 #[derive(Debug, Serialize, Deserialize)]
@@ -15,6 +38,11 @@ pub struct RuntimeConfig {
     pub mode: RuntimeMode,
     pub map_listen_addr: String, // e.g., "/ip4/0.0.0.0/tcp/0"
     pub db_path: String, // Path to MapleDB storage
+    pub trust_store_path: Option<String>, // JSON trust store of accepted DNA publishers
+    #[serde(default)]
+    pub map_key_file: Option<String>, // Node identity key file; None gives the node a new PeerId every start
+    #[serde(default)]
+    pub remote_peers: Option<Vec<String>>, // PeerIds whose MAP messages reach local agents; None accepts any connected peer
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -23,51 +51,219 @@ pub enum RuntimeMode {
     Enterprise,
 }
 
+/// How often changed agent state is checkpointed into MapleDB
+const CHECKPOINT_INTERVAL: Duration = Duration::from_secs(10);
+
 /// Runtime instance managing agents and network
 pub struct Runtime {
-    map: MapProtocol,
+    #[allow(dead_code)]
+    map: MapProtocol, // Keeps the node's MAP event loop running
+    #[allow(dead_code)]
     mrs: Mrs,
-    db: MapleDb,
-    agents: Vec<Agent>,
+    #[allow(dead_code)]
+    db: Arc<MapleDb>,
+    vectors: Arc<VectorDb>,
+    trust: TrustStore,
     command_tx: mpsc::Sender<RuntimeCommand>,
+    events_tx: broadcast::Sender<LifecycleEvent>,
 }
 
 #[derive(Debug)]
 pub enum RuntimeCommand {
     SpawnAgent(String), // DID of agent to spawn
+    SpawnDna(DnaFile, AgentConfig, String, oneshot::Sender<Result<(), SupervisionError>>), // Verified DNA, its config and the supervisor to place it under
+    AddSupervisor(SupervisorSpec, String, oneshot::Sender<Result<(), SupervisionError>>), // Spec and parent name
+    Restart(Vec<String>), // DIDs to stop if running and start again from their checkpoints
+    Relaunch(Vec<String>), // DIDs whose run loops have exited for a restart and can start again
+    GetAgent(String, oneshot::Sender<Option<Agent>>), // Look up a running agent by DID
+    FindCapable(String, oneshot::Sender<Option<Agent>>), // Cheapest running agent declaring an action
+    Control(String, AgentControl, oneshot::Sender<Result<AgentState, LifecycleError>>), // Move an agent to another state
+    ListAgents(oneshot::Sender<Vec<(String, AgentState)>>), // DIDs and lifecycle states of known agents
+    MailboxStats(oneshot::Sender<Vec<(String, MailboxStats)>>), // Queue depth and overflow counters per agent
     Shutdown,
 }
 
 impl Runtime {
-    /// Initializes a new runtime instance
+    /// Initializes a new runtime instance with the default agent behaviours
     pub async fn new(config: RuntimeConfig) -> Result<Self, Box<dyn Error>> {
-        let map_config = MapConfig {
-            listen_addr: config.map_listen_addr.clone(),
+        Self::with_behaviours(config, BehaviourRegistry::new()).await
+    }
+
+    /// Initializes a new runtime instance that picks agent behaviours by role
+    pub async fn with_behaviours(
+        config: RuntimeConfig,
+        behaviours: BehaviourRegistry,
+    ) -> Result<Self, Box<dyn Error>> {
+        let mut map_config = MapConfig::new(&config.map_listen_addr);
+        map_config.key_file = config.map_key_file.clone();
+        let db = Arc::new(MapleDb::new(&config.db_path)?);
+        let map = MapProtocol::with_peer_store(map_config, db.clone()).await?;
+        // The registry runs its own MAP node, which must not share this node's identity
+        let mrs = Mrs::new(MrsConfig {
+            map_config: MapConfig::new(&config.map_listen_addr),
+        })
+        .await?;
+        let vectors = Arc::new(VectorDb::new());
+        let trust = TrustStore::load_optional(config.trust_store_path.as_deref())?;
+        let remote_peers = match &config.remote_peers {
+            Some(peers) => Some(peers.iter().map(|peer| peer.parse()).collect::<Result<HashSet<PeerId>, _>>()?),
+            None => None,
         };
-        let map = MapProtocol::new(map_config).await?;
-        let mrs = Mrs::new(MrsConfig { map_config }).await?;
-        let db = MapleDb::new(&config.db_path)?;
 
         let (command_tx, mut command_rx) = mpsc::channel(100);
-        let mut agents = Vec::new();
+        let (events_tx, _) = broadcast::channel(lifecycle::LIFECYCLE_EVENT_CAPACITY);
+        let (failure_tx, mut failure_rx) = mpsc::unbounded_channel();
+        let restart_tx = command_tx.downgrade();
+        tokio::spawn(route_incoming(map.clone(), remote_peers, command_tx.downgrade()));
+        let mut agents: Vec<Agent> = Vec::new();
+        let launcher = Launcher {
+            map: map.clone(),
+            behaviours,
+            checkpoints: CheckpointStore::new(db.clone()),
+            stores: MemoryStores {
+                db: db.clone(),
+                vectors: vectors.clone(),
+            },
+            events: events_tx.clone(),
+            failures: failure_tx,
+        };
 
         tokio::spawn(async move {
-            while let Some(cmd) = command_rx.recv().await {
-                match cmd {
-                    RuntimeCommand::SpawnAgent(did) => {
-                        // Placeholder: Load from MRS or .map file
-                        let config = AgentConfig {
-                            name: format!("agent-{}", did),
-                            role: "default".to_string(),
-                        };
-                        let agent = Agent::new(config);
-                        agents.push(agent);
-                        println!("Spawned agent with DID: {}", did);
-                    }
-                    RuntimeCommand::Shutdown => {
-                        println!("Shutting down runtime...");
-                        break;
+            let mut snapshots: HashMap<String, watch::Receiver<Checkpoint>> = HashMap::new();
+            let mut specs: HashMap<String, (AgentConfig, Vec<u8>)> = HashMap::new(); // Initial config and state per DID
+            let mut tree = SupervisionTree::new(SupervisorSpec::new(ROOT_SUPERVISOR, RestartStrategy::OneForOne));
+            let mut checkpoint_timer = tokio::time::interval(CHECKPOINT_INTERVAL);
+            loop {
+                tokio::select! {
+                    cmd = command_rx.recv() => match cmd {
+                        Some(RuntimeCommand::SpawnAgent(did)) => {
+                            // Placeholder: Load from MRS or .map file
+                            let config = AgentConfig::new(&format!("agent-{}", did), "default");
+                            tree.add_agent(&did, ROOT_SUPERVISOR).expect("root supervisor exists");
+                            let agent = launcher.start(&did, config.clone(), Vec::new());
+                            specs.insert(did.clone(), (config, Vec::new()));
+                            snapshots.insert(did.clone(), agent.snapshots());
+                            agents.push(agent);
+                            println!("Spawned agent with DID: {}", did);
+                        }
+                        Some(RuntimeCommand::SpawnDna(dna, config, supervisor, reply)) => {
+                            if let Err(e) = tree.add_agent(dna.did(), &supervisor) {
+                                let _ = reply.send(Err(e));
+                                continue;
+                            }
+                            let state = dna.section(SectionKind::State).unwrap_or_default().to_vec();
+                            let agent = launcher.start(dna.did(), config.clone(), state.clone());
+                            println!("Spawned agent with DID: {}", agent.did());
+                            specs.insert(agent.did().to_string(), (config, state));
+                            snapshots.insert(agent.did().to_string(), agent.snapshots());
+                            agents.push(agent);
+                            let _ = reply.send(Ok(()));
+                        }
+                        Some(RuntimeCommand::AddSupervisor(spec, parent, reply)) => {
+                            let _ = reply.send(tree.add_supervisor(spec, &parent));
+                        }
+                        Some(RuntimeCommand::Restart(dids)) => {
+                            let stopping: Vec<Agent> = agents
+                                .iter()
+                                .filter(|a| dids.iter().any(|d| d == a.did()))
+                                .cloned()
+                                .collect();
+                            let restart_tx = restart_tx.clone();
+                            // Waiting for run loops to exit must not hold up other commands
+                            tokio::spawn(async move {
+                                stop_for_restart(&stopping).await;
+                                if let Some(tx) = restart_tx.upgrade() {
+                                    let _ = tx.send(RuntimeCommand::Relaunch(dids)).await;
+                                }
+                            });
+                        }
+                        Some(RuntimeCommand::Relaunch(dids)) => {
+                            for did in dids {
+                                let Some((config, state)) = specs.get(&did).cloned() else {
+                                    continue;
+                                };
+                                // A run loop that is still alive, e.g. one started by an earlier restart, is left alone
+                                let Some(slot) = agents
+                                    .iter()
+                                    .position(|a| a.did() == did && a.lifecycle().is_terminal())
+                                else {
+                                    continue;
+                                };
+                                // Persist the last state the old run loop published so the restart resumes from it
+                                if let Some(snapshot) = snapshots.get_mut(&did) {
+                                    save_checkpoint(&launcher.checkpoints, snapshot);
+                                }
+                                println!("Restarting agent {}", did);
+                                let agent = launcher.start(&did, config, state);
+                                snapshots.insert(did.clone(), agent.snapshots());
+                                agents[slot] = agent;
+                            }
+                        }
+                        Some(RuntimeCommand::GetAgent(did, reply)) => {
+                            let _ = reply.send(agents.iter().find(|a| a.did() == did).cloned());
+                        }
+                        Some(RuntimeCommand::FindCapable(action, reply)) => {
+                            let capable = agents
+                                .iter()
+                                .filter(|a| a.lifecycle() == AgentState::Running)
+                                .filter_map(|a| Some((a.config().capability(&action)?.cost.unwrap_or(u32::MAX), a)))
+                                .min_by_key(|(cost, _)| *cost)
+                                .map(|(_, a)| a.clone());
+                            let _ = reply.send(capable);
+                        }
+                        Some(RuntimeCommand::Control(did, control, reply)) => {
+                            match agents.iter().find(|a| a.did() == did).cloned() {
+                                // Waiting on the run loop must not hold up other commands
+                                Some(agent) => {
+                                    tokio::spawn(async move {
+                                        let _ = reply.send(lifecycle::apply_control(&agent, control).await);
+                                    });
+                                }
+                                None => {
+                                    let _ = reply.send(Err(LifecycleError::UnknownAgent(did)));
+                                }
+                            }
+                        }
+                        Some(RuntimeCommand::ListAgents(reply)) => {
+                            let _ = reply.send(agents.iter().map(|a| (a.did().to_string(), a.lifecycle())).collect());
+                        }
+                        Some(RuntimeCommand::MailboxStats(reply)) => {
+                            let _ = reply.send(agents.iter().map(|a| (a.did().to_string(), a.mailbox_stats())).collect());
+                        }
+                        Some(RuntimeCommand::Shutdown) | None => {
+                            println!("Shutting down runtime...");
+                            for agent in agents.iter().filter(|a| !a.lifecycle().is_terminal()) {
+                                agent.control(AgentControl::Drain);
+                            }
+                            save_checkpoints(&launcher.checkpoints, &mut snapshots);
+                            break;
+                        }
+                    },
+                    Some(did) = failure_rx.recv() => {
+                        // Ignore reports from run loops that have since been replaced
+                        if !agents.iter().any(|a| a.did() == did && a.lifecycle() == AgentState::Failed) {
+                            continue;
+                        }
+                        match tree.on_failure(&did, tokio::time::Instant::now()) {
+                            Decision::Restart { dids, after } => {
+                                let restart_tx = restart_tx.clone();
+                                tokio::spawn(async move {
+                                    tokio::time::sleep(after).await;
+                                    if let Some(tx) = restart_tx.upgrade() {
+                                        let _ = tx.send(RuntimeCommand::Restart(dids)).await;
+                                    }
+                                });
+                            }
+                            Decision::Shutdown { dids } => {
+                                eprintln!("Root supervisor gave up after agent {} failed", did);
+                                for agent in agents.iter().filter(|a| dids.iter().any(|d| d == a.did())) {
+                                    agent.control(AgentControl::Stop);
+                                }
+                            }
+                            Decision::Ignore => {}
+                        }
                     }
+                    _ = checkpoint_timer.tick() => save_checkpoints(&launcher.checkpoints, &mut snapshots),
                 }
             }
         });
@@ -76,8 +272,10 @@ impl Runtime {
             map,
             mrs,
             db,
-            agents,
+            vectors,
+            trust,
             command_tx,
+            events_tx,
         })
     }
 
@@ -87,9 +285,468 @@ impl Runtime {
         Ok(())
     }
 
+    /// Verifies DNA against the runtime's trust store and spawns it, returning the DID
+    pub async fn spawn_dna(&self, dna: DnaFile) -> Result<String, Box<dyn Error>> {
+        self.spawn_dna_under(dna, ROOT_SUPERVISOR).await
+    }
+
+    /// Verifies and spawns DNA as a child of the named supervisor, returning the DID
+    pub async fn spawn_dna_under(&self, dna: DnaFile, supervisor: &str) -> Result<String, Box<dyn Error>> {
+        self.trust.verify(&dna)?;
+        // Parsed here so a malformed config fails the call rather than the run loop
+        let config = AgentConfig::from_dna(&dna)?;
+        let did = dna.did().to_string();
+        let (tx, rx) = oneshot::channel();
+        self.command_tx
+            .send(RuntimeCommand::SpawnDna(dna, config, supervisor.to_string(), tx))
+            .await?;
+        rx.await??;
+        Ok(did)
+    }
+
+    /// Adds a supervisor under `parent` (use `ROOT_SUPERVISOR` for the top level)
+    pub async fn add_supervisor(&self, spec: SupervisorSpec, parent: &str) -> Result<(), Box<dyn Error>> {
+        let (tx, rx) = oneshot::channel();
+        self.command_tx
+            .send(RuntimeCommand::AddSupervisor(spec, parent.to_string(), tx))
+            .await?;
+        Ok(rx.await??)
+    }
+
+    /// Spawns a signed agent from a .map file, returning its DID
+    pub async fn spawn_map_file(&self, path: &str) -> Result<String, Box<dyn Error>> {
+        let bytes = tokio::fs::read(path).await?;
+        self.spawn_dna(DnaFile::from_bytes(&bytes)?).await
+    }
+
+    /// Sends a request to a running agent and waits for its response
+    pub async fn request(
+        &self,
+        did: &str,
+        msg: UalMessage,
+        timeout: Duration,
+    ) -> Result<UalMessage, Box<dyn Error>> {
+        let (tx, rx) = oneshot::channel();
+        self.command_tx
+            .send(RuntimeCommand::GetAgent(did.to_string(), tx))
+            .await?;
+        let agent = rx.await?.ok_or_else(|| format!("Agent not found: {}", did))?;
+        Ok(agent.request(msg, timeout).await?)
+    }
+
+    /// Moves an agent to another lifecycle state, returning the state it left
+    pub async fn control_agent(&self, did: &str, control: AgentControl) -> Result<AgentState, Box<dyn Error>> {
+        let (tx, rx) = oneshot::channel();
+        self.command_tx
+            .send(RuntimeCommand::Control(did.to_string(), control, tx))
+            .await?;
+        Ok(rx.await??)
+    }
+
+    /// Stops an agent taking messages off its mailbox
+    pub async fn pause_agent(&self, did: &str) -> Result<AgentState, Box<dyn Error>> {
+        self.control_agent(did, AgentControl::Pause).await
+    }
+
+    /// Resumes a paused agent
+    pub async fn resume_agent(&self, did: &str) -> Result<AgentState, Box<dyn Error>> {
+        self.control_agent(did, AgentControl::Run).await
+    }
+
+    /// Closes an agent's mailbox and stops it once queued messages are handled
+    pub async fn drain_agent(&self, did: &str) -> Result<AgentState, Box<dyn Error>> {
+        self.control_agent(did, AgentControl::Drain).await
+    }
+
+    /// Stops an agent immediately, dropping queued messages
+    pub async fn stop_agent(&self, did: &str) -> Result<AgentState, Box<dyn Error>> {
+        self.control_agent(did, AgentControl::Stop).await
+    }
+
+    /// Connects a Maple Core so its spawn and terminate commands act on this runtime's agents
+    pub fn connect_core(&self) -> MapleCore {
+        let (tx, mut rx) = mpsc::channel(100);
+        let command_tx = self.command_tx.downgrade();
+        tokio::spawn(async move {
+            while let Some(cmd) = rx.recv().await {
+                let Some(command_tx) = command_tx.upgrade() else {
+                    return;
+                };
+                match cmd {
+                    AgentCommand::Spawn(did) => {
+                        let _ = command_tx.send(RuntimeCommand::SpawnAgent(did)).await;
+                    }
+                    AgentCommand::Terminate(did) => {
+                        let (reply_tx, reply_rx) = oneshot::channel();
+                        let _ = command_tx
+                            .send(RuntimeCommand::Control(did.clone(), AgentControl::Stop, reply_tx))
+                            .await;
+                        if let Ok(Err(e)) = reply_rx.await {
+                            eprintln!("Failed to terminate agent {}: {}", did, e);
+                        }
+                    }
+                }
+            }
+        });
+        MapleCore::connect(tx)
+    }
+
+    /// Lists known agents with their lifecycle states
+    pub async fn list_agents(&self) -> Result<Vec<(String, AgentState)>, Box<dyn Error>> {
+        let (tx, rx) = oneshot::channel();
+        self.command_tx.send(RuntimeCommand::ListAgents(tx)).await?;
+        Ok(rx.await?)
+    }
+
+    /// Reports mailbox depth and overflow counters for every known agent
+    pub async fn mailbox_stats(&self) -> Result<Vec<(String, MailboxStats)>, Box<dyn Error>> {
+        let (tx, rx) = oneshot::channel();
+        self.command_tx.send(RuntimeCommand::MailboxStats(tx)).await?;
+        Ok(rx.await?)
+    }
+
+    /// Subscribes to agent lifecycle transitions
+    pub fn lifecycle_events(&self) -> broadcast::Receiver<LifecycleEvent> {
+        self.events_tx.subscribe()
+    }
+
+    /// Sends a request to whichever running agent declares its action, preferring the cheapest
+    pub async fn request_capable(&self, msg: UalMessage, timeout: Duration) -> Result<UalMessage, Box<dyn Error>> {
+        let (tx, rx) = oneshot::channel();
+        self.command_tx
+            .send(RuntimeCommand::FindCapable(msg.action().to_string(), tx))
+            .await?;
+        let agent = rx
+            .await?
+            .ok_or_else(|| format!("No running agent accepts action: {}", msg.action()))?;
+        Ok(agent.request(msg, timeout).await?)
+    }
+
+    /// Returns the vector store backing agents' semantic memory
+    pub fn vector_db(&self) -> &Arc<VectorDb> {
+        &self.vectors
+    }
+
+    /// Returns the trust store used to verify DNA before spawning
+    pub fn trust_store(&self) -> &TrustStore {
+        &self.trust
+    }
+
     /// Shuts down the runtime
     pub async fn shutdown(&self) -> Result<(), Box<dyn Error>> {
         self.command_tx.send(RuntimeCommand::Shutdown).await?;
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+/// Shared backends for agent episodic and semantic memory
+struct MemoryStores {
+    db: Arc<MapleDb>,
+    vectors: Arc<VectorDb>,
+}
+
+impl MemoryStores {
+    /// Builds the memory handed to an agent's behaviour
+    fn for_agent(&self, did: &str) -> AgentMemory {
+        AgentMemory::default()
+            .with_episodic(EpisodicLog::new(self.db.clone(), did))
+            .with_semantic(SemanticMemory::new(self.vectors.clone(), did))
+    }
+}
+
+/// Everything needed to start an agent run loop under the runtime
+struct Launcher {
+    map: MapProtocol, // Announces hosted agents on the DHT
+    behaviours: BehaviourRegistry,
+    checkpoints: CheckpointStore,
+    stores: MemoryStores,
+    events: broadcast::Sender<LifecycleEvent>,
+    failures: mpsc::UnboundedSender<String>,
+}
+
+impl Launcher {
+    /// Starts an agent, resuming from its latest checkpoint when one exists
+    fn start(&self, did: &str, config: AgentConfig, state: Vec<u8>) -> Agent {
+        let checkpoint = match self.checkpoints.load(did) {
+            Ok(Some(checkpoint)) => {
+                println!(
+                    "Restoring agent {} from checkpoint after {} messages",
+                    did, checkpoint.high_water_mark
+                );
+                checkpoint
+            }
+            Ok(None) => Checkpoint::new(did, state),
+            Err(e) => {
+                eprintln!("Ignoring unreadable checkpoint for {}: {}", did, e);
+                Checkpoint::new(did, state)
+            }
+        };
+        let leases = lease_schemas(&config);
+        let behaviour = self.behaviours.create(&config);
+        let agent = Agent::restore_with_memory(config, checkpoint, behaviour, self.stores.for_agent(did));
+        lifecycle::watch_agent(&agent, self.events.clone(), self.failures.clone());
+        let mut states = agent.lifecycle_changes();
+        let (map, did) = (self.map.clone(), did.to_string());
+        tokio::spawn(async move {
+            // Peers are only pointed at an agent once it has started; a closed channel means the run loop is gone
+            let started = states
+                .wait_for(|s| *s == AgentState::Running || s.is_terminal())
+                .await
+                .is_ok_and(|s| *s == AgentState::Running);
+            if started {
+                announce(&map, &did).await;
+            }
+            let _ = states.wait_for(|s| s.is_terminal()).await;
+            drop(leases);
+            if started {
+                // Fails only once the node is shutting down, when the record goes with it
+                let _ = map.stop_providing(&did).await;
+            }
+        });
+        agent
+    }
+}
+
+/// Records on the DHT that this node hosts an agent, so peers can find it by DID
+async fn announce(map: &MapProtocol, did: &str) {
+    // Without DHT peers yet, the record is still stored locally and served once peers connect
+    if let Err(e) = map.start_providing(did).await {
+        eprintln!("Failed to announce agent {} on the DHT: {}", did, e);
+    }
+}
+
+/// Declares the payload schemas an agent's capabilities carry so messages are validated against
+/// them; the declarations are withdrawn when the returned leases are dropped
+fn lease_schemas(config: &AgentConfig) -> Vec<SchemaLease<'static>> {
+    let mut leases = Vec::new();
+    for capability in &config.capabilities {
+        if let Some(schema) = &capability.schema {
+            let leased = ActionSchema::json(&capability.action, capability.version, schema)
+                .and_then(|schema| SchemaRegistry::global().lease(schema));
+            match leased {
+                Ok(lease) => leases.push(lease),
+                Err(e) => eprintln!("Ignoring schema of {} for {}: {}", capability.action, config.name, e),
+            }
+        }
+    }
+    leases
+}
+
+/// How long a restart waits for a still-running agent to stop before warning that it is stuck
+const RESTART_STOP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Stops agents that are about to be restarted and waits for every run loop to exit, so a
+/// restarted agent never runs alongside its previous loop
+async fn stop_for_restart(agents: &[Agent]) {
+    for agent in agents {
+        agent.control(AgentControl::Stop);
+    }
+    for agent in agents {
+        let mut states = agent.lifecycle_changes();
+        let exited = async {
+            // A closed channel means the run loop is gone as well
+            let _ = states.wait_for(|s| s.is_terminal()).await;
+        };
+        tokio::pin!(exited);
+        if tokio::time::timeout(RESTART_STOP_TIMEOUT, &mut exited).await.is_err() {
+            eprintln!("Agent {} has not stopped after {:?}; still waiting to restart it", agent.did(), RESTART_STOP_TIMEOUT);
+            exited.await;
+        }
+    }
+}
+
+/// Hands messages arriving over MAP to local agents until the runtime shuts down.
+///
+/// The only sender check is `remote_peers`: the peer is the authenticated node that sent a direct
+/// message or signed a broadcast, but the DIDs inside a message (e.g., `reply_to`) are not verified,
+/// so agents must not treat them as proof of who is asking. With no list, any connected peer reaches
+/// every agent, limited only by MAP's own allow/deny lists and rate limits.
+async fn route_incoming(
+    map: MapProtocol,
+    remote_peers: Option<HashSet<PeerId>>,
+    command_tx: mpsc::WeakSender<RuntimeCommand>,
+) {
+    let mut incoming = map.incoming();
+    loop {
+        let (peer, msg) = match incoming.recv().await {
+            Ok(received) => received,
+            Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                eprintln!("Dropped {} network messages while routing fell behind", skipped);
+                continue;
+            }
+            Err(broadcast::error::RecvError::Closed) => return,
+        };
+        if remote_peers.as_ref().is_some_and(|peers| !peers.contains(&peer)) {
+            continue; // Broadcasts from untrusted peers are expected, so this is not logged
+        }
+        let Some(command_tx) = command_tx.upgrade() else {
+            return;
+        };
+        // Addressed messages go to their agent; the rest to whichever agent handles the action
+        let (tx, rx) = oneshot::channel();
+        let command = match msg.recipient() {
+            Some(did) => RuntimeCommand::GetAgent(did.to_string(), tx),
+            None => RuntimeCommand::FindCapable(msg.action().to_string(), tx),
+        };
+        if command_tx.send(command).await.is_err() {
+            return;
+        }
+        let Ok(Some(agent)) = rx.await else {
+            continue; // Broadcasts reach every node, most of which have no agent for them
+        };
+        let map = map.clone();
+        tokio::spawn(async move {
+            if msg.reply_to().is_none() {
+                if let Err(e) = agent.send(msg).await {
+                    eprintln!("Failed to deliver message from {:?}: {}", peer, e);
+                }
+                return;
+            }
+            match agent.request(msg, DEFAULT_REQUEST_TIMEOUT).await {
+                Ok(reply) => {
+                    if let Err(e) = map.send_message(peer, reply).await {
+                        eprintln!("Failed to send reply to {:?}: {}", peer, e);
+                    }
+                }
+                Err(e) => eprintln!("Request from {:?} failed: {}", peer, e),
+            }
+        });
+    }
+}
+
+/// Saves the checkpoint of every agent whose state changed since the last save,
+/// dropping the snapshots of agents whose run loop has exited
+fn save_checkpoints(checkpoints: &CheckpointStore, snapshots: &mut HashMap<String, watch::Receiver<Checkpoint>>) {
+    snapshots.retain(|_, snapshot| save_checkpoint(checkpoints, snapshot));
+}
+
+/// Saves an agent's latest checkpoint if it changed since the last save.
+///
+/// Returns false once the run loop has exited; its final checkpoint is still saved if unseen.
+fn save_checkpoint(checkpoints: &CheckpointStore, snapshot: &mut watch::Receiver<Checkpoint>) -> bool {
+    let running = snapshot.has_changed().is_ok();
+    let checkpoint = {
+        // Unlike Receiver::has_changed, this still reports an unseen value after the sender drops
+        let latest = snapshot.borrow_and_update();
+        if !latest.has_changed() {
+            return running;
+        }
+        latest.clone()
+    };
+    if let Err(e) = checkpoints.save(&checkpoint) {
+        eprintln!("Failed to checkpoint agent {}: {}", checkpoint.did, e);
+    }
+    running
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use maple_agents::{AgentBehaviour, AgentContext, BehaviourError, DnaError};
+    use maple_ual::Mode;
+
+    #[test]
+    fn test_exited_agent_checkpoint_is_saved_once() {
+        let checkpoints = CheckpointStore::new(Arc::new(MapleDb::new("test_runtime_checkpoints").unwrap()));
+        let (snapshot_tx, snapshot_rx) = watch::channel(Checkpoint::new("did:maple:agent:a", Vec::new()));
+        let mut snapshots = HashMap::from([("did:maple:agent:a".to_string(), snapshot_rx)]);
+
+        save_checkpoints(&checkpoints, &mut snapshots);
+        assert_eq!(checkpoints.load("did:maple:agent:a").unwrap(), None); // Nothing new yet
+
+        snapshot_tx.send_replace(Checkpoint::new("did:maple:agent:a", vec![7]));
+        drop(snapshot_tx); // The run loop published its final state and exited
+        save_checkpoints(&checkpoints, &mut snapshots);
+        assert_eq!(checkpoints.load("did:maple:agent:a").unwrap().unwrap().state, vec![7]);
+        assert!(snapshots.is_empty());
+
+        drop(checkpoints);
+        std::fs::remove_dir_all("test_runtime_checkpoints").unwrap();
+    }
+
+    async fn wait_for(events: &mut broadcast::Receiver<LifecycleEvent>, state: AgentState) {
+        let wait = async { while events.recv().await.unwrap().to != state {} };
+        tokio::time::timeout(Duration::from_secs(10), wait).await.unwrap();
+    }
+
+    /// Panics on "crash" and answers anything else
+    struct Flaky;
+
+    #[async_trait::async_trait]
+    impl AgentBehaviour for Flaky {
+        async fn handle_message(
+            &mut self,
+            _ctx: &mut AgentContext,
+            msg: UalMessage,
+        ) -> Result<Option<UalMessage>, BehaviourError> {
+            assert_ne!(msg.action(), "crash", "asked to crash");
+            Ok(Some(UalMessage::new("pong", Mode::ByteLevel)))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_panicking_agent_is_restarted() {
+        let mut behaviours = BehaviourRegistry::new();
+        behaviours.register("default", |_| Box::new(Flaky));
+        let config = RuntimeConfig {
+            mode: RuntimeMode::Distributed,
+            map_listen_addr: "/ip4/127.0.0.1/tcp/0".to_string(),
+            db_path: "test_runtime_restart_db".to_string(),
+            trust_store_path: None,
+            map_key_file: None,
+            remote_peers: None,
+        };
+        let runtime = Runtime::with_behaviours(config, behaviours).await.unwrap();
+        let mut events = runtime.lifecycle_events();
+        let did = "did:maple:agent:flaky";
+        runtime.spawn_agent(did.to_string()).await.unwrap();
+        wait_for(&mut events, AgentState::Running).await;
+
+        let timeout = Duration::from_secs(5);
+        let crash = UalMessage::new("crash", Mode::ByteLevel);
+        assert!(runtime.request(did, crash, timeout).await.is_err());
+        wait_for(&mut events, AgentState::Failed).await;
+        wait_for(&mut events, AgentState::Running).await; // Started again by the root supervisor
+
+        let reply = runtime.request(did, UalMessage::new("ping", Mode::ByteLevel), timeout).await.unwrap();
+        assert_eq!(reply.action(), "pong");
+
+        runtime.shutdown().await.unwrap();
+        drop(runtime);
+        let _ = std::fs::remove_dir_all("test_runtime_restart_db");
+    }
+
+    #[tokio::test]
+    async fn test_spawn_under_unknown_supervisor_fails() {
+        let mut trust = TrustStore::new();
+        trust.set_allow_unsigned(true);
+        trust.save("test_runtime_spawn_trust.json").unwrap();
+        let config = RuntimeConfig {
+            mode: RuntimeMode::Distributed,
+            map_listen_addr: "/ip4/127.0.0.1/tcp/0".to_string(),
+            db_path: "test_runtime_spawn_db".to_string(),
+            trust_store_path: Some("test_runtime_spawn_trust.json".to_string()),
+            map_key_file: None,
+            remote_peers: None,
+        };
+        let runtime = Runtime::new(config).await.unwrap();
+        let mut dna = DnaFile::new("did:maple:agent:orphan");
+        dna.set_section(SectionKind::Config, br#"{"name":"orphan","role":"default"}"#.to_vec());
+
+        let e = runtime.spawn_dna_under(dna.clone(), "missing").await.unwrap_err();
+        assert!(matches!(e.downcast_ref(), Some(SupervisionError::UnknownSupervisor(name)) if name == "missing"));
+        assert!(runtime.list_agents().await.unwrap().is_empty());
+        assert_eq!(runtime.spawn_dna(dna).await.unwrap(), "did:maple:agent:orphan");
+
+        let mut malformed = DnaFile::new("did:maple:agent:malformed");
+        malformed.set_section(SectionKind::Config, b"not json".to_vec());
+        let e = runtime.spawn_dna(malformed).await.unwrap_err();
+        assert!(e.downcast_ref::<DnaError>().is_some());
+        assert_eq!(runtime.list_agents().await.unwrap().len(), 1);
+
+        runtime.shutdown().await.unwrap();
+        drop(runtime);
+        let _ = std::fs::remove_dir_all("test_runtime_spawn_db");
+        std::fs::remove_file("test_runtime_spawn_trust.json").unwrap();
+    }
+}
@@ -0,0 +1,116 @@
+// Runtime-owned agent lifecycle: validated transitions and transition events
+// © 2025 Finalverse Inc. All rights reserved.
+
+use maple_agents::{Agent, AgentControl, AgentState};
+use serde::{Deserialize, Serialize};
+use std::time::{SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+use tokio::sync::{broadcast, mpsc};
+
+/// Capacity of the lifecycle event channel; slow subscribers see `Lagged`
+pub const LIFECYCLE_EVENT_CAPACITY: usize = 256;
+
+/// One agent lifecycle transition
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LifecycleEvent {
+    pub did: String,
+    pub from: Option<AgentState>, // None when the runtime first learns of the agent
+    pub to: AgentState,
+    pub at: u64, // Unix time in milliseconds
+}
+
+impl LifecycleEvent {
+    pub fn new(did: &str, from: Option<AgentState>, to: AgentState) -> Self {
+        LifecycleEvent {
+            did: did.to_string(),
+            from,
+            to,
+            at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_millis() as u64)
+                .unwrap_or(0),
+        }
+    }
+}
+
+/// Errors raised by lifecycle commands
+#[derive(Debug, Error)]
+pub enum LifecycleError {
+    #[error("agent not found: {0}")]
+    UnknownAgent(String),
+    #[error("agent {did} cannot move from {from:?} to {to:?}")]
+    InvalidTransition {
+        did: String,
+        from: AgentState,
+        to: AgentState,
+    },
+}
+
+/// Hands a control signal to the agent's run loop, which checks it against the state it is in
+pub(crate) async fn apply_control(agent: &Agent, control: AgentControl) -> Result<AgentState, LifecycleError> {
+    agent
+        .apply_control(control)
+        .await
+        .map_err(|from| LifecycleError::InvalidTransition {
+            did: agent.did().to_string(),
+            from,
+            to: control.target(),
+        })
+}
+
+/// Publishes the `Created` event and forwards every transition the run loop reports afterwards,
+/// reporting the DID on `failures` if the agent fails
+pub(crate) fn watch_agent(
+    agent: &Agent,
+    events: broadcast::Sender<LifecycleEvent>,
+    failures: mpsc::UnboundedSender<String>,
+) {
+    let did = agent.did().to_string();
+    let Some(mut transitions) = agent.take_transitions() else {
+        eprintln!("Lifecycle of agent {} is already being watched", did);
+        return;
+    };
+    // No subscribers is not an error; lifecycle events are best-effort
+    let _ = events.send(LifecycleEvent::new(&did, None, AgentState::Created));
+    tokio::spawn(async move {
+        while let Some(transition) = transitions.recv().await {
+            let _ = events.send(LifecycleEvent::new(&did, Some(transition.from), transition.to));
+            if transition.to == AgentState::Failed {
+                let _ = failures.send(did.clone());
+            }
+            if transition.to.is_terminal() {
+                break;
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use maple_agents::AgentConfig;
+
+    #[tokio::test]
+    async fn test_transitions_are_validated_and_published() {
+        let (events_tx, mut events) = broadcast::channel(LIFECYCLE_EVENT_CAPACITY);
+        let (failures, _) = mpsc::unbounded_channel();
+        let agent = Agent::new(AgentConfig::new("test-agent", "test"));
+        watch_agent(&agent, events_tx, failures);
+
+        let mut seen = Vec::new();
+        while seen.last() != Some(&AgentState::Running) {
+            seen.push(events.recv().await.unwrap().to);
+        }
+        assert_eq!(seen.first(), Some(&AgentState::Created));
+
+        apply_control(&agent, AgentControl::Pause).await.unwrap();
+        apply_control(&agent, AgentControl::Run).await.unwrap();
+        apply_control(&agent, AgentControl::Stop).await.unwrap();
+        // Quick successive transitions are each published, none skipped
+        for to in [AgentState::Paused, AgentState::Running, AgentState::Stopped] {
+            assert_eq!(events.recv().await.unwrap().to, to);
+        }
+        let err = apply_control(&agent, AgentControl::Pause).await.unwrap_err();
+        assert!(matches!(err, LifecycleError::InvalidTransition { from: AgentState::Stopped, .. }));
+    }
+}
@@ -22,7 +22,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             runtime.shutdown().await?;
         }
         "enterprise" => {
-            let config_path = args.get(2).unwrap_or(&"config.toml".to_string());
+            let config_path = args.get(2).map(String::as_str).unwrap_or("config.toml");
             let runtime = start_enterprise(config_path).await?;
             tokio::signal::ctrl_c().await?;
             runtime.shutdown().await?;
@@ -0,0 +1,282 @@
+// Erlang-style supervision tree deciding how failed agents are restarted
+// © 2025 Finalverse Inc. All rights reserved.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::time::Duration;
+use thiserror::Error;
+use tokio::time::Instant;
+
+/// Name of the supervisor every runtime starts with
+pub const ROOT_SUPERVISOR: &str = "root";
+
+/// Which siblings are restarted along with a failed child
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RestartStrategy {
+    OneForOne, // Only the failed child
+    OneForAll, // Every child of the supervisor
+    RestForOne, // The failed child and the children added after it
+}
+
+/// How a supervisor restarts its children and when it gives up
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SupervisorSpec {
+    pub name: String,
+    pub strategy: RestartStrategy,
+    pub max_restarts: u32, // Restarts allowed within `period` before escalating to the parent
+    pub period: Duration,
+    pub backoff_initial: Duration, // Delay before the first restart in a period, doubled for each further one
+    pub backoff_max: Duration,
+}
+
+impl SupervisorSpec {
+    /// Creates a spec allowing 3 restarts in 5 seconds with backoff from 100ms to 5s
+    pub fn new(name: &str, strategy: RestartStrategy) -> Self {
+        SupervisorSpec {
+            name: name.to_string(),
+            strategy,
+            max_restarts: 3,
+            period: Duration::from_secs(5),
+            backoff_initial: Duration::from_millis(100),
+            backoff_max: Duration::from_secs(5),
+        }
+    }
+
+    pub fn with_intensity(mut self, max_restarts: u32, period: Duration) -> Self {
+        self.max_restarts = max_restarts;
+        self.period = period;
+        self
+    }
+
+    pub fn with_backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.backoff_initial = initial;
+        self.backoff_max = max;
+        self
+    }
+
+    /// Delay before the nth restart within the current period (1-based)
+    fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        self.backoff_initial.saturating_mul(factor).min(self.backoff_max)
+    }
+}
+
+/// What the runtime should do about a failed agent
+#[derive(Debug, Clone, PartialEq)]
+pub enum Decision {
+    Restart { dids: Vec<String>, after: Duration }, // Stop these agents if running, then start them again
+    Shutdown { dids: Vec<String> }, // The root supervisor gave up; stop these agents for good
+    Ignore, // The agent is not supervised
+}
+
+/// Errors raised when building the tree
+#[derive(Debug, Error)]
+pub enum SupervisionError {
+    #[error("supervisor not found: {0}")]
+    UnknownSupervisor(String),
+    #[error("supervisor already exists: {0}")]
+    DuplicateSupervisor(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Child {
+    Agent(String),
+    Supervisor(usize),
+}
+
+#[derive(Debug)]
+struct Node {
+    spec: SupervisorSpec,
+    parent: Option<usize>,
+    children: Vec<Child>,
+    restarts: VecDeque<Instant>, // Restarts within the current period
+}
+
+/// Supervisors and the agents they watch, rooted at `ROOT_SUPERVISOR`
+#[derive(Debug)]
+pub struct SupervisionTree {
+    nodes: Vec<Node>,
+    names: HashMap<String, usize>,
+    agents: HashMap<String, usize>, // Agent DID -> supervising node
+}
+
+impl SupervisionTree {
+    /// Creates a tree with only a root supervisor
+    pub fn new(mut root: SupervisorSpec) -> Self {
+        root.name = ROOT_SUPERVISOR.to_string();
+        let mut tree = SupervisionTree {
+            nodes: Vec::new(),
+            names: HashMap::new(),
+            agents: HashMap::new(),
+        };
+        tree.push(root, None);
+        tree
+    }
+
+    /// Adds a supervisor as the last child of `parent`
+    pub fn add_supervisor(&mut self, spec: SupervisorSpec, parent: &str) -> Result<(), SupervisionError> {
+        if self.names.contains_key(&spec.name) {
+            return Err(SupervisionError::DuplicateSupervisor(spec.name));
+        }
+        let parent = self.node(parent)?;
+        let id = self.push(spec, Some(parent));
+        self.nodes[parent].children.push(Child::Supervisor(id));
+        Ok(())
+    }
+
+    /// Places an agent as the last child of `supervisor`, moving it if already supervised
+    pub fn add_agent(&mut self, did: &str, supervisor: &str) -> Result<(), SupervisionError> {
+        let node = self.node(supervisor)?;
+        self.remove_agent(did);
+        self.nodes[node].children.push(Child::Agent(did.to_string()));
+        self.agents.insert(did.to_string(), node);
+        Ok(())
+    }
+
+    /// Stops supervising an agent
+    pub fn remove_agent(&mut self, did: &str) {
+        if let Some(node) = self.agents.remove(did) {
+            self.nodes[node].children.retain(|c| *c != Child::Agent(did.to_string()));
+        }
+    }
+
+    /// Records an agent failure and decides which agents to restart, escalating
+    /// through parents whose restart intensity is exceeded
+    pub fn on_failure(&mut self, did: &str, now: Instant) -> Decision {
+        let Some(&start) = self.agents.get(did) else {
+            return Decision::Ignore;
+        };
+        let mut failed = Child::Agent(did.to_string());
+        let mut node = start;
+        loop {
+            let n = &mut self.nodes[node];
+            while n.restarts.front().is_some_and(|t| now.duration_since(*t) > n.spec.period) {
+                n.restarts.pop_front();
+            }
+            if n.restarts.len() as u32 >= n.spec.max_restarts {
+                eprintln!(
+                    "Supervisor {} exceeded {} restarts in {:?}",
+                    n.spec.name, n.spec.max_restarts, n.spec.period
+                );
+                n.restarts.clear();
+                match n.parent {
+                    Some(parent) => {
+                        failed = Child::Supervisor(node);
+                        node = parent;
+                        continue;
+                    }
+                    None => {
+                        let mut dids = Vec::new();
+                        self.collect(&Child::Supervisor(node), &mut dids);
+                        return Decision::Shutdown { dids };
+                    }
+                }
+            }
+            n.restarts.push_back(now);
+            let after = n.spec.backoff(n.restarts.len() as u32);
+            let index = n.children.iter().position(|c| *c == failed).unwrap_or(0);
+            let affected = match n.spec.strategy {
+                RestartStrategy::OneForOne => vec![failed],
+                RestartStrategy::OneForAll => n.children.clone(),
+                RestartStrategy::RestForOne => n.children[index..].to_vec(),
+            };
+            let mut dids = Vec::new();
+            for child in &affected {
+                self.collect(child, &mut dids);
+            }
+            return Decision::Restart { dids, after };
+        }
+    }
+
+    fn node(&self, name: &str) -> Result<usize, SupervisionError> {
+        self.names
+            .get(name)
+            .copied()
+            .ok_or_else(|| SupervisionError::UnknownSupervisor(name.to_string()))
+    }
+
+    fn push(&mut self, spec: SupervisorSpec, parent: Option<usize>) -> usize {
+        let id = self.nodes.len();
+        self.names.insert(spec.name.clone(), id);
+        self.nodes.push(Node {
+            spec,
+            parent,
+            children: Vec::new(),
+            restarts: VecDeque::new(),
+        });
+        id
+    }
+
+    /// Collects agent DIDs under a child in start order; restarted supervisors start with a fresh period
+    fn collect(&mut self, child: &Child, dids: &mut Vec<String>) {
+        match child {
+            Child::Agent(did) => dids.push(did.clone()),
+            Child::Supervisor(id) => {
+                self.nodes[*id].restarts.clear();
+                for child in self.nodes[*id].children.clone() {
+                    self.collect(&child, dids);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn restart(dids: &[&str], after: Duration) -> Decision {
+        Decision::Restart {
+            dids: dids.iter().map(|d| d.to_string()).collect(),
+            after,
+        }
+    }
+
+    #[test]
+    fn test_strategies_pick_affected_siblings() {
+        let now = Instant::now();
+        let ms = Duration::from_millis;
+        let mut tree = SupervisionTree::new(SupervisorSpec::new(ROOT_SUPERVISOR, RestartStrategy::OneForOne));
+        tree.add_supervisor(SupervisorSpec::new("all", RestartStrategy::OneForAll), ROOT_SUPERVISOR)
+            .unwrap();
+        tree.add_supervisor(SupervisorSpec::new("rest", RestartStrategy::RestForOne), ROOT_SUPERVISOR)
+            .unwrap();
+        for (did, sup) in [("a", ROOT_SUPERVISOR), ("b", "all"), ("c", "all"), ("d", "rest"), ("e", "rest"), ("f", "rest")] {
+            tree.add_agent(did, sup).unwrap();
+        }
+
+        assert_eq!(tree.on_failure("a", now), restart(&["a"], ms(100)));
+        assert_eq!(tree.on_failure("c", now), restart(&["b", "c"], ms(100)));
+        assert_eq!(tree.on_failure("e", now), restart(&["e", "f"], ms(100)));
+        assert_eq!(tree.on_failure("e", now), restart(&["e", "f"], ms(200)));
+        assert_eq!(tree.on_failure("unknown", now), Decision::Ignore);
+    }
+
+    #[test]
+    fn test_intensity_escalates_to_parent_then_gives_up() {
+        let now = Instant::now();
+        let root = SupervisorSpec::new(ROOT_SUPERVISOR, RestartStrategy::OneForOne).with_intensity(1, Duration::from_secs(5));
+        let mut tree = SupervisionTree::new(root);
+        let child = SupervisorSpec::new("workers", RestartStrategy::OneForOne).with_intensity(1, Duration::from_secs(5));
+        tree.add_supervisor(child, ROOT_SUPERVISOR).unwrap();
+        tree.add_agent("a", "workers").unwrap();
+        tree.add_agent("b", "workers").unwrap();
+
+        assert!(matches!(tree.on_failure("a", now), Decision::Restart { ref dids, .. } if dids == &["a"]));
+        // Second failure exceeds the workers' intensity; root restarts the whole subtree
+        assert!(matches!(tree.on_failure("a", now), Decision::Restart { ref dids, .. } if dids == &["a", "b"]));
+        // Restarts outside the period are forgotten
+        let later = now + Duration::from_secs(6);
+        assert!(matches!(tree.on_failure("b", later), Decision::Restart { ref dids, .. } if dids == &["b"]));
+        assert!(matches!(tree.on_failure("b", later), Decision::Restart { ref dids, .. } if dids == &["a", "b"]));
+        // Restarting the subtree reset the workers' count, so they handle the next failure themselves
+        assert!(matches!(tree.on_failure("b", later), Decision::Restart { ref dids, .. } if dids == &["b"]));
+        // Root already restarted once in this period, so it gives up
+        assert_eq!(
+            tree.on_failure("b", later),
+            Decision::Shutdown {
+                dids: vec!["a".to_string(), "b".to_string()]
+            }
+        );
+    }
+}
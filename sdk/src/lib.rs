@@ -1,16 +1,17 @@
 // Rust SDK for interacting with the MAPLE ecosystem
 // © 2025 Finalverse Inc. All rights reserved.
 
-use maple_agents::{Agent, AgentConfig};
+use maple_agents::{Agent, AgentConfig, RequestError};
 use maple_map::{MapConfig, MapProtocol};
 use maple_mrs::{Mrs, MrsConfig};
 use maple_ual::{UalMessage, Mode};
 use mapledb::MapleDb;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
-use serde_json;
 use std::error::Error;
+use std::time::Duration;
 use thiserror::Error;
+use tokio::io::AsyncReadExt;
 
 #[cfg(feature = "python")]
 use pyo3::prelude::*;
@@ -22,10 +23,20 @@ pub enum SdkError {
     Network(#[from] reqwest::Error),
     #[error("Serialization error: {0}")]
     Serialization(#[from] serde_json::Error),
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Request error: {0}")]
+    Request(#[from] RequestError),
     #[error("MAPLE error: {0}")]
     Maple(String),
 }
 
+impl From<Box<dyn Error>> for SdkError {
+    fn from(e: Box<dyn Error>) -> Self {
+        SdkError::Maple(e.to_string())
+    }
+}
+
 /// Configuration for the SDK
 #[derive(Debug, Serialize, Deserialize)]
 pub struct SdkConfig {
@@ -41,6 +52,7 @@ pub struct MapleSdk {
     config: SdkConfig,
     map: MapProtocol,
     mrs: Mrs,
+    #[allow(dead_code)]
     db: MapleDb,
 }
 
@@ -48,12 +60,10 @@ impl MapleSdk {
     /// Initializes a new SDK instance
     pub async fn new(config: SdkConfig) -> Result<Self, SdkError> {
         let client = Client::new();
-        let map_config = MapConfig {
-            listen_addr: config.map_listen_addr.clone(),
-        };
-        let map = MapProtocol::new(map_config).await?;
+        let map = MapProtocol::new(MapConfig::new(&config.map_listen_addr)).await?;
+        let map_config = MapConfig::new(&config.map_listen_addr); // The registry runs its own node
         let mrs = Mrs::new(MrsConfig { map_config }).await?;
-        let db = MapleDb::new(&config.db_path)?;
+        let db = MapleDb::new(&config.db_path).map_err(|e| SdkError::Maple(e.to_string()))?;
 
         Ok(MapleSdk {
             client,
@@ -66,10 +76,7 @@ impl MapleSdk {
 
     /// Creates and registers a new agent
     pub async fn create_agent(&self, name: &str, role: &str) -> Result<String, SdkError> {
-        let config = AgentConfig {
-            name: name.to_string(),
-            role: role.to_string(),
-        };
+        let config = AgentConfig::new(name, role);
         let agent = Agent::new(config.clone());
         let did = self.mrs.register_agent(config).await?;
         agent.dump_to_map(&format!("{}.map", name)).await?;
@@ -84,7 +91,7 @@ impl MapleSdk {
 
         let response = self
             .client
-            .post(&format!("{}/agents/spawn", self.config.api_url))
+            .post(format!("{}/agents/spawn", self.config.api_url))
             .header("Authorization", &self.config.api_key)
             .body(buffer)
             .send()
@@ -100,16 +107,41 @@ impl MapleSdk {
 
     /// Sends a UAL message to an agent via the network
     pub async fn send_message(&self, did: &str, action: &str, payload: serde_json::Value) -> Result<(), SdkError> {
-        let msg = UalMessage::new(action, Mode::Json).with_json_payload(&payload)?;
-        let peer_id = self.resolve_did_to_peer(did).await?; // Placeholder for DID-to-PeerID mapping
-        self.map.send_message(peer_id, serde_json::to_string(&msg)?).await?;
+        let msg = UalMessage::new(action, Mode::Json)
+            .with_json_payload(&payload)?
+            .with_recipient(did);
+        let peer_id = self.resolve_did_to_peer(did).await?;
+        self.map.send_message(peer_id, msg).await?;
         Ok(())
     }
 
-    /// Placeholder: Resolves DID to PeerID (requires MRS or network lookup)
-    async fn resolve_did_to_peer(&self, _did: &str) -> Result<libp2p::PeerId, SdkError> {
-        // TODO: Implement DID resolution
-        Ok(libp2p::PeerId::random())
+    /// Sends a JSON request to an agent and waits for its JSON response
+    pub async fn request(
+        &self,
+        agent: &Agent,
+        action: &str,
+        payload: serde_json::Value,
+        timeout: Duration,
+    ) -> Result<serde_json::Value, SdkError> {
+        let msg = UalMessage::new(action, Mode::Json)
+            .with_json_payload(&payload)
+            .map_err(|e| SdkError::Maple(e.to_string()))?;
+        let reply = agent.request(msg, timeout).await?;
+        reply.decode().map_err(|e| SdkError::Maple(e.to_string()))
+    }
+
+    /// Resolves a DID to the peer hosting it
+    async fn resolve_did_to_peer(&self, did: &str) -> Result<maple_map::PeerId, SdkError> {
+        // Runtimes announce the DIDs they host as DHT provider records
+        let providers = self
+            .map
+            .get_providers(did)
+            .await
+            .map_err(|e| SdkError::Maple(e.to_string()))?;
+        providers
+            .into_iter()
+            .next()
+            .ok_or_else(|| SdkError::Maple(format!("No peer hosts agent {}", did)))
     }
 }
 
@@ -179,5 +211,6 @@ mod tests {
         let did = sdk.create_agent("test-agent", "test-role").await.unwrap();
         assert!(did.starts_with("did:maple:agent:"));
         tokio::fs::remove_file("test-agent.map").await.unwrap();
+        std::fs::remove_dir_all("test_sdk_db").unwrap();
     }
 }
\ No newline at end of file
@@ -30,6 +30,23 @@ impl MapleDb {
             .map(|opt| opt.map(|v| v.to_vec()))
     }
 
+    /// Retrieves all key-value pairs whose key starts with `prefix`, in key order
+    pub fn scan_prefix(&self, prefix: &str) -> Result<Vec<(String, Vec<u8>)>, SledError> {
+        self.db
+            .scan_prefix(prefix.as_bytes())
+            .map(|item| item.map(|(k, v)| (String::from_utf8_lossy(&k).into_owned(), v.to_vec())))
+            .collect()
+    }
+
+    /// Atomically increments a big-endian `u64` counter, returning its value before the increment
+    pub fn increment(&self, key: &str) -> Result<u64, SledError> {
+        let previous = self.db.fetch_and_update(key.as_bytes(), |old| {
+            Some((counter_value(old) + 1).to_be_bytes().to_vec())
+        })?;
+        self.db.flush()?;
+        Ok(counter_value(previous.as_deref()))
+    }
+
     /// Deletes a key-value pair
     pub fn delete(&self, key: &str) -> Result<(), SledError> {
         self.db.remove(key.as_bytes())?;
@@ -37,6 +54,12 @@ impl MapleDb {
     }
 }
 
+fn counter_value(bytes: Option<&[u8]>) -> u64 {
+    bytes
+        .and_then(|b| <[u8; 8]>::try_from(b).ok())
+        .map_or(0, u64::from_be_bytes)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -49,5 +72,10 @@ mod tests {
         assert_eq!(value, b"data");
         db.delete("agent1").unwrap();
         assert!(db.get("agent1").unwrap().is_none());
+
+        assert_eq!(db.increment("counter").unwrap(), 0);
+        assert_eq!(db.increment("counter").unwrap(), 1);
+        assert_eq!(db.get("counter").unwrap().unwrap(), 2u64.to_be_bytes());
+        db.delete("counter").unwrap();
     }
 }
\ No newline at end of file
@@ -2,38 +2,77 @@
 // © 2025 Finalverse Inc. All rights reserved.
 
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::error::Error;
+use std::sync::RwLock;
 
 /// Represents a vector entry
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct VectorEntry {
-    id: String,
-    vector: Vec<f32>,
+    pub id: String,
+    pub vector: Vec<f32>,
 }
 
-/// Vector database instance (placeholder)
+/// Vector database instance (in-memory; exact cosine-similarity search)
+#[derive(Debug, Default)]
 pub struct VectorDb {
     // TODO: Integrate with an actual vector DB like Qdrant
+    entries: RwLock<HashMap<String, Vec<f32>>>,
 }
 
 impl VectorDb {
     /// Creates a new vector database instance
     pub fn new() -> Self {
-        VectorDb {}
+        VectorDb::default()
     }
 
-    /// Stores a vector entry
+    /// Stores a vector entry, replacing any entry with the same ID
     pub async fn store(&self, entry: VectorEntry) -> Result<(), Box<dyn Error>> {
-        println!("Storing vector for {}: {:?}", entry.id, entry.vector);
-        // TODO: Implement actual storage
+        if entry.vector.is_empty() {
+            return Err(format!("Empty vector for {}", entry.id).into());
+        }
+        let mut entries = self.entries.write().map_err(|_| "Vector store lock poisoned")?;
+        entries.insert(entry.id, entry.vector);
         Ok(())
     }
 
-    /// Searches for similar vectors
+    /// Searches for similar vectors, most similar first
     pub async fn search(&self, query: Vec<f32>, limit: usize) -> Result<Vec<VectorEntry>, Box<dyn Error>> {
-        println!("Searching with query: {:?}", query);
-        // TODO: Implement actual search
-        Ok(vec![])
+        self.search_prefix(query, limit, "").await
+    }
+
+    /// Searches for similar vectors among entries whose ID starts with `prefix`
+    pub async fn search_prefix(
+        &self,
+        query: Vec<f32>,
+        limit: usize,
+        prefix: &str,
+    ) -> Result<Vec<VectorEntry>, Box<dyn Error>> {
+        let entries = self.entries.read().map_err(|_| "Vector store lock poisoned")?;
+        let mut scored: Vec<(f32, &String, &Vec<f32>)> = entries
+            .iter()
+            .filter(|(id, vector)| id.starts_with(prefix) && vector.len() == query.len())
+            .map(|(id, vector)| (cosine_similarity(&query, vector), id, vector))
+            .collect();
+        scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+        Ok(scored
+            .into_iter()
+            .take(limit)
+            .map(|(_, id, vector)| VectorEntry {
+                id: id.clone(),
+                vector: vector.clone(),
+            })
+            .collect())
+    }
+}
+
+fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm = a.iter().map(|x| x * x).sum::<f32>().sqrt() * b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm == 0.0 {
+        0.0
+    } else {
+        dot / norm
     }
 }
 
@@ -49,7 +88,14 @@ mod tests {
             vector: vec![1.0, 2.0, 3.0],
         };
         db.store(entry).await.unwrap();
+        db.store(VectorEntry {
+            id: "agent2".to_string(),
+            vector: vec![-3.0, 0.0, 1.0],
+        })
+        .await
+        .unwrap();
         let results = db.search(vec![1.0, 2.0, 3.0], 1).await.unwrap();
-        assert_eq!(results.len(), 0); // Placeholder
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].id, "agent1");
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,658 @@
+// Canonical binary frame for whole UAL messages and a streaming codec
+// © 2025 Finalverse Inc. All rights reserved.
+//
+// Layout (integers big-endian):
+//   magic "UALF" | version u8 | mode u8 | flags u8
+//   | action len u16 | action
+//   | header count u16 | (key len u16 | key | value len u16 | value)*
+//   | payload len u32 | payload
+//
+// A compressed payload is flagged in `flags`; a payload above the chunk size is split
+// across frames sharing the message ID, each carrying a ":chunk" header ("index/count").
+
+use crate::{Mode, UalMessage};
+use bytes::{Buf, BufMut, BytesMut};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::btree_map::Entry;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::io::Read;
+use thiserror::Error;
+use tokio_util::codec::{Decoder, Encoder};
+
+/// Magic bytes opening every frame
+pub const FRAME_MAGIC: &[u8; 4] = b"UALF";
+
+/// Frame layout version written by this crate
+pub const FRAME_VERSION: u8 = 1;
+
+/// Default largest frame the codec accepts (16 MiB)
+pub const DEFAULT_MAX_FRAME_LEN: usize = 16 * 1024 * 1024;
+
+/// Default payload bytes per frame before a message is chunked (1 MiB)
+pub const DEFAULT_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Default largest payload accepted after reassembly and decompression (256 MiB)
+pub const DEFAULT_MAX_MESSAGE_LEN: usize = 256 * 1024 * 1024;
+
+/// Default smallest payload worth compressing
+pub const DEFAULT_COMPRESS_THRESHOLD: usize = 1024;
+
+/// Chunked messages a reassembler tracks at once; the oldest is abandoned beyond this
+pub const MAX_PENDING_MESSAGES: usize = 64;
+
+/// Frame flag: payload is zstd-compressed
+pub const FLAG_ZSTD: u8 = 0x01;
+
+/// Frame flag: payload is lz4-compressed, prefixed with its decompressed size
+pub const FLAG_LZ4: u8 = 0x02;
+
+/// Frame flag: payload is one chunk of a larger message
+pub const FLAG_CHUNKED: u8 = 0x04;
+
+// Pseudo-headers carrying envelope fields; user headers may not start with ':'
+const ID: &str = ":id";
+const CORRELATION_ID: &str = ":correlation-id";
+const REPLY_TO: &str = ":reply-to";
+const DEADLINE: &str = ":deadline";
+const CHUNK: &str = ":chunk";
+
+/// Payload compression applied when framing
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Compression {
+    #[default]
+    None,
+    Zstd, // Best ratio, e.g., for DNA and model weights
+    Lz4,  // Fastest
+}
+
+/// How messages are split and compressed into frames, and how large they may grow when read back
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FrameOptions {
+    pub compression: Compression,
+    pub compress_threshold: usize, // Smaller payloads are sent as-is
+    pub chunk_size: usize, // Payload bytes per frame; larger payloads are chunked
+    pub max_message_len: usize, // Largest reassembled or decompressed payload accepted
+}
+
+impl Default for FrameOptions {
+    fn default() -> Self {
+        FrameOptions {
+            compression: Compression::None,
+            compress_threshold: DEFAULT_COMPRESS_THRESHOLD,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            max_message_len: DEFAULT_MAX_MESSAGE_LEN,
+        }
+    }
+}
+
+impl FrameOptions {
+    /// Compresses payloads of at least `compress_threshold` bytes
+    pub fn with_compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Splits payloads larger than `chunk_size` bytes across frames
+    pub fn with_chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = chunk_size.max(1);
+        self
+    }
+}
+
+/// Errors raised while framing or parsing UAL messages
+#[derive(Debug, Error)]
+pub enum FrameError {
+    #[error("not a UAL frame")]
+    BadMagic,
+    #[error("unsupported UAL frame version {0}")]
+    UnsupportedVersion(u8),
+    #[error("unknown UAL mode tag {0}")]
+    UnknownMode(u8),
+    #[error("unsupported UAL frame flags {0:#04x}")]
+    UnsupportedFlags(u8),
+    #[error("UAL frame is truncated")]
+    Truncated,
+    #[error("UAL frame of {len} bytes exceeds the {max} byte limit")]
+    TooLarge { len: usize, max: usize },
+    #[error("UAL frame field is too long to encode: {0}")]
+    FieldTooLong(&'static str),
+    #[error("invalid UAL frame header {0}")]
+    InvalidHeader(String),
+    #[error("UAL frame text is not valid UTF-8")]
+    InvalidUtf8,
+    #[error("{0} trailing bytes after UAL frame")]
+    TrailingBytes(usize),
+    #[error("UAL frame is one chunk of a larger message; read it with a Reassembler")]
+    Chunked,
+    #[error("UAL payload compression failed: {0}")]
+    Compression(String),
+    #[error("UAL frame I/O error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+impl UalMessage {
+    /// Encodes the whole message, including action, mode and envelope fields, as one uncompressed frame
+    pub fn to_frame(&self) -> Result<Vec<u8>, FrameError> {
+        let mut buf = BytesMut::new();
+        write_frame(self, &self.payload, 0, None, &mut buf)?;
+        Ok(buf.to_vec())
+    }
+
+    /// Encodes the message as one or more frames, compressing and chunking its payload
+    pub fn to_frames(&self, options: &FrameOptions) -> Result<Vec<Vec<u8>>, FrameError> {
+        let (payload, flags) = compress(&self.payload, options)?;
+        let chunk_size = options.chunk_size.max(1);
+        if payload.len() <= chunk_size {
+            let mut buf = BytesMut::new();
+            write_frame(self, &payload, flags, None, &mut buf)?;
+            return Ok(vec![buf.to_vec()]);
+        }
+        let count = u32::try_from(payload.len().div_ceil(chunk_size)).map_err(|_| FrameError::FieldTooLong("payload"))?;
+        let mut frames = Vec::with_capacity(count as usize);
+        for (index, chunk) in payload.chunks(chunk_size).enumerate() {
+            let mut buf = BytesMut::new();
+            write_frame(self, chunk, flags | FLAG_CHUNKED, Some((index as u32, count)), &mut buf)?;
+            frames.push(buf.to_vec());
+        }
+        Ok(frames)
+    }
+
+    /// Decodes a message from exactly one frame, decompressing its payload
+    pub fn from_frame(bytes: &[u8]) -> Result<Self, FrameError> {
+        let mut reader = bytes;
+        let raw = read_frame(&mut reader, usize::MAX)?;
+        if !reader.is_empty() {
+            return Err(FrameError::TrailingBytes(reader.len()));
+        }
+        if raw.chunk.is_some() {
+            return Err(FrameError::Chunked);
+        }
+        raw.finish(DEFAULT_MAX_MESSAGE_LEN)
+    }
+}
+
+/// Frame as read off the wire, before decompression and reassembly
+struct RawFrame {
+    msg: UalMessage, // Payload still as framed
+    flags: u8,
+    chunk: Option<(u32, u32)>, // Index and count
+}
+
+impl RawFrame {
+    fn finish(mut self, max_message_len: usize) -> Result<UalMessage, FrameError> {
+        let payload = std::mem::take(&mut self.msg.payload);
+        self.msg.payload = decompress(self.flags, payload, max_message_len)?;
+        Ok(self.msg)
+    }
+}
+
+/// Collects the chunks of large messages, from any number of frames in any order
+#[derive(Debug, Clone)]
+pub struct Reassembler {
+    max_message_len: usize,
+    pending: HashMap<String, Partial>,
+    order: VecDeque<String>, // Message IDs, oldest first
+}
+
+#[derive(Debug, Clone)]
+struct Partial {
+    msg: UalMessage, // Envelope of the first chunk seen, without payload
+    flags: u8,
+    count: u32,
+    chunks: BTreeMap<u32, Vec<u8>>, // Received so far, by index
+    len: usize,
+}
+
+impl Reassembler {
+    /// Creates a reassembler rejecting payloads larger than `max_message_len` bytes
+    pub fn new(max_message_len: usize) -> Self {
+        Reassembler {
+            max_message_len,
+            pending: HashMap::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    /// Adds one frame, returning the message once all of its chunks have arrived
+    pub fn push(&mut self, frame: &[u8]) -> Result<Option<UalMessage>, FrameError> {
+        let mut reader = frame;
+        let raw = read_frame(&mut reader, usize::MAX)?;
+        if !reader.is_empty() {
+            return Err(FrameError::TrailingBytes(reader.len()));
+        }
+        self.push_raw(raw)
+    }
+
+    /// Number of messages still missing chunks
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    fn push_raw(&mut self, mut raw: RawFrame) -> Result<Option<UalMessage>, FrameError> {
+        let Some((index, count)) = raw.chunk else {
+            return raw.finish(self.max_message_len).map(Some);
+        };
+        // Every chunk carries at least one byte, so the count alone can rule a message out
+        if count as usize > self.max_message_len {
+            return Err(FrameError::TooLarge {
+                len: count as usize,
+                max: self.max_message_len,
+            });
+        }
+        if raw.msg.payload.is_empty() {
+            return Err(FrameError::InvalidHeader(CHUNK.to_string()));
+        }
+        let id = raw.msg.id.clone();
+        if !self.pending.contains_key(&id) {
+            if self.pending.len() >= MAX_PENDING_MESSAGES {
+                if let Some(oldest) = self.order.pop_front() {
+                    self.pending.remove(&oldest);
+                }
+            }
+            let partial = Partial {
+                msg: raw.msg.clone(),
+                flags: raw.flags,
+                count,
+                chunks: BTreeMap::new(),
+                len: 0,
+            };
+            self.pending.insert(id.clone(), partial);
+            self.order.push_back(id.clone());
+        }
+        let partial = self.pending.get_mut(&id).expect("inserted above");
+        if partial.count != count || partial.flags != raw.flags {
+            return Err(FrameError::InvalidHeader(CHUNK.to_string()));
+        }
+        if let Entry::Vacant(slot) = partial.chunks.entry(index) {
+            partial.len += raw.msg.payload.len();
+            slot.insert(std::mem::take(&mut raw.msg.payload));
+        }
+        if partial.len > self.max_message_len {
+            let len = partial.len;
+            self.forget(&id);
+            return Err(FrameError::TooLarge {
+                len,
+                max: self.max_message_len,
+            });
+        }
+        if partial.chunks.len() < partial.count as usize {
+            return Ok(None);
+        }
+        let mut partial = self.forget(&id).expect("tracked above");
+        partial.msg.payload = partial.chunks.into_values().flatten().collect();
+        RawFrame {
+            msg: partial.msg,
+            flags: partial.flags & !FLAG_CHUNKED,
+            chunk: None,
+        }
+        .finish(self.max_message_len)
+        .map(Some)
+    }
+
+    fn forget(&mut self, id: &str) -> Option<Partial> {
+        self.order.retain(|pending| pending != id);
+        self.pending.remove(id)
+    }
+}
+
+impl Default for Reassembler {
+    fn default() -> Self {
+        Reassembler::new(DEFAULT_MAX_MESSAGE_LEN)
+    }
+}
+
+/// Streaming codec for UAL frames over TCP, files or libp2p streams (via `tokio_util::compat`);
+/// chunked messages are split on write and reassembled on read
+#[derive(Debug, Clone)]
+pub struct UalCodec {
+    max_frame_len: usize,
+    options: FrameOptions,
+    reassembler: Reassembler,
+}
+
+impl UalCodec {
+    /// Creates a codec rejecting frames larger than `max_frame_len` bytes
+    pub fn new(max_frame_len: usize) -> Self {
+        UalCodec::new_with_options(max_frame_len, FrameOptions::default())
+    }
+
+    /// Creates a codec compressing and chunking outgoing messages as configured
+    pub fn new_with_options(max_frame_len: usize, options: FrameOptions) -> Self {
+        UalCodec {
+            max_frame_len,
+            reassembler: Reassembler::new(options.max_message_len),
+            options,
+        }
+    }
+}
+
+impl Default for UalCodec {
+    fn default() -> Self {
+        UalCodec::new(DEFAULT_MAX_FRAME_LEN)
+    }
+}
+
+impl Decoder for UalCodec {
+    type Item = UalMessage;
+    type Error = FrameError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<UalMessage>, FrameError> {
+        loop {
+            let mut reader = &src[..];
+            let raw = match read_frame(&mut reader, self.max_frame_len) {
+                Ok(raw) => raw,
+                Err(FrameError::Truncated) => return Ok(None),
+                Err(e) => return Err(e),
+            };
+            let consumed = src.len() - reader.len();
+            src.advance(consumed);
+            if let Some(msg) = self.reassembler.push_raw(raw)? {
+                return Ok(Some(msg));
+            }
+        }
+    }
+}
+
+impl Encoder<UalMessage> for UalCodec {
+    type Error = FrameError;
+
+    fn encode(&mut self, msg: UalMessage, dst: &mut BytesMut) -> Result<(), FrameError> {
+        let frames = msg.to_frames(&self.options)?;
+        if let Some(len) = frames.iter().map(Vec::len).find(|len| *len > self.max_frame_len) {
+            return Err(FrameError::TooLarge {
+                len,
+                max: self.max_frame_len,
+            });
+        }
+        for frame in frames {
+            dst.extend_from_slice(&frame);
+        }
+        Ok(())
+    }
+}
+
+fn write_frame(
+    msg: &UalMessage,
+    payload: &[u8],
+    flags: u8,
+    chunk: Option<(u32, u32)>,
+    buf: &mut BytesMut,
+) -> Result<(), FrameError> {
+    let mut headers: Vec<(&str, String)> = vec![(ID, msg.id.clone())];
+    if let Some((index, count)) = chunk {
+        headers.push((CHUNK, format!("{}/{}", index, count)));
+    }
+    if let Some(id) = &msg.correlation_id {
+        headers.push((CORRELATION_ID, id.clone()));
+    }
+    if let Some(did) = &msg.reply_to {
+        headers.push((REPLY_TO, did.clone()));
+    }
+    if let Some(deadline) = msg.deadline {
+        headers.push((DEADLINE, deadline.to_string()));
+    }
+    for (key, value) in &msg.headers {
+        if key.starts_with(':') {
+            return Err(FrameError::InvalidHeader(key.clone()));
+        }
+        headers.push((key, value.clone()));
+    }
+
+    buf.put_slice(FRAME_MAGIC);
+    buf.put_u8(FRAME_VERSION);
+    buf.put_u8(msg.mode.tag());
+    buf.put_u8(flags);
+    put_short(buf, msg.action.as_bytes(), "action")?;
+    let count = u16::try_from(headers.len()).map_err(|_| FrameError::FieldTooLong("headers"))?;
+    buf.put_u16(count);
+    for (key, value) in &headers {
+        put_short(buf, key.as_bytes(), "header key")?;
+        put_short(buf, value.as_bytes(), "header value")?;
+    }
+    let len = u32::try_from(payload.len()).map_err(|_| FrameError::FieldTooLong("payload"))?;
+    buf.put_u32(len);
+    buf.put_slice(payload);
+    Ok(())
+}
+
+fn read_frame(buf: &mut &[u8], max_frame_len: usize) -> Result<RawFrame, FrameError> {
+    let start = buf.len();
+    if take(buf, 4)? != FRAME_MAGIC {
+        return Err(FrameError::BadMagic);
+    }
+    let version = take_u8(buf)?;
+    if version != FRAME_VERSION {
+        return Err(FrameError::UnsupportedVersion(version));
+    }
+    let tag = take_u8(buf)?;
+    let mode = Mode::from_tag(tag).ok_or(FrameError::UnknownMode(tag))?;
+    let flags = take_u8(buf)?;
+    if flags & !(FLAG_ZSTD | FLAG_LZ4 | FLAG_CHUNKED) != 0 || flags & (FLAG_ZSTD | FLAG_LZ4) == FLAG_ZSTD | FLAG_LZ4 {
+        return Err(FrameError::UnsupportedFlags(flags));
+    }
+    let action = take_string(buf)?;
+
+    let mut msg = UalMessage::new(&action, mode);
+    let count = take_u16(buf)?;
+    let mut headers = BTreeMap::new();
+    let mut chunk = None;
+    for _ in 0..count {
+        let key = take_string(buf)?;
+        let value = take_string(buf)?;
+        match key.as_str() {
+            ID => msg.id = value,
+            CORRELATION_ID => msg.correlation_id = Some(value),
+            REPLY_TO => msg.reply_to = Some(value),
+            DEADLINE => msg.deadline = Some(value.parse().map_err(|_| FrameError::InvalidHeader(key))?),
+            CHUNK => chunk = Some(parse_chunk(&value).ok_or(FrameError::InvalidHeader(key))?),
+            _ if key.starts_with(':') => return Err(FrameError::InvalidHeader(key)),
+            _ => {
+                headers.insert(key, value);
+            }
+        }
+        // Refuse an endless header section before it is buffered in full
+        check_frame_len(start - buf.len(), max_frame_len)?;
+    }
+    msg.headers = headers;
+    if chunk.is_some() != (flags & FLAG_CHUNKED != 0) {
+        return Err(FrameError::InvalidHeader(CHUNK.to_string()));
+    }
+
+    let len = take_u32(buf)? as usize;
+    check_frame_len((start - buf.len()).saturating_add(len), max_frame_len)?;
+    msg.payload = take(buf, len)?.to_vec();
+    Ok(RawFrame { msg, flags, chunk })
+}
+
+fn check_frame_len(len: usize, max_frame_len: usize) -> Result<(), FrameError> {
+    if len > max_frame_len {
+        return Err(FrameError::TooLarge { len, max: max_frame_len });
+    }
+    Ok(())
+}
+
+/// Parses "index/count", requiring index < count
+fn parse_chunk(value: &str) -> Option<(u32, u32)> {
+    let (index, count) = value.split_once('/')?;
+    let (index, count) = (index.parse().ok()?, count.parse().ok()?);
+    (index < count).then_some((index, count))
+}
+
+fn compress<'a>(payload: &'a [u8], options: &FrameOptions) -> Result<(Cow<'a, [u8]>, u8), FrameError> {
+    if payload.len() < options.compress_threshold {
+        return Ok((Cow::Borrowed(payload), 0));
+    }
+    match options.compression {
+        Compression::None => Ok((Cow::Borrowed(payload), 0)),
+        Compression::Zstd => zstd::bulk::compress(payload, zstd::DEFAULT_COMPRESSION_LEVEL)
+            .map(|compressed| (Cow::Owned(compressed), FLAG_ZSTD))
+            .map_err(|e| FrameError::Compression(e.to_string())),
+        Compression::Lz4 => Ok((Cow::Owned(lz4_flex::compress_prepend_size(payload)), FLAG_LZ4)),
+    }
+}
+
+/// Undoes the compression named in `flags`, refusing to inflate past `max_len` bytes
+fn decompress(flags: u8, payload: Vec<u8>, max_len: usize) -> Result<Vec<u8>, FrameError> {
+    let too_large = |len| FrameError::TooLarge { len, max: max_len };
+    let decompressed = if flags & FLAG_ZSTD != 0 {
+        let mut out = Vec::new();
+        zstd::stream::read::Decoder::new(payload.as_slice())
+            .and_then(|decoder| decoder.take(max_len as u64 + 1).read_to_end(&mut out))
+            .map_err(|e| FrameError::Compression(e.to_string()))?;
+        out
+    } else if flags & FLAG_LZ4 != 0 {
+        let size = payload.get(..4).ok_or(FrameError::Truncated)?;
+        let size = u32::from_le_bytes([size[0], size[1], size[2], size[3]]) as usize;
+        if size > max_len {
+            return Err(too_large(size));
+        }
+        lz4_flex::decompress_size_prepended(&payload).map_err(|e| FrameError::Compression(e.to_string()))?
+    } else {
+        payload
+    };
+    if decompressed.len() > max_len {
+        return Err(too_large(decompressed.len()));
+    }
+    Ok(decompressed)
+}
+
+fn put_short(buf: &mut BytesMut, bytes: &[u8], field: &'static str) -> Result<(), FrameError> {
+    let len = u16::try_from(bytes.len()).map_err(|_| FrameError::FieldTooLong(field))?;
+    buf.put_u16(len);
+    buf.put_slice(bytes);
+    Ok(())
+}
+
+fn take<'a>(buf: &mut &'a [u8], len: usize) -> Result<&'a [u8], FrameError> {
+    if buf.len() < len {
+        return Err(FrameError::Truncated);
+    }
+    let (head, tail) = buf.split_at(len);
+    *buf = tail;
+    Ok(head)
+}
+
+fn take_u8(buf: &mut &[u8]) -> Result<u8, FrameError> {
+    Ok(take(buf, 1)?[0])
+}
+
+fn take_u16(buf: &mut &[u8]) -> Result<u16, FrameError> {
+    Ok(u16::from_be_bytes(take(buf, 2)?.try_into().expect("2 bytes")))
+}
+
+fn take_u32(buf: &mut &[u8]) -> Result<u32, FrameError> {
+    Ok(u32::from_be_bytes(take(buf, 4)?.try_into().expect("4 bytes")))
+}
+
+fn take_string(buf: &mut &[u8]) -> Result<String, FrameError> {
+    let len = take_u16(buf)? as usize;
+    String::from_utf8(take(buf, len)?.to_vec()).map_err(|_| FrameError::InvalidUtf8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_frame_roundtrip_keeps_whole_message() {
+        let msg = UalMessage::new("move", Mode::Json)
+            .with_json_payload(&serde_json::json!({"x": 10}))
+            .unwrap()
+            .with_reply_to("did:maple:agent:1234")
+            .with_timeout(Duration::from_secs(60))
+            .with_header("trace", "abc");
+        let frame = msg.to_frame().unwrap();
+        assert_eq!(&frame[..4], FRAME_MAGIC);
+
+        let decoded = UalMessage::from_frame(&frame).unwrap();
+        assert_eq!(decoded.action(), "move");
+        assert_eq!(decoded.mode(), &Mode::Json);
+        assert_eq!(decoded.id(), msg.id());
+        assert_eq!(decoded.reply_to(), msg.reply_to());
+        assert_eq!(decoded.deadline(), msg.deadline());
+        assert_eq!(decoded.header("trace"), Some("abc"));
+        assert_eq!(decoded.payload(), msg.payload());
+
+        assert!(matches!(UalMessage::from_frame(&frame[..frame.len() - 1]), Err(FrameError::Truncated)));
+        assert!(matches!(UalMessage::from_frame(b"nope"), Err(FrameError::BadMagic)));
+    }
+
+    #[test]
+    fn test_codec_handles_partial_and_back_to_back_frames() {
+        let mut codec = UalCodec::default();
+        let mut wire = BytesMut::new();
+        codec.encode(UalMessage::new("a", Mode::ByteLevel).with_byte_payload(vec![1, 2]), &mut wire).unwrap();
+        codec.encode(UalMessage::new("b", Mode::ByteLevel), &mut wire).unwrap();
+
+        let mut src = BytesMut::from(&wire[..5]);
+        assert!(codec.decode(&mut src).unwrap().is_none());
+        src.extend_from_slice(&wire[5..]);
+        assert_eq!(codec.decode(&mut src).unwrap().unwrap().action(), "a");
+        assert_eq!(codec.decode(&mut src).unwrap().unwrap().action(), "b");
+        assert!(src.is_empty());
+
+        let mut small = UalCodec::new(16);
+        let big = UalMessage::new("a", Mode::ByteLevel).with_byte_payload(vec![0; 64]);
+        assert!(matches!(small.encode(big, &mut BytesMut::new()), Err(FrameError::TooLarge { .. })));
+    }
+
+    #[test]
+    fn test_compressed_chunks_reassemble_in_any_order() {
+        let weights: Vec<u8> = (0..300_000u32).map(|i| (i % 251) as u8).collect();
+        let msg = UalMessage::new("weights", Mode::ByteLevel).with_byte_payload(weights.clone());
+        for compression in [Compression::Zstd, Compression::Lz4] {
+            let options = FrameOptions::default().with_compression(compression).with_chunk_size(64);
+            let mut frames = msg.to_frames(&options).unwrap();
+            assert!(frames.len() > 1);
+            assert!(frames.iter().map(Vec::len).sum::<usize>() < weights.len());
+            assert!(matches!(UalMessage::from_frame(&frames[0]), Err(FrameError::Chunked)));
+
+            frames.reverse();
+            let mut reassembler = Reassembler::default();
+            let (last, rest) = frames.split_last().unwrap();
+            for frame in rest {
+                assert!(reassembler.push(frame).unwrap().is_none());
+            }
+            let whole = reassembler.push(last).unwrap().unwrap();
+            assert_eq!((whole.id(), whole.payload()), (msg.id(), &weights[..]));
+            assert_eq!(reassembler.pending(), 0);
+
+            let mut codec = UalCodec::new_with_options(DEFAULT_MAX_FRAME_LEN, options);
+            let mut wire = BytesMut::new();
+            codec.encode(msg.clone(), &mut wire).unwrap();
+            assert_eq!(codec.decode(&mut wire).unwrap().unwrap().payload(), &weights[..]);
+        }
+
+        let frames = msg.to_frames(&FrameOptions::default().with_chunk_size(100_000)).unwrap();
+        let mut small = Reassembler::new(150_000);
+        small.push(&frames[0]).unwrap();
+        assert!(matches!(small.push(&frames[1]), Err(FrameError::TooLarge { .. })));
+    }
+
+    #[test]
+    fn test_oversized_chunk_count_and_header_section_are_rejected() {
+        // A one-byte chunk claiming to be one of four billion must not allocate for all of them
+        let msg = UalMessage::new("weights", Mode::ByteLevel);
+        let mut frame = BytesMut::new();
+        write_frame(&msg, &[1], FLAG_CHUNKED, Some((0, u32::MAX)), &mut frame).unwrap();
+        let mut reassembler = Reassembler::new(1024);
+        assert!(matches!(reassembler.push(&frame), Err(FrameError::TooLarge { len, max: 1024 }) if len == u32::MAX as usize));
+        assert_eq!(reassembler.pending(), 0);
+
+        let mut empty = BytesMut::new();
+        write_frame(&msg, &[], FLAG_CHUNKED, Some((0, 2)), &mut empty).unwrap();
+        assert!(matches!(reassembler.push(&empty), Err(FrameError::InvalidHeader(_))));
+
+        // Headers alone past the frame limit fail before the rest of the frame arrives
+        let mut noisy = msg.clone();
+        for i in 0..64 {
+            noisy = noisy.with_header(&format!("h{}", i), &"x".repeat(64));
+        }
+        let frame = noisy.to_frame().unwrap();
+        let mut codec = UalCodec::new(1024);
+        let mut src = BytesMut::from(&frame[..frame.len() / 2]);
+        assert!(matches!(codec.decode(&mut src), Err(FrameError::TooLarge { max: 1024, .. })));
+    }
+}
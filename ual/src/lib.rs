@@ -2,24 +2,83 @@
 // © 2025 Finalverse Inc. All rights reserved.
 
 use serde::{Deserialize, Serialize};
-use serde_json;
-use prost::Message as ProstMessage;
+use std::collections::BTreeMap;
 use std::error::Error;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use uuid::Uuid;
+
+pub mod frame;
+pub mod negotiate;
+pub mod proto;
+pub mod schema;
+pub mod seal;
+
+pub use frame::{Compression, FrameError, FrameOptions, Reassembler, UalCodec};
+pub use schema::{ActionSchema, SchemaLease, SchemaRegistry, ValidationError, SCHEMA_VERSION_HEADER};
+pub use seal::{DidKeys, DidPublicKeys, SealError};
 
 /// Defines the communication mode for UAL
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Mode {
     Json,      // Lightweight JSON format
     Grpc,      // Structured binary format (gRPC-like)
     ByteLevel, // Custom byte-level format
+    Cbor,        // Compact self-describing binary (RFC 8949)
+    MessagePack, // Compact self-describing binary
+    CapnProto,   // Cap'n Proto message in stream framing, read without copying
 }
 
+impl Mode {
+    /// Tag identifying the mode in a UAL frame
+    pub fn tag(&self) -> u8 {
+        match self {
+            Mode::Json => 1,
+            Mode::Grpc => 2,
+            Mode::ByteLevel => 3,
+            Mode::Cbor => 4,
+            Mode::MessagePack => 5,
+            Mode::CapnProto => 6,
+        }
+    }
+
+    /// Whether payloads decode into any serde type without a schema
+    pub fn is_self_describing(&self) -> bool {
+        matches!(self, Mode::Json | Mode::Cbor | Mode::MessagePack)
+    }
+
+    /// Mode for a frame tag, if known
+    pub fn from_tag(tag: u8) -> Option<Mode> {
+        match tag {
+            1 => Some(Mode::Json),
+            2 => Some(Mode::Grpc),
+            3 => Some(Mode::ByteLevel),
+            4 => Some(Mode::Cbor),
+            5 => Some(Mode::MessagePack),
+            6 => Some(Mode::CapnProto),
+            _ => None,
+        }
+    }
+}
+
+/// Header naming the DID a message is addressed to
+pub const RECIPIENT_HEADER: &str = "to";
+
 /// Represents a UAL message
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct UalMessage {
     action: String, // e.g., "move", "compute"
     mode: Mode,
     payload: Vec<u8>, // Raw payload bytes
+    #[serde(default)]
+    id: String, // Unique message ID
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    correlation_id: Option<String>, // ID of the request this message answers
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    reply_to: Option<String>, // DID that should receive the response
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    deadline: Option<u64>, // Unix time in milliseconds after which the message is stale
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    headers: BTreeMap<String, String>, // e.g., "trace" -> "abc"
 }
 
 impl UalMessage {
@@ -29,9 +88,122 @@ impl UalMessage {
             action: action.to_string(),
             mode,
             payload: Vec::new(),
+            id: Uuid::new_v4().to_string(),
+            correlation_id: None,
+            reply_to: None,
+            deadline: None,
+            headers: BTreeMap::new(),
         }
     }
 
+    /// Creates a response to this message, correlated by its ID
+    pub fn reply(&self, action: &str, mode: Mode) -> Self {
+        let mut reply = UalMessage::new(action, mode);
+        reply.correlation_id = Some(self.id.clone());
+        reply
+    }
+
+    /// Sets the DID that should receive the response
+    pub fn with_reply_to(mut self, did: &str) -> Self {
+        self.reply_to = Some(did.to_string());
+        self
+    }
+
+    /// Sets a deadline relative to now
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.deadline = Some(unix_millis() + timeout.as_millis() as u64);
+        self
+    }
+
+    /// Marks this message as answering the given request ID
+    pub fn with_correlation_id(mut self, id: &str) -> Self {
+        self.correlation_id = Some(id.to_string());
+        self
+    }
+
+    /// Sets a free-form header carried alongside the payload
+    pub fn with_header(mut self, key: &str, value: &str) -> Self {
+        self.headers.insert(key.to_string(), value.to_string());
+        self
+    }
+
+    /// Addresses the message to an agent DID, for transports that route by recipient
+    pub fn with_recipient(self, did: &str) -> Self {
+        self.with_header(RECIPIENT_HEADER, did)
+    }
+
+    /// Pins the schema version the payload was built against; it may be set before or after the
+    /// payload, as messages are validated when sent to an agent or decoded
+    pub fn with_schema_version(self, version: u32) -> Self {
+        self.with_header(SCHEMA_VERSION_HEADER, &version.to_string())
+    }
+
+    /// Checks the message against its action's schema in the global registry
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        SchemaRegistry::global().validate(self)
+    }
+
+    /// Returns a header value
+    pub fn header(&self, key: &str) -> Option<&str> {
+        self.headers.get(key).map(String::as_str)
+    }
+
+    /// Returns all headers
+    pub fn headers(&self) -> &BTreeMap<String, String> {
+        &self.headers
+    }
+
+    /// Returns the DID the message is addressed to, if any
+    pub fn recipient(&self) -> Option<&str> {
+        self.header(RECIPIENT_HEADER)
+    }
+
+    /// Returns the unique message ID
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Returns the ID of the request this message answers
+    pub fn correlation_id(&self) -> Option<&str> {
+        self.correlation_id.as_deref()
+    }
+
+    /// Returns the DID that should receive the response
+    pub fn reply_to(&self) -> Option<&str> {
+        self.reply_to.as_deref()
+    }
+
+    /// Returns the deadline as Unix time in milliseconds
+    pub fn deadline(&self) -> Option<u64> {
+        self.deadline
+    }
+
+    /// Time left before the deadline, if one is set
+    pub fn remaining(&self) -> Option<Duration> {
+        self.deadline
+            .map(|d| Duration::from_millis(d.saturating_sub(unix_millis())))
+    }
+
+    /// Whether the deadline has passed
+    pub fn is_expired(&self) -> bool {
+        self.deadline.is_some_and(|d| unix_millis() >= d)
+    }
+
+    /// Returns the message action
+    pub fn action(&self) -> &str {
+        &self.action
+    }
+
+    /// Returns the communication mode
+    pub fn mode(&self) -> &Mode {
+        &self.mode
+    }
+
+    /// Returns the raw payload bytes
+    pub fn payload(&self) -> &[u8] {
+        &self.payload
+    }
+
     /// Adds a JSON payload to the message
     pub fn with_json_payload<T: Serialize>(mut self, payload: &T) -> Result<Self, Box<dyn Error>> {
         if self.mode != Mode::Json {
@@ -41,6 +213,62 @@ impl UalMessage {
         Ok(self)
     }
 
+    /// Adds a CBOR payload to the message
+    pub fn with_cbor_payload<T: Serialize>(mut self, payload: &T) -> Result<Self, Box<dyn Error>> {
+        if self.mode != Mode::Cbor {
+            return Err("Mode must be Cbor for CBOR payload".into());
+        }
+        self.payload.clear();
+        ciborium::into_writer(payload, &mut self.payload)?;
+        Ok(self)
+    }
+
+    /// Adds a MessagePack payload to the message
+    pub fn with_msgpack_payload<T: Serialize>(mut self, payload: &T) -> Result<Self, Box<dyn Error>> {
+        if self.mode != Mode::MessagePack {
+            return Err("Mode must be MessagePack for MessagePack payload".into());
+        }
+        // Named fields keep payloads self-describing for schema validation
+        self.payload = rmp_serde::to_vec_named(payload)?;
+        Ok(self)
+    }
+
+    /// Adds a Cap'n Proto message in stream framing (e.g., from `capnp::serialize::write_message`)
+    pub fn with_capnp_payload(mut self, payload: Vec<u8>) -> Result<Self, Box<dyn Error>> {
+        if self.mode != Mode::CapnProto {
+            return Err("Mode must be CapnProto for Cap'n Proto payload".into());
+        }
+        capnp_segments(&payload)?;
+        self.payload = payload;
+        Ok(self)
+    }
+
+    /// Borrows the segments of a Cap'n Proto payload, e.g., for `capnp::message::SegmentArray`
+    pub fn capnp_segments(&self) -> Result<Vec<&[u8]>, Box<dyn Error>> {
+        if self.mode != Mode::CapnProto {
+            return Err("Mode must be CapnProto for Cap'n Proto segments".into());
+        }
+        Ok(capnp_segments(&self.payload)?)
+    }
+
+    /// Adds a Protobuf payload to the message
+    pub fn with_proto_payload<M: prost::Message>(mut self, payload: &M) -> Result<Self, Box<dyn Error>> {
+        if self.mode != Mode::Grpc {
+            return Err("Mode must be Grpc for Protobuf payload".into());
+        }
+        self.payload = payload.encode_to_vec();
+        Ok(self)
+    }
+
+    /// Decodes a Protobuf payload into the given message type
+    pub fn decode_proto<M: prost::Message + Default>(&self) -> Result<M, Box<dyn Error>> {
+        if self.mode != Mode::Grpc {
+            return Err("Mode must be Grpc for Protobuf decoding".into());
+        }
+        self.validate()?;
+        Ok(M::decode(self.payload.as_slice())?)
+    }
+
     /// Adds a byte-level payload (e.g., custom encoding)
     pub fn with_byte_payload(mut self, payload: Vec<u8>) -> Self {
         if self.mode != Mode::ByteLevel {
@@ -52,29 +280,126 @@ impl UalMessage {
 
     /// Decodes the payload based on mode
     pub fn decode<T: for<'de> Deserialize<'de>>(&self) -> Result<T, Box<dyn Error>> {
+        if self.mode.is_self_describing() {
+            self.validate()?;
+        }
+        self.decode_unchecked()
+    }
+
+    /// Decodes the payload without consulting the schema registry
+    pub(crate) fn decode_unchecked<T: for<'de> Deserialize<'de>>(&self) -> Result<T, Box<dyn Error>> {
         match self.mode {
             Mode::Json => serde_json::from_slice(&self.payload).map_err(Into::into),
-            Mode::Grpc => {
-                // Placeholder for gRPC decoding (using prost)
-                unimplemented!("gRPC decoding not yet implemented")
-            }
+            Mode::Cbor => ciborium::from_reader(self.payload.as_slice()).map_err(Into::into),
+            Mode::MessagePack => rmp_serde::from_slice(&self.payload).map_err(Into::into),
+            Mode::Grpc => Err("Protobuf payloads decode with decode_proto".into()),
+            Mode::CapnProto => Err("Cap'n Proto payloads are read through capnp_segments".into()),
             Mode::ByteLevel => Err("Byte-level decoding requires custom logic".into()),
         }
     }
 
-    /// Encodes the message for transmission
+    /// Encodes the payload for transmission; use `to_frame` to send the whole message
     pub fn encode(&self) -> Vec<u8> {
         match self.mode {
             Mode::Json => self.payload.clone(), // Already JSON-encoded
-            Mode::Grpc => {
-                // Placeholder for gRPC encoding
-                unimplemented!("gRPC encoding not yet implemented")
-            }
+            Mode::Grpc => self.payload.clone(), // Already Protobuf-encoded
             Mode::ByteLevel => self.payload.clone(), // Raw bytes
+            Mode::Cbor | Mode::MessagePack | Mode::CapnProto => self.payload.clone(), // Already encoded
         }
     }
 }
 
+impl UalMessage {
+    /// Converts the whole message into its Protobuf envelope (see proto/ual.proto)
+    pub fn to_proto(&self) -> proto::UalEnvelope {
+        let mode = match self.mode {
+            Mode::Json => proto::PayloadMode::Json,
+            Mode::Grpc => proto::PayloadMode::Grpc,
+            Mode::ByteLevel => proto::PayloadMode::ByteLevel,
+            Mode::Cbor => proto::PayloadMode::Cbor,
+            Mode::MessagePack => proto::PayloadMode::MessagePack,
+            Mode::CapnProto => proto::PayloadMode::CapnProto,
+        };
+        proto::UalEnvelope {
+            action: self.action.clone(),
+            mode: mode as i32,
+            payload: self.payload.clone(),
+            id: self.id.clone(),
+            correlation_id: self.correlation_id.clone(),
+            reply_to: self.reply_to.clone(),
+            deadline: self.deadline,
+            headers: self.headers.clone(),
+        }
+    }
+
+    /// Rebuilds a message from its Protobuf envelope
+    pub fn from_proto(envelope: proto::UalEnvelope) -> Result<Self, Box<dyn Error>> {
+        let mode = match proto::PayloadMode::try_from(envelope.mode) {
+            Ok(proto::PayloadMode::Json) => Mode::Json,
+            Ok(proto::PayloadMode::Grpc) => Mode::Grpc,
+            Ok(proto::PayloadMode::ByteLevel) => Mode::ByteLevel,
+            Ok(proto::PayloadMode::Cbor) => Mode::Cbor,
+            Ok(proto::PayloadMode::MessagePack) => Mode::MessagePack,
+            Ok(proto::PayloadMode::CapnProto) => Mode::CapnProto,
+            Err(_) => return Err(format!("Unknown UAL payload mode: {}", envelope.mode).into()),
+        };
+        Ok(UalMessage {
+            action: envelope.action,
+            mode,
+            payload: envelope.payload,
+            id: envelope.id,
+            correlation_id: envelope.correlation_id,
+            reply_to: envelope.reply_to,
+            deadline: envelope.deadline,
+            headers: envelope.headers,
+        })
+    }
+
+    /// Encodes the whole message as a Protobuf envelope
+    pub fn to_proto_bytes(&self) -> Vec<u8> {
+        prost::Message::encode_to_vec(&self.to_proto())
+    }
+
+    /// Decodes a message from Protobuf envelope bytes
+    pub fn from_proto_bytes(bytes: &[u8]) -> Result<Self, Box<dyn Error>> {
+        Self::from_proto(prost::Message::decode(bytes)?)
+    }
+}
+
+/// Splits a Cap'n Proto stream-framed message into its segments, checking the segment table
+fn capnp_segments(bytes: &[u8]) -> Result<Vec<&[u8]>, String> {
+    let word = |i: usize| -> Option<usize> {
+        bytes.get(i * 4..i * 4 + 4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize)
+    };
+    let count = word(0).ok_or("Cap'n Proto payload is empty")?.saturating_add(1);
+    if count > 512 {
+        return Err(format!("Cap'n Proto payload has too many segments: {}", count));
+    }
+    // Segment table: count word, one size word per segment, padded to a whole 8-byte word
+    let mut offset = (4 + count * 4).div_ceil(8) * 8;
+    let mut segments = Vec::with_capacity(count);
+    for i in 1..=count {
+        let len = word(i).ok_or("Cap'n Proto segment table is truncated")? * 8;
+        let segment = bytes
+            .get(offset..offset + len)
+            .ok_or("Cap'n Proto segment is truncated")?;
+        segments.push(segment);
+        offset += len;
+    }
+    if offset != bytes.len() {
+        return Err(format!("{} trailing bytes after Cap'n Proto message", bytes.len() - offset));
+    }
+    Ok(segments)
+}
+
+/// Current Unix time in milliseconds
+fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -94,4 +419,70 @@ mod tests {
         let msg = UalMessage::new("move", Mode::ByteLevel).with_byte_payload(payload.clone());
         assert_eq!(msg.payload, payload);
     }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    struct Move {
+        #[prost(int32, tag = "1")]
+        x: i32,
+        #[prost(int32, tag = "2")]
+        y: i32,
+    }
+
+    #[test]
+    fn test_proto_payload_and_envelope_roundtrip() {
+        let msg = UalMessage::new("move", Mode::Grpc)
+            .with_proto_payload(&Move { x: 10, y: 20 })
+            .unwrap()
+            .with_reply_to("did:maple:agent:1234")
+            .with_timeout(Duration::from_secs(60));
+        assert_eq!(msg.decode_proto::<Move>().unwrap(), Move { x: 10, y: 20 });
+        assert!(msg.decode::<serde_json::Value>().is_err());
+
+        let decoded = UalMessage::from_proto_bytes(&msg.to_proto_bytes()).unwrap();
+        assert_eq!(decoded.action(), "move");
+        assert_eq!(decoded.mode(), &Mode::Grpc);
+        assert_eq!(decoded.id(), msg.id());
+        assert_eq!(decoded.reply_to(), msg.reply_to());
+        assert_eq!(decoded.deadline(), msg.deadline());
+        assert_eq!(decoded.decode_proto::<Move>().unwrap(), Move { x: 10, y: 20 });
+
+        let json = UalMessage::new("move", Mode::Json);
+        assert!(json.with_proto_payload(&Move::default()).is_err());
+    }
+
+    #[test]
+    fn test_compact_modes_roundtrip() {
+        let point = serde_json::json!({"x": 10, "y": 20});
+        let cbor = UalMessage::new("move", Mode::Cbor).with_cbor_payload(&point).unwrap();
+        let msgpack = UalMessage::new("move", Mode::MessagePack).with_msgpack_payload(&point).unwrap();
+        for msg in [&cbor, &msgpack] {
+            let decoded: serde_json::Value = msg.decode().unwrap();
+            assert_eq!(decoded, point);
+            assert!(msg.payload().len() < serde_json::to_vec(&point).unwrap().len());
+        }
+
+        // One segment of one word: table (count - 1 = 0, size = 1), then the segment
+        let capnp = [0u32.to_le_bytes(), 1u32.to_le_bytes()].concat();
+        let capnp = [capnp, vec![7; 8]].concat();
+        let msg = UalMessage::new("move", Mode::CapnProto).with_capnp_payload(capnp.clone()).unwrap();
+        assert_eq!(msg.capnp_segments().unwrap(), vec![&[7u8; 8][..]]);
+        assert!(UalMessage::new("move", Mode::CapnProto).with_capnp_payload(capnp[..12].to_vec()).is_err());
+        assert!(UalMessage::from_frame(&msg.to_frame().unwrap()).unwrap().capnp_segments().is_ok());
+    }
+
+    #[test]
+    fn test_reply_correlation_and_deadline() {
+        let request = UalMessage::new("move", Mode::Json)
+            .with_reply_to("did:maple:agent:1234")
+            .with_timeout(Duration::from_secs(60));
+        assert!(!request.is_expired());
+        assert_eq!(request.reply_to(), Some("did:maple:agent:1234"));
+
+        let reply = request.reply("move.result", Mode::Json);
+        assert_eq!(reply.correlation_id(), Some(request.id()));
+        assert_ne!(reply.id(), request.id());
+
+        let stale = UalMessage::new("move", Mode::Json).with_timeout(Duration::ZERO);
+        assert!(stale.is_expired());
+    }
 }
\ No newline at end of file
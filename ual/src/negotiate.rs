@@ -0,0 +1,134 @@
+// Mode negotiation handshake so two agents agree on the best encoding both support
+// © 2025 Finalverse Inc. All rights reserved.
+
+use crate::{FrameError, Mode, UalMessage};
+use futures::{Sink, SinkExt, Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// Action of the opening message listing the initiator's modes
+pub const HELLO_ACTION: &str = "ual.hello";
+
+/// Action of the reply naming the chosen mode
+pub const HELLO_ACK_ACTION: &str = "ual.hello.ack";
+
+/// Modes any agent can use without shared schemas, most preferred first
+pub const DEFAULT_PREFERENCE: [Mode; 3] = [Mode::MessagePack, Mode::Cbor, Mode::Json];
+
+/// Errors raised while negotiating a mode
+#[derive(Debug, Error)]
+pub enum NegotiationError {
+    #[error("expected {expected}, got {actual}")]
+    Unexpected { expected: &'static str, actual: String },
+    #[error("malformed handshake message: {0}")]
+    Malformed(String),
+    #[error("no mode supported by both peers")]
+    NoCommonMode,
+    #[error("peer closed the stream during the handshake")]
+    Closed,
+    #[error(transparent)]
+    Frame(#[from] FrameError),
+}
+
+#[derive(Serialize, Deserialize)]
+struct Hello {
+    modes: Vec<Mode>, // Most preferred first
+}
+
+#[derive(Serialize, Deserialize)]
+struct HelloAck {
+    mode: Option<Mode>, // None when the peers share no mode
+}
+
+/// Picks the first offered mode that is also supported, honouring the offerer's preference
+pub fn select(offered: &[Mode], supported: &[Mode]) -> Option<Mode> {
+    offered.iter().find(|mode| supported.contains(mode)).cloned()
+}
+
+/// Builds the opening message of a handshake
+pub fn hello(modes: &[Mode]) -> UalMessage {
+    // Handshake messages are always JSON so any peer can read them
+    json_message(UalMessage::new(HELLO_ACTION, Mode::Json), &Hello { modes: modes.to_vec() })
+}
+
+/// Answers a hello, returning the acknowledgement to send and the chosen mode
+pub fn answer(hello: &UalMessage, supported: &[Mode]) -> Result<(UalMessage, Option<Mode>), NegotiationError> {
+    let offer: Hello = read(hello, HELLO_ACTION)?;
+    let mode = select(&offer.modes, supported);
+    let ack = json_message(hello.reply(HELLO_ACK_ACTION, Mode::Json), &HelloAck { mode: mode.clone() });
+    Ok((ack, mode))
+}
+
+/// Reads the mode chosen in an acknowledgement
+pub fn accepted(ack: &UalMessage) -> Result<Mode, NegotiationError> {
+    let ack: HelloAck = read(ack, HELLO_ACK_ACTION)?;
+    ack.mode.ok_or(NegotiationError::NoCommonMode)
+}
+
+/// Runs the initiating side of the handshake over a framed stream (e.g., `Framed<TcpStream, UalCodec>`)
+pub async fn initiate<S>(stream: &mut S, modes: &[Mode]) -> Result<Mode, NegotiationError>
+where
+    S: Sink<UalMessage, Error = FrameError> + Stream<Item = Result<UalMessage, FrameError>> + Unpin,
+{
+    stream.send(hello(modes)).await?;
+    let ack = stream.next().await.ok_or(NegotiationError::Closed)??;
+    accepted(&ack)
+}
+
+/// Runs the answering side of the handshake over a framed stream
+pub async fn respond<S>(stream: &mut S, supported: &[Mode]) -> Result<Mode, NegotiationError>
+where
+    S: Sink<UalMessage, Error = FrameError> + Stream<Item = Result<UalMessage, FrameError>> + Unpin,
+{
+    let hello = stream.next().await.ok_or(NegotiationError::Closed)??;
+    let (ack, mode) = answer(&hello, supported)?;
+    // The peer learns of a failed negotiation from the acknowledgement too
+    stream.send(ack).await?;
+    mode.ok_or(NegotiationError::NoCommonMode)
+}
+
+/// Sets a JSON payload without consulting the schema registry, which may be strict
+fn json_message<T: Serialize>(mut msg: UalMessage, payload: &T) -> UalMessage {
+    msg.payload = serde_json::to_vec(payload).expect("handshake payloads serialize");
+    msg
+}
+
+fn read<T: for<'de> Deserialize<'de>>(msg: &UalMessage, action: &'static str) -> Result<T, NegotiationError> {
+    if msg.action() != action {
+        return Err(NegotiationError::Unexpected {
+            expected: action,
+            actual: msg.action().to_string(),
+        });
+    }
+    msg.decode_unchecked()
+        .map_err(|e| NegotiationError::Malformed(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::UalCodec;
+    use tokio_util::codec::Framed;
+
+    #[tokio::test]
+    async fn test_handshake_picks_initiator_preference() {
+        let (a, b) = tokio::io::duplex(1024);
+        let mut initiator = Framed::new(a, UalCodec::default());
+        let mut responder = Framed::new(b, UalCodec::default());
+
+        let offered = [Mode::CapnProto, Mode::Cbor, Mode::Json];
+        let (left, right) = tokio::join!(
+            initiate(&mut initiator, &offered),
+            respond(&mut responder, &DEFAULT_PREFERENCE)
+        );
+        assert_eq!(left.unwrap(), Mode::Cbor);
+        assert_eq!(right.unwrap(), Mode::Cbor);
+
+        let (left, right) = tokio::join!(
+            initiate(&mut initiator, &[Mode::CapnProto]),
+            respond(&mut responder, &[Mode::Json])
+        );
+        assert!(matches!(left, Err(NegotiationError::NoCommonMode)));
+        assert!(matches!(right, Err(NegotiationError::NoCommonMode)));
+    }
+}
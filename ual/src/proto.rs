@@ -0,0 +1,37 @@
+// Protobuf types for the UAL envelope, kept in sync with proto/ual.proto
+// © 2025 Finalverse Inc. All rights reserved.
+
+// Written out by hand rather than generated by prost-build so the crate builds without protoc.
+
+/// Encoding of the envelope payload
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+#[repr(i32)]
+pub enum PayloadMode {
+    Json = 0,
+    Grpc = 1,
+    ByteLevel = 2,
+    Cbor = 3,
+    MessagePack = 4,
+    CapnProto = 5,
+}
+
+/// A complete UAL message
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct UalEnvelope {
+    #[prost(string, tag = "1")]
+    pub action: String,
+    #[prost(enumeration = "PayloadMode", tag = "2")]
+    pub mode: i32,
+    #[prost(bytes = "vec", tag = "3")]
+    pub payload: Vec<u8>,
+    #[prost(string, tag = "4")]
+    pub id: String,
+    #[prost(string, optional, tag = "5")]
+    pub correlation_id: Option<String>,
+    #[prost(string, optional, tag = "6")]
+    pub reply_to: Option<String>,
+    #[prost(uint64, optional, tag = "7")]
+    pub deadline: Option<u64>,
+    #[prost(btree_map = "string, string", tag = "8")]
+    pub headers: ::std::collections::BTreeMap<String, String>,
+}
@@ -0,0 +1,419 @@
+// Registry of declared UAL actions and validation of payloads against their schemas
+// © 2025 Finalverse Inc. All rights reserved.
+
+use crate::{Mode, UalMessage};
+use jsonschema::JSONSchema;
+use prost_reflect::{DescriptorPool, DynamicMessage, MessageDescriptor};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use thiserror::Error;
+
+/// Header selecting the schema version a message was built against; latest when absent
+pub const SCHEMA_VERSION_HEADER: &str = "schema-version";
+
+/// Typed reasons a message does not match its declared action
+#[derive(Debug, Clone, PartialEq, Error, Serialize, Deserialize)]
+pub enum ValidationError {
+    #[error("action {0} is not declared")]
+    UnknownAction(String),
+    #[error("action {action} has no schema version {version}")]
+    UnknownVersion { action: String, version: u32 },
+    #[error("invalid {SCHEMA_VERSION_HEADER} header: {0}")]
+    InvalidVersionHeader(String),
+    #[error("action {action} expects {expected:?} payloads, got {actual:?}")]
+    ModeMismatch {
+        action: String,
+        expected: Mode,
+        actual: Mode,
+    },
+    #[error("payload does not match {action} v{version}: {}", .errors.join("; "))]
+    InvalidPayload {
+        action: String,
+        version: u32,
+        errors: Vec<String>, // e.g., "/x: \"ten\" is not of type \"integer\""
+    },
+    #[error("schema for {action} is invalid: {reason}")]
+    InvalidSchema { action: String, reason: String },
+    #[error("action {action} v{version} is already declared with a different schema")]
+    ConflictingSchema { action: String, version: u32 },
+}
+
+/// Shape a payload must have
+#[derive(Clone)]
+enum PayloadSchema {
+    Json(Arc<JSONSchema>, Value), // JSON Schema compiled once, with its source for comparison
+    Protobuf(MessageDescriptor),
+}
+
+impl PayloadSchema {
+    fn same_as(&self, other: &PayloadSchema) -> bool {
+        match (self, other) {
+            (PayloadSchema::Json(_, a), PayloadSchema::Json(_, b)) => a == b,
+            (PayloadSchema::Protobuf(a), PayloadSchema::Protobuf(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+/// One version of a declared action
+#[derive(Clone)]
+pub struct ActionSchema {
+    action: String,
+    version: u32,
+    payload: PayloadSchema,
+}
+
+impl ActionSchema {
+    /// Declares an action whose JSON payloads follow a JSON Schema document
+    pub fn json(action: &str, version: u32, schema: &Value) -> Result<Self, ValidationError> {
+        let compiled = JSONSchema::compile(schema).map_err(|e| ValidationError::InvalidSchema {
+            action: action.to_string(),
+            reason: e.to_string(),
+        })?;
+        Ok(ActionSchema {
+            action: action.to_string(),
+            version,
+            payload: PayloadSchema::Json(Arc::new(compiled), schema.clone()),
+        })
+    }
+
+    /// Declares an action whose Protobuf payloads are the given message type
+    pub fn protobuf(action: &str, version: u32, descriptor: MessageDescriptor) -> Self {
+        ActionSchema {
+            action: action.to_string(),
+            version,
+            payload: PayloadSchema::Protobuf(descriptor),
+        }
+    }
+
+    /// Declares a Protobuf action from an encoded `FileDescriptorSet` (e.g., `protoc --descriptor_set_out`)
+    pub fn protobuf_from_descriptor_set(
+        action: &str,
+        version: u32,
+        descriptor_set: &[u8],
+        message: &str, // Fully qualified, e.g., "maple.game.Move"
+    ) -> Result<Self, ValidationError> {
+        let invalid = |reason: String| ValidationError::InvalidSchema {
+            action: action.to_string(),
+            reason,
+        };
+        let pool = DescriptorPool::decode(descriptor_set).map_err(|e| invalid(e.to_string()))?;
+        let descriptor = pool
+            .get_message_by_name(message)
+            .ok_or_else(|| invalid(format!("message {} not found in descriptor set", message)))?;
+        Ok(Self::protobuf(action, version, descriptor))
+    }
+
+    /// Returns the declared action
+    pub fn action(&self) -> &str {
+        &self.action
+    }
+
+    /// Returns the schema version
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    /// Mode payloads of this action are expected in; JSON Schemas also cover CBOR and MessagePack
+    pub fn mode(&self) -> Mode {
+        match self.payload {
+            PayloadSchema::Json(..) => Mode::Json,
+            PayloadSchema::Protobuf(_) => Mode::Grpc,
+        }
+    }
+
+    /// Checks a message payload against this schema
+    pub fn validate(&self, msg: &UalMessage) -> Result<(), ValidationError> {
+        let accepted = match self.payload {
+            PayloadSchema::Json(..) => msg.mode().is_self_describing(),
+            PayloadSchema::Protobuf(_) => msg.mode() == &Mode::Grpc,
+        };
+        if !accepted {
+            return Err(ValidationError::ModeMismatch {
+                action: self.action.clone(),
+                expected: self.mode(),
+                actual: msg.mode().clone(),
+            });
+        }
+        let errors = match &self.payload {
+            PayloadSchema::Json(schema, _) => match msg.decode_unchecked::<Value>() {
+                Ok(value) => match schema.validate(&value) {
+                    Ok(()) => Vec::new(),
+                    Err(errors) => errors.map(|e| format!("{}: {}", e.instance_path, e)).collect(),
+                },
+                Err(e) => vec![format!("not {:?}: {}", msg.mode(), e)],
+            },
+            PayloadSchema::Protobuf(descriptor) => match DynamicMessage::decode(descriptor.clone(), msg.payload()) {
+                Ok(_) => Vec::new(),
+                Err(e) => vec![format!("not a {}: {}", descriptor.full_name(), e)],
+            },
+        };
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(ValidationError::InvalidPayload {
+                action: self.action.clone(),
+                version: self.version,
+                errors,
+            })
+        }
+    }
+}
+
+/// A declared action version and who declared it
+struct Declaration {
+    schema: ActionSchema,
+    pinned: bool, // Registered outright rather than leased; stays until unregistered
+    leases: BTreeSet<u64>, // Outstanding leases sharing this schema
+}
+
+/// Declared actions by name and version
+#[derive(Default)]
+pub struct SchemaRegistry {
+    schemas: RwLock<HashMap<String, BTreeMap<u32, Declaration>>>,
+    strict: AtomicBool, // Reject undeclared actions instead of passing them through
+    next_lease: AtomicU64,
+}
+
+/// An action version declared for as long as its holder, e.g. a running agent, needs it
+#[must_use = "the declaration is withdrawn when the lease is dropped"]
+pub struct SchemaLease<'a> {
+    registry: &'a SchemaRegistry,
+    action: String,
+    version: u32,
+    id: u64,
+}
+
+impl Drop for SchemaLease<'_> {
+    fn drop(&mut self) {
+        let mut schemas = self.registry.write();
+        let Some(versions) = schemas.get_mut(&self.action) else {
+            return;
+        };
+        if let Some(declaration) = versions.get_mut(&self.version) {
+            declaration.leases.remove(&self.id);
+            if declaration.leases.is_empty() && !declaration.pinned {
+                versions.remove(&self.version);
+            }
+        }
+        if versions.is_empty() {
+            schemas.remove(&self.action);
+        }
+    }
+}
+
+impl SchemaRegistry {
+    /// Creates an empty, permissive registry
+    pub fn new() -> Self {
+        SchemaRegistry::default()
+    }
+
+    /// Process-wide registry consulted when messages are built or decoded
+    pub fn global() -> &'static SchemaRegistry {
+        static GLOBAL: OnceLock<SchemaRegistry> = OnceLock::new();
+        GLOBAL.get_or_init(SchemaRegistry::new)
+    }
+
+    /// Whether undeclared actions fail validation
+    pub fn set_strict(&self, strict: bool) {
+        self.strict.store(strict, Ordering::Relaxed);
+    }
+
+    /// Declares an action version until it is unregistered, replacing any earlier declaration of the same version
+    pub fn register(&self, schema: ActionSchema) {
+        let mut schemas = self.write();
+        let versions = schemas.entry(schema.action.clone()).or_default();
+        let leases = versions.remove(&schema.version).map(|d| d.leases).unwrap_or_default();
+        versions.insert(schema.version, Declaration { schema, pinned: true, leases });
+    }
+
+    /// Declares an action version until the returned lease is dropped. Holders leasing the
+    /// same version share it, and must agree on its schema.
+    pub fn lease(&self, schema: ActionSchema) -> Result<SchemaLease<'_>, ValidationError> {
+        let id = self.next_lease.fetch_add(1, Ordering::Relaxed);
+        let (action, version) = (schema.action.clone(), schema.version);
+        let mut schemas = self.write();
+        let versions = schemas.entry(action.clone()).or_default();
+        match versions.get_mut(&version) {
+            Some(declaration) if !declaration.schema.payload.same_as(&schema.payload) => {
+                return Err(ValidationError::ConflictingSchema { action, version });
+            }
+            Some(declaration) => {
+                declaration.leases.insert(id);
+            }
+            None => {
+                versions.insert(
+                    version,
+                    Declaration {
+                        schema,
+                        pinned: false,
+                        leases: BTreeSet::from([id]),
+                    },
+                );
+            }
+        }
+        Ok(SchemaLease {
+            registry: self,
+            action,
+            version,
+            id,
+        })
+    }
+
+    /// Removes every version of an action, including leased ones
+    pub fn unregister(&self, action: &str) {
+        self.write().remove(action);
+    }
+
+    /// Looks up a version of an action, or its latest version
+    pub fn get(&self, action: &str, version: Option<u32>) -> Option<ActionSchema> {
+        let schemas = self.read();
+        let versions = schemas.get(action)?;
+        let declaration = match version {
+            Some(version) => versions.get(&version),
+            None => versions.values().next_back(),
+        };
+        declaration.map(|d| d.schema.clone())
+    }
+
+    /// Declared actions with their versions, sorted by action
+    pub fn actions(&self) -> BTreeMap<String, Vec<u32>> {
+        self.read()
+            .iter()
+            .map(|(action, versions)| (action.clone(), versions.keys().copied().collect()))
+            .collect()
+    }
+
+    /// Checks a message against the schema of its action
+    pub fn validate(&self, msg: &UalMessage) -> Result<(), ValidationError> {
+        let version = match msg.header(SCHEMA_VERSION_HEADER) {
+            Some(v) => Some(v.parse().map_err(|_| ValidationError::InvalidVersionHeader(v.to_string()))?),
+            None => None,
+        };
+        if let Some(schema) = self.get(msg.action(), version) {
+            return schema.validate(msg);
+        }
+        match version {
+            Some(version) if self.get(msg.action(), None).is_some() => Err(ValidationError::UnknownVersion {
+                action: msg.action().to_string(),
+                version,
+            }),
+            _ if self.strict.load(Ordering::Relaxed) => Err(ValidationError::UnknownAction(msg.action().to_string())),
+            _ => Ok(()),
+        }
+    }
+
+    fn read(&self) -> RwLockReadGuard<'_, HashMap<String, BTreeMap<u32, Declaration>>> {
+        self.schemas.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, HashMap<String, BTreeMap<u32, Declaration>>> {
+        self.schemas.write().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prost_reflect::prost_types::{field_descriptor_proto::Type, DescriptorProto, FieldDescriptorProto, FileDescriptorProto};
+
+    #[test]
+    fn test_json_schema_versions_and_global_validation() {
+        let registry = SchemaRegistry::new();
+        let v1 = serde_json::json!({"type": "object", "required": ["x"], "properties": {"x": {"type": "integer"}}});
+        let v2 = serde_json::json!({"type": "object", "required": ["x", "y"]});
+        registry.register(ActionSchema::json("move", 1, &v1).unwrap());
+        registry.register(ActionSchema::json("move", 2, &v2).unwrap());
+        assert_eq!(registry.actions()["move"], vec![1, 2]);
+
+        let msg = UalMessage::new("move", Mode::Json).with_json_payload(&serde_json::json!({"x": 10})).unwrap();
+        assert!(matches!(registry.validate(&msg), Err(ValidationError::InvalidPayload { version: 2, .. })));
+        assert!(registry.validate(&msg.clone().with_schema_version(1)).is_ok());
+        assert!(matches!(
+            registry.validate(&msg.clone().with_schema_version(3)),
+            Err(ValidationError::UnknownVersion { version: 3, .. })
+        ));
+        assert!(matches!(
+            registry.validate(&UalMessage::new("move", Mode::ByteLevel)),
+            Err(ValidationError::ModeMismatch { .. })
+        ));
+
+        assert!(registry.validate(&UalMessage::new("jump", Mode::Json)).is_ok());
+        registry.set_strict(true);
+        assert!(matches!(registry.validate(&UalMessage::new("jump", Mode::Json)), Err(ValidationError::UnknownAction(_))));
+
+        // Messages validate against the global registry, with the version pinned in any order
+        SchemaRegistry::global().register(ActionSchema::json("schema-test.move", 1, &v1).unwrap());
+        SchemaRegistry::global().register(ActionSchema::json("schema-test.move", 2, &v2).unwrap());
+        let msg = UalMessage::new("schema-test.move", Mode::Json)
+            .with_json_payload(&serde_json::json!({"x": 10}))
+            .unwrap();
+        assert!(msg.validate().is_err());
+        assert!(msg.clone().with_schema_version(1).validate().is_ok());
+        let wrong = UalMessage::new("schema-test.move", Mode::Json)
+            .with_schema_version(1)
+            .with_json_payload(&serde_json::json!({"x": "ten"}))
+            .unwrap();
+        assert!(wrong.decode::<Value>().unwrap_err().downcast_ref::<ValidationError>().is_some());
+        SchemaRegistry::global().unregister("schema-test.move");
+    }
+
+    #[test]
+    fn test_leases_share_a_schema_and_withdraw_it_when_dropped() {
+        let registry = SchemaRegistry::new();
+        let v1 = serde_json::json!({"type": "object", "required": ["x"]});
+        let other = serde_json::json!({"type": "object", "required": ["y"]});
+        let first = registry.lease(ActionSchema::json("move", 1, &v1).unwrap()).unwrap();
+        let second = registry.lease(ActionSchema::json("move", 1, &v1).unwrap()).unwrap();
+        assert!(matches!(
+            registry.lease(ActionSchema::json("move", 1, &other).unwrap()),
+            Err(ValidationError::ConflictingSchema { version: 1, .. })
+        ));
+
+        drop(first);
+        assert!(registry.get("move", Some(1)).is_some()); // Still held by the second lease
+        drop(second);
+        assert!(registry.get("move", None).is_none());
+
+        // Registered outright, a schema outlives leases of it
+        registry.register(ActionSchema::json("move", 1, &v1).unwrap());
+        drop(registry.lease(ActionSchema::json("move", 1, &v1).unwrap()).unwrap());
+        assert!(registry.get("move", Some(1)).is_some());
+    }
+
+    #[test]
+    fn test_protobuf_descriptor_validation() {
+        let file = FileDescriptorProto {
+            name: Some("game.proto".into()),
+            package: Some("maple.game".into()),
+            syntax: Some("proto3".into()),
+            message_type: vec![DescriptorProto {
+                name: Some("Move".into()),
+                field: vec![FieldDescriptorProto {
+                    name: Some("x".into()),
+                    number: Some(1),
+                    r#type: Some(Type::Int32 as i32),
+                    json_name: Some("x".into()),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let mut pool = DescriptorPool::new();
+        pool.add_file_descriptor_proto(file).unwrap();
+        let registry = SchemaRegistry::new();
+        registry.register(ActionSchema::protobuf("move", 1, pool.get_message_by_name("maple.game.Move").unwrap()));
+
+        let valid = UalMessage::new("move", Mode::Grpc).with_proto_payload(&crate::proto::UalEnvelope::default()).unwrap();
+        assert!(registry.validate(&valid).is_ok());
+        // Field 1 as a length-delimited string where Move expects a varint
+        let wrong = UalMessage::new("move", Mode::Grpc)
+            .with_proto_payload(&crate::proto::UalEnvelope { action: "x".into(), ..Default::default() })
+            .unwrap();
+        assert!(matches!(registry.validate(&wrong), Err(ValidationError::InvalidPayload { .. })));
+    }
+}
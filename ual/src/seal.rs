@@ -0,0 +1,345 @@
+// End-to-end sealing and signing of UAL messages between DIDs
+// © 2025 Finalverse Inc. All rights reserved.
+
+// A sealed message is the inner message's frame, signed with the sender's Ed25519 key and
+// encrypted with ChaCha20-Poly1305 under a key agreed between a fresh X25519 key and the
+// recipient's. Relays and stores see only the routing fields (ID, correlation ID, reply-to,
+// deadline) and the two DIDs.
+
+use crate::{FrameError, Mode, UalMessage};
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Nonce};
+use libp2p_identity::{Keypair, PublicKey};
+use thiserror::Error;
+use x25519_dalek::{EphemeralSecret, PublicKey as X25519PublicKey, StaticSecret};
+
+/// Action of a sealed message; the real action travels encrypted
+pub const SEALED_ACTION: &str = "ual.sealed";
+
+/// Header naming the sender of a sealed message
+pub const SEALED_FROM_HEADER: &str = "sealed-from";
+
+/// Header naming the recipient of a sealed message
+pub const SEALED_TO_HEADER: &str = "sealed-to";
+
+/// Header naming the DID that signed a message in the clear
+pub const SIGNED_BY_HEADER: &str = "signed-by";
+
+/// Header carrying the hex-encoded signature of a message signed in the clear
+pub const SIGNATURE_HEADER: &str = "signature";
+
+const SEAL_KEY_CONTEXT: &str = "maple ual seal v1"; // BLAKE3 key derivation context
+const SEAL_SIGN_CONTEXT: &[u8] = b"maple-ual-seal-v1";
+const SIGN_CONTEXT: &[u8] = b"maple-ual-sign-v1";
+const NONCE_LEN: usize = 12;
+
+/// Errors raised while sealing, opening, signing or verifying messages
+#[derive(Debug, Error)]
+pub enum SealError {
+    #[error("message is not sealed")]
+    NotSealed,
+    #[error("message is not signed")]
+    Unsigned,
+    #[error("message is addressed to {actual}, not {expected}")]
+    WrongRecipient { expected: String, actual: String },
+    #[error("message comes from {actual}, not {expected}")]
+    WrongSender { expected: String, actual: String },
+    #[error("malformed sealed message: {0}")]
+    Malformed(&'static str),
+    #[error("sealed message could not be decrypted")]
+    Decryption,
+    #[error("signature does not match")]
+    BadSignature,
+    #[error("signing failed: {0}")]
+    Signing(String),
+    #[error(transparent)]
+    Frame(#[from] FrameError),
+}
+
+/// Secret keys of a DID: Ed25519 for signing, X25519 for receiving sealed messages
+pub struct DidKeys {
+    did: String,
+    signing: Keypair,
+    encryption: StaticSecret,
+}
+
+impl DidKeys {
+    /// Generates fresh keys for a DID
+    pub fn generate(did: &str) -> Self {
+        DidKeys {
+            did: did.to_string(),
+            signing: Keypair::generate_ed25519(),
+            encryption: StaticSecret::random_from_rng(OsRng),
+        }
+    }
+
+    /// Rebuilds keys from a stored signing keypair and X25519 secret
+    pub fn from_parts(did: &str, signing: Keypair, encryption: [u8; 32]) -> Self {
+        DidKeys {
+            did: did.to_string(),
+            signing,
+            encryption: StaticSecret::from(encryption),
+        }
+    }
+
+    /// Returns the DID these keys belong to
+    pub fn did(&self) -> &str {
+        &self.did
+    }
+
+    /// Returns the X25519 secret, e.g., to store it alongside the signing keypair
+    pub fn encryption_secret(&self) -> [u8; 32] {
+        self.encryption.to_bytes()
+    }
+
+    /// Returns the keys others need to seal to and verify this DID
+    pub fn public(&self) -> DidPublicKeys {
+        DidPublicKeys {
+            did: self.did.clone(),
+            signing: self.signing.public(),
+            encryption: X25519PublicKey::from(&self.encryption).to_bytes(),
+        }
+    }
+}
+
+impl std::fmt::Debug for DidKeys {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Never print secrets
+        f.debug_struct("DidKeys").field("did", &self.did).finish_non_exhaustive()
+    }
+}
+
+/// Public keys of a DID, as published in a key directory
+#[derive(Debug, Clone, PartialEq)]
+pub struct DidPublicKeys {
+    pub did: String,
+    pub signing: PublicKey,
+    pub encryption: [u8; 32], // X25519 public key
+}
+
+impl DidPublicKeys {
+    /// Encodes the keys as `did len u16 | did | signing key len u16 | signing key (protobuf) | encryption key`
+    pub fn encode(&self) -> Vec<u8> {
+        let signing = self.signing.encode_protobuf();
+        let mut buf = Vec::with_capacity(4 + self.did.len() + signing.len() + 32);
+        buf.extend_from_slice(&(self.did.len() as u16).to_be_bytes());
+        buf.extend_from_slice(self.did.as_bytes());
+        buf.extend_from_slice(&(signing.len() as u16).to_be_bytes());
+        buf.extend_from_slice(&signing);
+        buf.extend_from_slice(&self.encryption);
+        buf
+    }
+
+    /// Decodes keys written by `encode`
+    pub fn decode(bytes: &[u8]) -> Result<Self, SealError> {
+        let mut rest = bytes;
+        let did = String::from_utf8(take_field(&mut rest)?.to_vec()).map_err(|_| SealError::Malformed("DID"))?;
+        let signing = PublicKey::try_decode_protobuf(take_field(&mut rest)?).map_err(|_| SealError::Malformed("signing key"))?;
+        let encryption = rest.try_into().map_err(|_| SealError::Malformed("encryption key"))?;
+        Ok(DidPublicKeys { did, signing, encryption })
+    }
+}
+
+impl UalMessage {
+    /// Signs and encrypts the whole message so only the recipient can read it
+    pub fn seal(&self, sender: &DidKeys, recipient: &DidPublicKeys) -> Result<UalMessage, SealError> {
+        let frame = self.to_frame()?;
+        let ephemeral = EphemeralSecret::random_from_rng(OsRng);
+        let ephemeral_public = X25519PublicKey::from(&ephemeral);
+        let recipient_public = X25519PublicKey::from(recipient.encryption);
+        let shared = ephemeral.diffie_hellman(&recipient_public);
+        let key = seal_key(shared.as_bytes(), &ephemeral_public, &recipient_public);
+
+        let transcript = seal_transcript(&sender.did, &recipient.did, ephemeral_public.as_bytes(), &frame);
+        let signature = sender.signing.sign(&transcript).map_err(|e| SealError::Signing(e.to_string()))?;
+        let mut plaintext = Vec::with_capacity(2 + signature.len() + frame.len());
+        plaintext.extend_from_slice(&(signature.len() as u16).to_be_bytes());
+        plaintext.extend_from_slice(&signature);
+        plaintext.extend_from_slice(&frame);
+
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let aad = seal_aad(&sender.did, &recipient.did);
+        let ciphertext = ChaCha20Poly1305::new(&key.into())
+            .encrypt(&nonce, Payload { msg: &plaintext, aad: &aad })
+            .map_err(|_| SealError::Malformed("plaintext too large"))?;
+
+        let mut sealed = UalMessage::new(SEALED_ACTION, Mode::ByteLevel)
+            .with_header(SEALED_FROM_HEADER, &sender.did)
+            .with_header(SEALED_TO_HEADER, &recipient.did)
+            .with_recipient(&recipient.did); // Routed like any addressed message; the seal binds `SEALED_TO_HEADER`
+        sealed.id = self.id.clone();
+        sealed.correlation_id = self.correlation_id.clone();
+        sealed.reply_to = self.reply_to.clone();
+        sealed.deadline = self.deadline;
+        sealed.payload = [ephemeral_public.as_bytes().as_slice(), nonce.as_slice(), &ciphertext].concat();
+        Ok(sealed)
+    }
+
+    /// Decrypts a sealed message and checks the sender's signature
+    pub fn open(&self, recipient: &DidKeys, sender: &DidPublicKeys) -> Result<UalMessage, SealError> {
+        if !self.is_sealed() {
+            return Err(SealError::NotSealed);
+        }
+        let to = self.header(SEALED_TO_HEADER).ok_or(SealError::Malformed("missing recipient"))?;
+        if to != recipient.did {
+            return Err(SealError::WrongRecipient {
+                expected: recipient.did.clone(),
+                actual: to.to_string(),
+            });
+        }
+        let from = self.header(SEALED_FROM_HEADER).ok_or(SealError::Malformed("missing sender"))?;
+        if from != sender.did {
+            return Err(SealError::WrongSender {
+                expected: sender.did.clone(),
+                actual: from.to_string(),
+            });
+        }
+        if self.payload.len() < 32 + NONCE_LEN {
+            return Err(SealError::Malformed("payload too short"));
+        }
+        let (ephemeral, rest) = self.payload.split_at(32);
+        let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+        let ephemeral_public = X25519PublicKey::from(<[u8; 32]>::try_from(ephemeral).expect("32 bytes"));
+        let shared = recipient.encryption.diffie_hellman(&ephemeral_public);
+        let key = seal_key(shared.as_bytes(), &ephemeral_public, &X25519PublicKey::from(&recipient.encryption));
+        let aad = seal_aad(from, to);
+        let plaintext = ChaCha20Poly1305::new(&key.into())
+            .decrypt(Nonce::from_slice(nonce), Payload { msg: ciphertext, aad: &aad })
+            .map_err(|_| SealError::Decryption)?;
+
+        let mut rest = plaintext.as_slice();
+        let signature = take_field(&mut rest)?;
+        let transcript = seal_transcript(from, to, ephemeral, rest);
+        if !sender.signing.verify(&transcript, signature) {
+            return Err(SealError::BadSignature);
+        }
+        Ok(UalMessage::from_frame(rest)?)
+    }
+
+    /// Whether this message is sealed
+    pub fn is_sealed(&self) -> bool {
+        self.action == SEALED_ACTION
+    }
+
+    /// DID that sealed this message, so the receiver can look up its keys
+    pub fn sealed_by(&self) -> Option<&str> {
+        self.header(SEALED_FROM_HEADER).filter(|_| self.is_sealed())
+    }
+
+    /// Signs the message in the clear, replacing any earlier signature
+    pub fn sign(mut self, signer: &DidKeys) -> Result<UalMessage, SealError> {
+        self.headers.remove(SIGNATURE_HEADER);
+        self.headers.insert(SIGNED_BY_HEADER.to_string(), signer.did.clone());
+        let signed = [SIGN_CONTEXT, &self.to_frame()?].concat();
+        let signature = signer.signing.sign(&signed).map_err(|e| SealError::Signing(e.to_string()))?;
+        self.headers.insert(SIGNATURE_HEADER.to_string(), hex::encode(signature));
+        Ok(self)
+    }
+
+    /// Checks that the message was signed by the given DID and not altered since
+    pub fn verify(&self, signer: &DidPublicKeys) -> Result<(), SealError> {
+        let by = self.signed_by().ok_or(SealError::Unsigned)?;
+        if by != signer.did {
+            return Err(SealError::WrongSender {
+                expected: signer.did.clone(),
+                actual: by.to_string(),
+            });
+        }
+        let signature = self.header(SIGNATURE_HEADER).ok_or(SealError::Unsigned)?;
+        let signature = hex::decode(signature).map_err(|_| SealError::Malformed("signature"))?;
+        let mut unsigned = self.clone();
+        unsigned.headers.remove(SIGNATURE_HEADER);
+        let signed = [SIGN_CONTEXT, &unsigned.to_frame()?].concat();
+        if signer.signing.verify(&signed, &signature) {
+            Ok(())
+        } else {
+            Err(SealError::BadSignature)
+        }
+    }
+
+    /// DID that signed this message in the clear
+    pub fn signed_by(&self) -> Option<&str> {
+        self.header(SIGNED_BY_HEADER)
+    }
+}
+
+/// Derives the AEAD key from the X25519 shared secret, bound to both public keys
+fn seal_key(shared: &[u8; 32], ephemeral: &X25519PublicKey, recipient: &X25519PublicKey) -> [u8; 32] {
+    let material = [shared.as_slice(), ephemeral.as_bytes(), recipient.as_bytes()].concat();
+    blake3::derive_key(SEAL_KEY_CONTEXT, &material)
+}
+
+/// Bytes the sender signs: both DIDs, the ephemeral key and the inner frame
+fn seal_transcript(from: &str, to: &str, ephemeral: &[u8], frame: &[u8]) -> Vec<u8> {
+    [SEAL_SIGN_CONTEXT, &seal_aad(from, to), ephemeral, frame].concat()
+}
+
+/// Associated data binding the ciphertext to the DIDs in the clear headers
+fn seal_aad(from: &str, to: &str) -> Vec<u8> {
+    format!("{}\n{}", from, to).into_bytes()
+}
+
+/// Reads a u16 big-endian length-prefixed field
+fn take_field<'a>(rest: &mut &'a [u8]) -> Result<&'a [u8], SealError> {
+    if rest.len() < 2 {
+        return Err(SealError::Malformed("truncated field"));
+    }
+    let len = u16::from_be_bytes([rest[0], rest[1]]) as usize;
+    let field = rest.get(2..2 + len).ok_or(SealError::Malformed("truncated field"))?;
+    *rest = &rest[2 + len..];
+    Ok(field)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seal_and_open_between_dids() {
+        let alice = DidKeys::generate("did:maple:agent:alice");
+        let bob = DidKeys::generate("did:maple:agent:bob");
+        let eve = DidKeys::generate("did:maple:agent:eve");
+        let msg = UalMessage::new("secret.plan", Mode::Json)
+            .with_json_payload(&serde_json::json!({"launch": 42}))
+            .unwrap()
+            .with_reply_to("did:maple:agent:alice");
+
+        let sealed = msg.seal(&alice, &bob.public()).unwrap();
+        assert_eq!(sealed.action(), SEALED_ACTION);
+        assert_eq!(sealed.sealed_by(), Some("did:maple:agent:alice"));
+        assert_eq!(sealed.recipient(), Some("did:maple:agent:bob"));
+        assert_eq!((sealed.id(), sealed.reply_to()), (msg.id(), msg.reply_to()));
+        assert!(!sealed.payload().windows(6).any(|w| w == b"launch"));
+
+        // Keys survive publication through a directory
+        let alice_public = DidPublicKeys::decode(&alice.public().encode()).unwrap();
+        let opened = sealed.open(&bob, &alice_public).unwrap();
+        assert_eq!(opened.action(), "secret.plan");
+        assert_eq!(opened.decode::<serde_json::Value>().unwrap()["launch"], 42);
+
+        assert!(matches!(sealed.open(&eve, &alice.public()), Err(SealError::WrongRecipient { .. })));
+        let mut tampered = sealed.clone();
+        *tampered.payload.last_mut().unwrap() ^= 1;
+        assert!(matches!(tampered.open(&bob, &alice.public()), Err(SealError::Decryption)));
+        // A forger can encrypt to Bob but cannot sign as Alice
+        let forged = msg.seal(&eve, &bob.public()).unwrap().with_header(SEALED_FROM_HEADER, &alice.did);
+        assert!(forged.open(&bob, &alice.public()).is_err());
+    }
+
+    #[test]
+    fn test_sign_and_verify_in_the_clear() {
+        let alice = DidKeys::generate("did:maple:agent:alice");
+        let msg = UalMessage::new("announce", Mode::ByteLevel)
+            .with_byte_payload(b"hello".to_vec())
+            .sign(&alice)
+            .unwrap();
+        assert_eq!(msg.signed_by(), Some("did:maple:agent:alice"));
+        msg.verify(&alice.public()).unwrap();
+
+        let mut tampered = msg.clone();
+        tampered.payload = b"HELLO".to_vec();
+        assert!(matches!(tampered.verify(&alice.public()), Err(SealError::BadSignature)));
+        let unsigned = UalMessage::new("announce", Mode::ByteLevel);
+        assert!(matches!(unsigned.verify(&alice.public()), Err(SealError::Unsigned)));
+    }
+}